@@ -0,0 +1,58 @@
+//! A calculator engine: expressions, variables, user defined functions and a builtin function library.
+//!
+//! Create a [`Calculator`] and pass input to [`Calc::eval`], which solves expressions, assigns
+//! variables and functions (`x = 2`, `f(x) = x^2`) and runs commands such as `deg` or `history`.
+//! `vars`, `funcs`, `show <name>` and `del <name>` inspect and delete them, `reset` starts over and
+//! `help` lists every command, function and constant. Variables and functions can also be inspected
+//! and set directly through [`Calculator`].
+//!
+//! The builtin constants (`pi`, `e`, `tau`, `i`, `j`) cannot be reassigned, and `const g = 9.80665`
+//! declares a constant of your own. Assigning to or declaring a constant again fails with
+//! [`ErrorKind::ConstReassignAttempt`], and `del` refuses to delete one. Only a saved session
+//! being loaded can replace the constants it declares.
+//!
+//! Answers are [`Value`]s. By default these are floats, in [`NumberMode::Exact`] integers are big
+//! integers and division gives fractions, so `0.1 + 0.2` is exactly `3/10`. In
+//! [`NumberMode::Decimal`] numbers are big decimals rounded to a chosen number of significant
+//! digits, so `sqrt(2)` or `pi` can be worked out to 100 digits.
+//!
+//! `i` (or `j`) is the imaginary unit, so `3 + 4i` is a complex number, as is `polar(2, pi/3)` or
+//! `rect(1, 2)`. `abs` and `arg`, or `re` and `im`, take one apart again, and `display polar`
+//! shows answers in polar form. Complex numbers work with the arithmetic operators and most
+//! functions, and functions such as `sqrt` or `ln` give complex results for negative numbers. They
+//! are always floating point, whatever the number mode.
+//!
+//! [`NumberMode::Programmer`] works with fixed size integers such as `i32` or `u8`, see
+//! [`WordType`]. Bitwise operators (`&`, `|`, `^^`, `~`, `<<`, `>>`) only take integers in every mode.
+//!
+//! [`Calculator::format`] writes answers out using the display settings, eg. fixed decimal places,
+//! scientific or engineering notation, significant figures and thousands grouping, see
+//! [`NumberFormat`].
+//!
+//! [`Calculator::save_session`] writes the settings, variables and functions to a text file as the
+//! commands and assignments that recreate them, and [`Calculator::load_session`] runs it again. The
+//! `save <file>` and `load <file>` commands do the same. [`Calculator::load_config`] applies
+//! settings from a `name = value` file and [`Calculator::run_file`] runs a prelude of constants and
+//! functions, errors in either name the file and line.
+//!
+//! There is no separate boolean type. Comparisons (`<`, `<=`, `>`, `>=`, `==`, `!=`) and the logic
+//! operators (`&&`, `||`, `!`) give 1 for true and 0 for false, and take 0 as false and any other
+//! number as true. So `if(x >= 0 && x <= 1, x, 0)` and `(x > 0) * x` both work as piecewise
+//! expressions. `&&`, `||` and `if` only solve the operands they need.
+
+mod mathengine;
+
+pub use mathengine::angle::AngleUnit;
+pub use mathengine::builtin::Arity;
+pub use mathengine::core::{Calc, Calculator, EvalResult};
+pub use mathengine::decimal::{Decimal, RoundingMode};
+pub use mathengine::error::{CalcError, ErrorKind};
+pub use mathengine::format::NumberFormat;
+pub use mathengine::history::HistoryEntry;
+pub use mathengine::provider::VariableProvider;
+pub use mathengine::token::Span;
+pub use mathengine::value::{ComplexDisplay, NumberMode, RationalDisplay, Value};
+pub use mathengine::word::{Overflow, Radix, WordType};
+pub use num_bigint::BigInt;
+pub use num_complex::Complex64;
+pub use num_rational::BigRational;
@@ -1,28 +1,302 @@
-mod mathengine;
-use mathengine::core::*;
+use rustcalc::{Calc, Calculator, EvalResult, Span};
 
-use std::io::{self, Write};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
 
-fn main() {
+const USAGE: &str = "usage: rustcalc [-n] [-r] [EXPRESSION | -f FILE]...
+
+With no expressions or files, starts an interactive session, or reads statements from standard input
+if it is not a terminal. Expressions and files are run in order, and the first error stops the run.
+An interactive session is saved when it ends, and can be picked up again with -r.
+
+Settings are read from rustcalc/config in the config directory, eg. ~/.config/rustcalc/config,
+one `name = value` per line, such as `angle = deg` or `display = fixed 4`. Then the statements in
+rustcalc/prelude.calc are run, so it can define constants and functions for every session. An error
+in either is reported and stops at that line, but only a run with -f exits because of it.
+
+  -f, --file FILE  run the statements in FILE, one per line, # starts a comment line
+  -n, --no-config  skip the config and prelude files
+  -r, --restore    restore the last interactive session first
+  -h, --help       show this message";
+
+// a statement failed to solve
+const EXIT_CALC_ERROR: u8 = 1;
+// bad arguments or a file that cannot be read
+const EXIT_USAGE_ERROR: u8 = 2;
+
+// something to run, in the order given on the command line
+enum Job {
+    Statement(String),
+    File(String),
+}
+
+fn main() -> ExitCode {
     let mut calc = Calculator::new();
+    let mut restore = false;
+    let mut use_config = true;
+    let mut jobs = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            "-r" | "--restore" => restore = true,
+            "-n" | "--no-config" => use_config = false,
+            "-f" | "--file" => match args.next() {
+                Some(path) => jobs.push(Job::File(path)),
+                None => {
+                    eprintln!("{USAGE}");
+                    return ExitCode::from(EXIT_USAGE_ERROR);
+                }
+            },
+            _ => jobs.push(Job::Statement(arg)),
+        }
+    }
+
+    // the config and prelude come first, so a restored session overrides them. A mistake in one
+    // is reported and the rest still starts, only running a script file stops at it
+    let scripted = jobs.iter().any(|job| matches!(job, Job::File(_)));
+    let config_dir = dirs::config_dir().map(|dir| dir.join("rustcalc"));
+    if let Some(dir) = config_dir.filter(|_| use_config) {
+        let config = dir.join("config");
+        if config.exists() {
+            if let Err(e) = calc.load_config(&config) {
+                eprintln!("error: {e}");
+                if scripted {
+                    return ExitCode::from(EXIT_CALC_ERROR);
+                }
+            }
+        }
+        let prelude = dir.join("prelude.calc");
+        if prelude.exists() {
+            if let Err(e) = calc.run_file(&prelude) {
+                eprintln!("error: {e}");
+                if scripted {
+                    return ExitCode::from(EXIT_CALC_ERROR);
+                }
+            }
+        }
+    }
 
+    if restore {
+        if let Some(path) = last_session_path().filter(|path| path.exists()) {
+            if let Err(e) = calc.load_session(&path) {
+                eprintln!("error: {e}");
+                if scripted {
+                    return ExitCode::from(EXIT_CALC_ERROR);
+                }
+            }
+        }
+    }
+
+    if jobs.is_empty() {
+        if io::stdin().is_terminal() {
+            return repl(&mut calc);
+        }
+        return exit_code(run_lines(&mut calc, io::stdin().lock().lines(), "<stdin>"));
+    }
+
+    // later jobs can use what earlier ones define, eg. rustcalc -f constants.calc "f(2)"
+    for job in &jobs {
+        let result = match job {
+            Job::Statement(input) => run_statement(&mut calc, input, None),
+            Job::File(path) => run_file(&mut calc, path),
+        };
+        if result.is_err() {
+            return exit_code(result);
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+// where an interactive session is saved when it ends
+fn last_session_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("rustcalc").join("last-session.calc"))
+}
+
+fn save_last_session(calc: &Calculator) {
+    let Some(path) = last_session_path() else {
+        return;
+    };
+    if let Some(dir) = path.parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            eprintln!("could not save the session: {}: {e}", dir.display());
+            return;
+        }
+    }
+    if let Err(e) = calc.save_session(&path) {
+        eprintln!("could not save the session: {e}");
+    }
+}
+
+fn exit_code(result: Result<(), u8>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(code) => ExitCode::from(code),
+    }
+}
+
+fn repl(calc: &mut Calculator) -> ExitCode {
+    let code = run_repl(calc);
+    save_last_session(calc);
+    code
+}
+
+fn run_repl(calc: &mut Calculator) -> ExitCode {
     loop {
         print!("> ");
         io::stdout().flush().unwrap();
         let mut user_input = String::new();
-        io::stdin()
-            .read_line(&mut user_input)
-            .expect("Error reading from STDIN");
+        match io::stdin().read_line(&mut user_input) {
+            // end of input, eg. ctrl-d
+            Ok(0) => {
+                println!();
+                return ExitCode::SUCCESS;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("error reading from STDIN: {e}");
+                return ExitCode::from(EXIT_USAGE_ERROR);
+            }
+        }
         if user_input.trim().to_lowercase() == "quit" {
+            return ExitCode::SUCCESS;
+        }
+        match calc.eval(&user_input) {
+            Ok(EvalResult::Answer(ans)) => println!("  {}", calc.format(&ans)),
+            Ok(EvalResult::Feedback(fb)) => println!("  {}", fb),
+            // results added to the library later have nothing to show yet
+            Ok(_) => {}
+            Err(e) => {
+                println!("! {}", e);
+                if let Some(span) = e.span {
+                    println!("{}", error_location(&user_input, span));
+                }
+            }
+        }
+    }
+}
+
+fn run_file(calc: &mut Calculator, path: &str) -> Result<(), u8> {
+    match fs::File::open(path) {
+        Ok(file) => run_lines(calc, io::BufReader::new(file).lines(), path),
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            Err(EXIT_USAGE_ERROR)
+        }
+    }
+}
+
+// runs a statement per line without prompts, errors are reported as source:line
+fn run_lines<I>(calc: &mut Calculator, lines: I, source: &str) -> Result<(), u8>
+where
+    I: Iterator<Item = io::Result<String>>,
+{
+    for (i, line) in lines.enumerate() {
+        let line = line.map_err(|e| {
+            eprintln!("{source}: {e}");
+            EXIT_USAGE_ERROR
+        })?;
+        let statement = line.trim();
+        if statement.is_empty() || statement.starts_with('#') {
+            continue;
+        }
+        if statement.to_lowercase() == "quit" {
             break;
         }
-        let output = calc.eval(&user_input);
-        match output {
-            Ok(eval_result) => match eval_result {
-                EvalResult::Answer(ans) => println!("  {}", ans),
-                EvalResult::Feedback(fb) => println!("  {}", fb),
-            },
-            Err(e) => println!("{} {}", "!", e),
+        run_statement(calc, &line, Some((source, i + 1)))?;
+    }
+    Ok(())
+}
+
+// prints the answer on stdout, or the error on stderr
+fn run_statement(calc: &mut Calculator, input: &str, location: Option<(&str, usize)>) -> Result<(), u8> {
+    match calc.eval(input) {
+        Ok(EvalResult::Answer(ans)) => println!("{}", calc.format(&ans)),
+        Ok(EvalResult::Feedback(fb)) => println!("{}", fb),
+        Ok(_) => {}
+        Err(e) => {
+            match location {
+                Some((source, line)) => eprintln!("{source}:{line}: {e}"),
+                None => eprintln!("error: {e}"),
+            }
+            if let Some(span) = e.span {
+                eprintln!("{}", error_location(input, span));
+            }
+            return Err(EXIT_CALC_ERROR);
         }
     }
+    Ok(())
+}
+
+// the input with the part that caused an error underlined, eg.
+//   2 + foo * 3
+//       ^~~
+fn error_location(input: &str, span: Span) -> String {
+    let input = input.trim_end();
+    // spans are byte offsets, but the underline is measured in characters
+    let column = |byte: usize| input.get(..byte.min(input.len())).map_or(0, |s| s.chars().count());
+    let start = column(span.start);
+    let width = column(span.end).saturating_sub(start).max(1);
+    format!("  {}\n  {}^{}", input, " ".repeat(start), "~".repeat(width - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustcalc::Value;
+
+    fn lines(text: &str) -> impl Iterator<Item = io::Result<String>> + '_ {
+        text.lines().map(|line| Ok(line.to_string()))
+    }
+
+    #[test]
+    fn statements_run_in_order_until_the_end_of_input() {
+        let mut calc = Calculator::new();
+        let script = "# a comment\n\nx = 2\n  \nf(a) = a * x\nf(3)\n";
+        assert_eq!(run_lines(&mut calc, lines(script), "<test>"), Ok(()));
+        assert_eq!(calc.variable("ans"), Some(Value::Float(6.0)));
+    }
+
+    #[test]
+    fn errors_stop_the_run_with_a_calc_error_code() {
+        let mut calc = Calculator::new();
+        assert_eq!(run_lines(&mut calc, lines("x = 1\ny + 1\nx = 2"), "<test>"), Err(EXIT_CALC_ERROR));
+        assert_eq!(calc.variable("x"), Some(Value::Float(1.0)));
+        assert_eq!(run_statement(&mut calc, "1 +", None), Err(EXIT_CALC_ERROR));
+        assert_eq!(run_statement(&mut calc, "1 + 1", None), Ok(()));
+    }
+
+    #[test]
+    fn quit_ends_a_script() {
+        let mut calc = Calculator::new();
+        assert_eq!(run_lines(&mut calc, lines("x = 1\nQUIT\nnope"), "<test>"), Ok(()));
+        assert_eq!(calc.variable("x"), Some(Value::Float(1.0)));
+    }
+
+    #[test]
+    fn unreadable_input_is_a_usage_error() {
+        let mut calc = Calculator::new();
+        assert_eq!(run_file(&mut calc, "/nonexistent/script.calc"), Err(EXIT_USAGE_ERROR));
+        let failing = [Ok("1".to_string()), Err(io::Error::other("broken pipe"))];
+        assert_eq!(run_lines(&mut calc, failing.into_iter(), "<test>"), Err(EXIT_USAGE_ERROR));
+    }
+
+    #[test]
+    fn carets_underline_the_span() {
+        assert_eq!(error_location("1 + foo * 2", Span::new(4, 7)), "  1 + foo * 2\n      ^~~");
+        assert_eq!(error_location("(1 + 2\n", Span::new(0, 1)), "  (1 + 2\n  ^");
+        // an empty span still gets a caret
+        assert_eq!(error_location("2 +", Span::new(3, 3)), "  2 +\n     ^");
+    }
+
+    #[test]
+    fn carets_count_characters_not_bytes() {
+        assert_eq!(error_location("\u{e9}\u{e9} + foo", Span::new(7, 10)), "  \u{e9}\u{e9} + foo\n       ^~~");
+    }
 }
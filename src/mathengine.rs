@@ -1,6 +1,21 @@
+pub mod angle;
+pub mod builtin;
+pub mod command;
+pub mod config;
 pub mod core;
+pub mod decimal;
 pub mod error;
+pub mod expr;
+pub mod format;
+pub mod history;
 pub mod math_function;
+pub mod parse;
+pub mod provider;
+pub mod session;
 pub mod solve;
+#[cfg(test)]
+mod testing;
 pub mod token;
 pub mod validate;
+pub mod value;
+pub mod word;
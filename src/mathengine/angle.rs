@@ -0,0 +1,155 @@
+use std::f64::consts::TAU;
+use std::fmt;
+
+use super::decimal::{Decimal, DecimalContext};
+
+// the unit that trig functions take and inverse trig functions return
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AngleUnit {
+    Radians,
+    Degrees,
+    Gradians,
+    Turns,
+}
+
+impl AngleUnit {
+    // the names used both as REPL commands (deg) and as unit suffixes (30deg)
+    pub fn from_name(name: &[u8]) -> Option<AngleUnit> {
+        match name {
+            b"rad" => Some(AngleUnit::Radians),
+            b"deg" => Some(AngleUnit::Degrees),
+            b"grad" => Some(AngleUnit::Gradians),
+            b"turn" => Some(AngleUnit::Turns),
+            _ => None,
+        }
+    }
+
+    // the inverse of from_name
+    pub fn name(self) -> &'static str {
+        match self {
+            AngleUnit::Radians => "rad",
+            AngleUnit::Degrees => "deg",
+            AngleUnit::Gradians => "grad",
+            AngleUnit::Turns => "turn",
+        }
+    }
+
+    // how many of this unit make up a full turn
+    fn full_turn(self) -> f64 {
+        match self {
+            AngleUnit::Radians => TAU,
+            AngleUnit::Degrees => 360.0,
+            AngleUnit::Gradians => 400.0,
+            AngleUnit::Turns => 1.0,
+        }
+    }
+
+    pub fn to_radians(self, x: f64) -> f64 {
+        match self {
+            AngleUnit::Radians => x,
+            _ => x * TAU / self.full_turn(),
+        }
+    }
+
+    pub fn radians_to_unit(self, x: f64) -> f64 {
+        match self {
+            AngleUnit::Radians => x,
+            _ => x * self.full_turn() / TAU,
+        }
+    }
+
+    // the size of one of this unit, measured in the unit `active`
+    // this is what makes 30deg mean 30 degrees no matter which unit is active
+    pub fn in_unit(self, active: AngleUnit) -> f64 {
+        active.full_turn() / self.full_turn()
+    }
+
+    // the same as above for decimal mode, where a turn is tau to the calculators precision
+    fn decimal_full_turn(self, ctx: DecimalContext) -> Decimal {
+        match self {
+            AngleUnit::Radians => Decimal::tau(ctx),
+            _ => Decimal::from_integer((self.full_turn() as i64).into()),
+        }
+    }
+
+    pub(crate) fn decimal_to_radians(self, x: &Decimal, ctx: DecimalContext) -> Decimal {
+        self.decimal_in_unit(AngleUnit::Radians, ctx).mul(x, ctx)
+    }
+
+    pub(crate) fn decimal_radians_to_unit(self, x: &Decimal, ctx: DecimalContext) -> Decimal {
+        AngleUnit::Radians.decimal_in_unit(self, ctx).mul(x, ctx)
+    }
+
+    pub(crate) fn decimal_in_unit(self, active: AngleUnit, ctx: DecimalContext) -> Decimal {
+        if self == active {
+            return Decimal::one();
+        }
+        let turns = active.decimal_full_turn(ctx).div(&self.decimal_full_turn(ctx), ctx);
+        turns.unwrap_or_else(Decimal::zero)
+    }
+}
+
+impl fmt::Display for AngleUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AngleUnit::Radians => write!(f, "radians"),
+            AngleUnit::Degrees => write!(f, "degrees"),
+            AngleUnit::Gradians => write!(f, "gradians"),
+            AngleUnit::Turns => write!(f, "turns"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AngleUnit;
+    use crate::mathengine::core::{Calc, Calculator};
+    use crate::mathengine::testing::{assert_close, feedback, float, float_of};
+
+    const UNITS: [AngleUnit; 4] = [AngleUnit::Radians, AngleUnit::Degrees, AngleUnit::Gradians, AngleUnit::Turns];
+
+    #[test]
+    fn names_round_trip() {
+        for unit in UNITS {
+            assert_eq!(AngleUnit::from_name(unit.name().as_bytes()), Some(unit));
+        }
+        assert_eq!(AngleUnit::from_name(b"degrees"), None);
+    }
+
+    #[test]
+    fn conversions() {
+        assert_close(AngleUnit::Degrees.to_radians(180.0), std::f64::consts::PI);
+        assert_close(AngleUnit::Gradians.to_radians(100.0), std::f64::consts::FRAC_PI_2);
+        assert_close(AngleUnit::Turns.radians_to_unit(std::f64::consts::PI), 0.5);
+        assert_eq!(AngleUnit::Degrees.in_unit(AngleUnit::Gradians), 400.0 / 360.0);
+        for unit in UNITS {
+            assert_close(unit.radians_to_unit(unit.to_radians(0.3)), 0.3);
+        }
+    }
+
+    #[test]
+    fn commands_switch_the_unit() {
+        let mut calc = Calculator::new();
+        for unit in UNITS {
+            assert_eq!(feedback(&mut calc, unit.name()), format!("angle unit set to {unit}"));
+            assert_eq!(calc.angle_unit(), unit);
+        }
+        calc.eval("grad").unwrap();
+        assert_close(float_of(&mut calc, "sin(100)"), 1.0);
+        assert_close(float_of(&mut calc, "acos(0)"), 100.0);
+        calc.eval("turn").unwrap();
+        assert_close(float_of(&mut calc, "asin(1)"), 0.25);
+    }
+
+    #[test]
+    fn suffixes_override_the_unit() {
+        assert_close(float("sin(30deg)"), 0.5);
+        assert_close(float("sin(100grad)"), 1.0);
+        assert_close(float("cos(0.5turn)"), -1.0);
+        assert_close(float("30deg"), std::f64::consts::FRAC_PI_6);
+        let mut calc = Calculator::new();
+        calc.eval("deg").unwrap();
+        assert_close(float_of(&mut calc, "sin(pi/6 rad)"), 0.5);
+        assert_close(float_of(&mut calc, "0.25turn"), 90.0);
+    }
+}
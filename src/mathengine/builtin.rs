@@ -0,0 +1,763 @@
+use std::cmp::Ordering;
+use std::f64::consts::{E, PI, TAU};
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_integer::Integer;
+use num_traits::{One, Signed, Zero};
+
+use super::{
+    core::Calculator,
+    decimal::{Decimal, DecimalContext},
+    error::{CalcError, ErrorKind},
+    value::{exact_combinations, exact_factorial, exact_permutations, NumberMode, Value},
+    word::WordType,
+};
+
+// a function that is built into the calculator and cannot be reassigned
+pub struct Builtin {
+    pub name: &'static str,
+    pub arity: Arity,
+    // whether func handles complex args, the others are never called with them
+    pub complex: bool,
+    pub func: fn(&Calculator, &[Value]) -> Result<Value, CalcError>,
+}
+
+// how many args a function accepts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arity {
+    Exact(usize),
+    Range(usize, usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(self, arg_count: usize) -> bool {
+        match self {
+            Arity::Exact(n) => arg_count == n,
+            Arity::Range(min, max) => (min..=max).contains(&arg_count),
+            Arity::AtLeast(min) => arg_count >= min,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arity::Exact(n) => write!(f, "{n}"),
+            Arity::Range(min, max) => write!(f, "{min} to {max}"),
+            Arity::AtLeast(min) => write!(f, "at least {min}"),
+        }
+    }
+}
+
+// the one list of builtins, both validation and solving read from this
+// args are checked against the arity before func is called, so indexing them is safe
+// most builtins work with floats, the ones that can give an exact answer for exact args do so
+// and the ones with a decimal version use it in decimal mode
+pub const BUILTINS: &[Builtin] = &[
+    // trig, these use the calculators angle unit
+    Builtin { name: "sin", arity: Arity::Exact(1), complex: true, func: |c, a| trig(c, &a[0], Decimal::sin, f64::sin, Complex64::sin) },
+    Builtin { name: "cos", arity: Arity::Exact(1), complex: true, func: |c, a| trig(c, &a[0], Decimal::cos, f64::cos, Complex64::cos) },
+    Builtin { name: "tan", arity: Arity::Exact(1), complex: true, func: |c, a| trig(c, &a[0], Decimal::tan, f64::tan, Complex64::tan) },
+    Builtin { name: "asin", arity: Arity::Exact(1), complex: true, func: |c, a| inverse_trig(c, &a[0], Decimal::asin, f64::asin, Complex64::asin) },
+    Builtin { name: "acos", arity: Arity::Exact(1), complex: true, func: |c, a| inverse_trig(c, &a[0], Decimal::acos, f64::acos, Complex64::acos) },
+    Builtin { name: "atan", arity: Arity::Exact(1), complex: true, func: |c, a| inverse_trig(c, &a[0], Decimal::atan, f64::atan, Complex64::atan) },
+    Builtin { name: "atan2", arity: Arity::Exact(2), complex: false, func: |c, a| atan2(c, &a[0], &a[1]) },
+
+    // hyperbolic
+    Builtin { name: "sinh", arity: Arity::Exact(1), complex: true, func: |_, a| complex_or_float(&a[0], f64::sinh, Complex64::sinh) },
+    Builtin { name: "cosh", arity: Arity::Exact(1), complex: true, func: |_, a| complex_or_float(&a[0], f64::cosh, Complex64::cosh) },
+    Builtin { name: "tanh", arity: Arity::Exact(1), complex: true, func: |_, a| complex_or_float(&a[0], f64::tanh, Complex64::tanh) },
+    Builtin { name: "asinh", arity: Arity::Exact(1), complex: true, func: |_, a| complex_or_float(&a[0], f64::asinh, Complex64::asinh) },
+    Builtin { name: "acosh", arity: Arity::Exact(1), complex: true, func: |_, a| complex_or_float(&a[0], f64::acosh, Complex64::acosh) },
+    Builtin { name: "atanh", arity: Arity::Exact(1), complex: true, func: |_, a| complex_or_float(&a[0], f64::atanh, Complex64::atanh) },
+
+    // powers, roots and logs
+    Builtin { name: "sqrt", arity: Arity::Exact(1), complex: true, func: |c, a| root_or_float(c, &a[0], 2, f64::sqrt, Complex64::sqrt) },
+    Builtin { name: "cbrt", arity: Arity::Exact(1), complex: true, func: |c, a| root_or_float(c, &a[0], 3, f64::cbrt, Complex64::cbrt) },
+    Builtin { name: "nthroot", arity: Arity::Exact(2), complex: true, func: |c, a| nth_root(c, &a[0], &a[1]) },
+    Builtin { name: "hypot", arity: Arity::Exact(2), complex: false, func: |c, a| precise2(c, &a[0], &a[1], Decimal::hypot, f64::hypot) },
+    Builtin { name: "exp", arity: Arity::Exact(1), complex: true, func: |c, a| precise(c, &a[0], Decimal::exp, f64::exp, Complex64::exp) },
+    Builtin { name: "ln", arity: Arity::Exact(1), complex: true, func: |c, a| precise(c, &a[0], Decimal::ln, f64::ln, Complex64::ln) },
+    // log(x) is base 10, log(x, base) is any base
+    Builtin { name: "log", arity: Arity::Range(1, 2), complex: true, func: |c, a| log(c, &a[0], a.get(1).unwrap_or(&Value::from(10))) },
+
+    // complex numbers, real args are complex numbers with no imaginary part
+    Builtin { name: "re", arity: Arity::Exact(1), complex: true, func: |_, a| Ok(real_part(&a[0])) },
+    Builtin { name: "im", arity: Arity::Exact(1), complex: true, func: |_, a| Ok(imaginary_part(&a[0])) },
+    Builtin { name: "arg", arity: Arity::Exact(1), complex: true, func: |c, a| arg(c, &a[0]) },
+    Builtin { name: "conj", arity: Arity::Exact(1), complex: true, func: |_, a| Ok(conjugate(&a[0])) },
+    // polar(r, angle) and rect(x, y) make a complex number from its parts, there is no list type to
+    // split one into, that is what abs and arg, or re and im, are for
+    Builtin { name: "polar", arity: Arity::Exact(2), complex: false, func: |c, a| Ok(Value::Complex(Complex64::from_polar(a[0].to_f64(), c.angle_unit.to_radians(a[1].to_f64())))) },
+    Builtin { name: "rect", arity: Arity::Exact(2), complex: false, func: |_, a| Ok(Value::Complex(Complex64::new(a[0].to_f64(), a[1].to_f64()))) },
+
+    // rounding and sign, abs and sign of a complex number are its magnitude and direction
+    Builtin { name: "abs", arity: Arity::Exact(1), complex: true, func: |_, a| Ok(a[0].abs()) },
+    Builtin { name: "sign", arity: Arity::Exact(1), complex: true, func: |_, a| Ok(a[0].sign()) },
+    Builtin { name: "floor", arity: Arity::Exact(1), complex: true, func: |_, a| Ok(a[0].floor()) },
+    Builtin { name: "ceil", arity: Arity::Exact(1), complex: true, func: |_, a| Ok(a[0].ceil()) },
+    Builtin { name: "round", arity: Arity::Exact(1), complex: true, func: |_, a| Ok(a[0].round()) },
+    Builtin { name: "trunc", arity: Arity::Exact(1), complex: true, func: |_, a| Ok(a[0].trunc()) },
+    Builtin { name: "min", arity: Arity::AtLeast(1), complex: false, func: |_, a| Ok(extreme(a, Ordering::Less)) },
+    Builtin { name: "max", arity: Arity::AtLeast(1), complex: false, func: |_, a| Ok(extreme(a, Ordering::Greater)) },
+
+    // gamma and combinatorics
+    Builtin { name: "fact", arity: Arity::Exact(1), complex: false, func: |_, a| factorial(&a[0]) },
+    Builtin { name: "gamma", arity: Arity::Exact(1), complex: false, func: |_, a| gamma(&a[0]) },
+    Builtin { name: "lgamma", arity: Arity::Exact(1), complex: false, func: |_, a| float(ln_gamma(a[0].to_f64())) },
+    Builtin { name: "nCr", arity: Arity::Exact(2), complex: false, func: |_, a| combinations(&a[0], &a[1]) },
+    Builtin { name: "nPr", arity: Arity::Exact(2), complex: false, func: |_, a| permutations(&a[0], &a[1]) },
+    Builtin { name: "gcd", arity: Arity::AtLeast(1), complex: false, func: |_, a| gcd_all(b"gcd", a) },
+    Builtin { name: "lcm", arity: Arity::AtLeast(1), complex: false, func: |_, a| lcm_all(a) },
+
+    // bits of the word type, see programmer mode
+    Builtin { name: "popcount", arity: Arity::Exact(1), complex: false, func: |c, a| count_bits(c, b"popcount", &a[0], WordType::count_ones) },
+    Builtin { name: "clz", arity: Arity::Exact(1), complex: false, func: |c, a| count_bits(c, b"clz", &a[0], WordType::leading_zeros) },
+    Builtin { name: "ctz", arity: Arity::Exact(1), complex: false, func: |c, a| count_bits(c, b"ctz", &a[0], WordType::trailing_zeros) },
+    Builtin { name: "rotl", arity: Arity::Exact(2), complex: false, func: |c, a| rotate(c, b"rotl", &a[0], &a[1], false) },
+    Builtin { name: "rotr", arity: Arity::Exact(2), complex: false, func: |c, a| rotate(c, b"rotr", &a[0], &a[1], true) },
+];
+
+pub fn get_builtin(name: &[u8]) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|b| b.name.as_bytes() == name)
+}
+
+// a constant that is worked out for the number mode, so that pi has every digit of the precision
+pub struct Constant {
+    pub name: &'static str,
+    pub value: fn(&Calculator) -> Value,
+}
+
+pub const CONSTANTS: &[Constant] = &[
+    Constant { name: "pi", value: |c| precise_constant(c, Decimal::pi, PI) },
+    Constant { name: "e", value: |c| precise_constant(c, Decimal::e, E) },
+    Constant { name: "tau", value: |c| precise_constant(c, Decimal::tau, TAU) },
+    // the imaginary unit, j is what engineers call it
+    Constant { name: "i", value: |_| Value::Complex(Complex64::i()) },
+    Constant { name: "j", value: |_| Value::Complex(Complex64::i()) },
+];
+
+pub fn get_constant(name: &[u8]) -> Option<&'static Constant> {
+    CONSTANTS.iter().find(|c| c.name.as_bytes() == name)
+}
+
+// if(condition, then, otherwise) is parsed into its own expression so that only one branch is
+// solved, it is not in BUILTINS but its name is just as reserved
+pub const IF_NAME: &[u8] = b"if";
+pub const IF_ARITY: Arity = Arity::Exact(3);
+
+// names that user defined and registered functions cannot take
+pub fn is_reserved_name(name: &[u8]) -> bool {
+    name == IF_NAME || get_builtin(name).is_some()
+}
+
+fn float(x: f64) -> Result<Value, CalcError> {
+    Ok(Value::Float(x))
+}
+
+fn invalid_arg(name: &[u8]) -> CalcError {
+    ErrorKind::FuncInvalidArg(String::from_utf8_lossy(name).into_owned()).into()
+}
+
+fn precise_constant(calc: &Calculator, decimal: fn(DecimalContext) -> Decimal, x: f64) -> Value {
+    match calc.number_mode {
+        NumberMode::Decimal => Value::Decimal(decimal(calc.decimal_context())),
+        _ => Value::Float(x),
+    }
+}
+
+// the arg as a decimal in decimal mode, None otherwise
+fn as_decimal(calc: &Calculator, x: &Value) -> Option<Decimal> {
+    match calc.number_mode {
+        NumberMode::Decimal => x.to_decimal(calc.decimal_context()),
+        _ => None,
+    }
+}
+
+// complex args use the complex version, and so do real args that the real version is undefined
+// for, eg. sqrt(-4) or ln(-1)
+fn complex_or_float(
+    x: &Value,
+    float_fn: fn(f64) -> f64,
+    complex_fn: fn(Complex64) -> Complex64,
+) -> Result<Value, CalcError> {
+    if let Value::Complex(z) = x {
+        return Ok(Value::Complex(complex_fn(*z)));
+    }
+    let x = x.to_f64();
+    let result = float_fn(x);
+    if result.is_nan() && !x.is_nan() {
+        return Ok(Value::Complex(complex_fn(Complex64::from(x))));
+    }
+    float(result)
+}
+
+// uses the decimal version in decimal mode, it gives None where the result is undefined and then
+// the float or complex version is used instead
+fn precise(
+    calc: &Calculator,
+    x: &Value,
+    decimal: fn(&Decimal, DecimalContext) -> Option<Decimal>,
+    float_fn: fn(f64) -> f64,
+    complex_fn: fn(Complex64) -> Complex64,
+) -> Result<Value, CalcError> {
+    match as_decimal(calc, x).and_then(|x| decimal(&x, calc.decimal_context())) {
+        Some(result) => Ok(Value::Decimal(result)),
+        None => complex_or_float(x, float_fn, complex_fn),
+    }
+}
+
+fn precise2(
+    calc: &Calculator,
+    x: &Value,
+    y: &Value,
+    decimal: fn(&Decimal, &Decimal, DecimalContext) -> Option<Decimal>,
+    float_fn: fn(f64, f64) -> f64,
+) -> Result<Value, CalcError> {
+    if let (Some(x), Some(y)) = (as_decimal(calc, x), as_decimal(calc, y)) {
+        if let Some(result) = decimal(&x, &y, calc.decimal_context()) {
+            return Ok(Value::Decimal(result));
+        }
+    }
+    float(float_fn(x.to_f64(), y.to_f64()))
+}
+
+// converts x from the calculators angle unit into radians and then applies the function
+fn trig(
+    calc: &Calculator,
+    x: &Value,
+    decimal: fn(&Decimal, DecimalContext) -> Option<Decimal>,
+    float_fn: fn(f64) -> f64,
+    complex_fn: fn(Complex64) -> Complex64,
+) -> Result<Value, CalcError> {
+    let ctx = calc.decimal_context();
+    if let Some(x) = as_decimal(calc, x) {
+        let radians = calc.angle_unit.decimal_to_radians(&x, ctx.working(0));
+        if let Some(result) = decimal(&radians, ctx) {
+            return Ok(Value::Decimal(result));
+        }
+    }
+    match x {
+        Value::Complex(z) => Ok(Value::Complex(complex_fn(z * calc.angle_unit.to_radians(1.0)))),
+        _ => float(float_fn(calc.angle_unit.to_radians(x.to_f64()))),
+    }
+}
+
+// applies the function and converts its result from radians into the calculators angle unit
+fn inverse_trig(
+    calc: &Calculator,
+    x: &Value,
+    decimal: fn(&Decimal, DecimalContext) -> Option<Decimal>,
+    float_fn: fn(f64) -> f64,
+    complex_fn: fn(Complex64) -> Complex64,
+) -> Result<Value, CalcError> {
+    let ctx = calc.decimal_context();
+    if let Some(x) = as_decimal(calc, x) {
+        if let Some(radians) = decimal(&x, ctx.working(0)) {
+            return Ok(Value::Decimal(calc.angle_unit.decimal_radians_to_unit(&radians, ctx.working(0)).round(ctx)));
+        }
+    }
+    match complex_or_float(x, float_fn, complex_fn)? {
+        Value::Complex(z) => Ok(Value::Complex(z * calc.angle_unit.radians_to_unit(1.0))),
+        radians => float(calc.angle_unit.radians_to_unit(radians.to_f64())),
+    }
+}
+
+// the angle of the point (x, y) in the calculators angle unit
+fn atan2(calc: &Calculator, y: &Value, x: &Value) -> Result<Value, CalcError> {
+    let ctx = calc.decimal_context();
+    if let (Some(y), Some(x)) = (as_decimal(calc, y), as_decimal(calc, x)) {
+        if let Some(radians) = y.atan2(&x, ctx.working(0)) {
+            return Ok(Value::Decimal(calc.angle_unit.decimal_radians_to_unit(&radians, ctx.working(0)).round(ctx)));
+        }
+    }
+    float(calc.angle_unit.radians_to_unit(y.to_f64().atan2(x.to_f64())))
+}
+
+fn real_part(x: &Value) -> Value {
+    match x {
+        Value::Complex(z) => Value::Float(z.re),
+        x => x.clone(),
+    }
+}
+
+fn imaginary_part(x: &Value) -> Value {
+    match x {
+        Value::Complex(z) => Value::Float(z.im),
+        _ => Value::from(0),
+    }
+}
+
+fn conjugate(x: &Value) -> Value {
+    match x {
+        Value::Complex(z) => Value::Complex(z.conj()),
+        x => x.clone(),
+    }
+}
+
+// the angle of a complex number, for real numbers this is 0 or half a turn
+fn arg(calc: &Calculator, x: &Value) -> Result<Value, CalcError> {
+    match x {
+        Value::Complex(z) => float(calc.angle_unit.radians_to_unit(z.arg())),
+        x => atan2(calc, &Value::from(0), x),
+    }
+}
+
+// the log of a negative number is complex, eg. log(-100) is 2 + 1.364...i
+fn log(calc: &Calculator, x: &Value, base: &Value) -> Result<Value, CalcError> {
+    // ln(1) and ln(0) would be divided by, and a negative base only makes sense for complex numbers
+    let b = base.to_complex();
+    let negative_base = b.re <= 0.0 && !x.is_complex() && !base.is_complex();
+    if b == Complex64::from(1.0) || b == Complex64::from(0.0) || negative_base {
+        return Err(invalid_arg(b"log"));
+    }
+    if let (Some(x), Some(base)) = (as_decimal(calc, x), as_decimal(calc, base)) {
+        if let Some(result) = x.log(&base, calc.decimal_context()) {
+            return Ok(Value::Decimal(result));
+        }
+    }
+    let result = x.to_f64().log(base.to_f64());
+    let undefined = result.is_nan() && !x.to_f64().is_nan() && !base.to_f64().is_nan();
+    if x.is_complex() || base.is_complex() || undefined {
+        return Ok(Value::Complex(x.to_complex().ln() / base.to_complex().ln()));
+    }
+    float(result)
+}
+
+fn is_integer(x: f64) -> bool {
+    x.is_finite() && x.fract() == 0.0
+}
+
+// the smallest arg for Ordering::Less, the largest for Ordering::Greater, NaNs are skipped
+fn extreme(args: &[Value], ordering: Ordering) -> Value {
+    let mut best = &args[0];
+    for arg in &args[1..] {
+        if best.to_f64().is_nan() || arg.compare(best) == Some(ordering) {
+            best = arg;
+        }
+    }
+    best.clone()
+}
+
+// exact when the root is, eg. sqrt(9/4) is 3/2
+fn root_or_float(
+    calc: &Calculator,
+    x: &Value,
+    n: i64,
+    float_root: fn(f64) -> f64,
+    complex_root: fn(Complex64) -> Complex64,
+) -> Result<Value, CalcError> {
+    if let Some(root) = x.root(&Value::from(n)) {
+        return Ok(root);
+    }
+    if let Some(x) = as_decimal(calc, x) {
+        let root = match n {
+            2 => x.sqrt(calc.decimal_context()),
+            _ => x.nth_root(&Decimal::from_integer(n.into()), calc.decimal_context()),
+        };
+        if let Some(root) = root {
+            return Ok(Value::Decimal(root));
+        }
+    }
+    complex_or_float(x, float_root, complex_root)
+}
+
+// odd roots of negative numbers are real, eg. nthroot(-8, 3) is -2
+fn nth_root(calc: &Calculator, x: &Value, n: &Value) -> Result<Value, CalcError> {
+    if n.is_zero() {
+        return Err(invalid_arg(b"nthroot"));
+    }
+    if let Some(root) = x.root(n) {
+        return Ok(root);
+    }
+    if x.is_complex() || n.is_complex() {
+        return Ok(Value::Complex(x.to_complex().powc(n.to_complex().inv())));
+    }
+    if let (Some(x), Some(n)) = (as_decimal(calc, x), as_decimal(calc, n)) {
+        if let Some(root) = x.nth_root(&n, calc.decimal_context()) {
+            return Ok(Value::Decimal(root));
+        }
+    }
+    let (x, n) = (x.to_f64(), n.to_f64());
+    if x < 0.0 && is_integer(n) && n % 2.0 != 0.0 {
+        return float(-(-x).powf(1.0 / n));
+    }
+    let root = x.powf(1.0 / n);
+    // even roots of negative numbers are complex
+    if root.is_nan() && !x.is_nan() && !n.is_nan() {
+        let x = Complex64::from(x);
+        return Ok(Value::Complex(if n == 2.0 { x.sqrt() } else { x.powf(1.0 / n) }));
+    }
+    float(root)
+}
+
+fn factorial(n: &Value) -> Result<Value, CalcError> {
+    if let Some(n) = n.to_integer() {
+        if n.is_negative() {
+            return Err(invalid_arg(b"fact"));
+        }
+        if let Some(result) = exact_factorial(&n) {
+            return Ok(Value::from(result));
+        }
+    }
+    float_factorial(n.to_f64()).map(Value::Float)
+}
+
+fn float_factorial(n: f64) -> Result<f64, CalcError> {
+    if n < 0.0 || !is_integer(n) {
+        return Err(invalid_arg(b"fact"));
+    }
+    // anything past 170! is larger than f64::MAX
+    if n > 170.0 {
+        return Ok(f64::INFINITY);
+    }
+    Ok((2..=n as u64).fold(1.0, |acc, k| acc * k as f64))
+}
+
+// lanczos approximation, g = 7, n = 9
+const LANCZOS_G: f64 = 7.0;
+const LANCZOS_COEFFICIENTS: [f64; 9] = [
+    0.999_999_999_999_809_9,
+    676.520_368_121_885_1,
+    -1_259.139_216_722_402_8,
+    771.323_428_777_653_1,
+    -176.615_029_162_140_6,
+    12.507_343_278_686_905,
+    -0.138_571_095_265_720_12,
+    9.984_369_578_019_572e-6,
+    1.505_632_735_149_311_6e-7,
+];
+
+fn lanczos_sum(x: f64) -> f64 {
+    let mut sum = LANCZOS_COEFFICIENTS[0];
+    for (i, c) in LANCZOS_COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += c / (x + i as f64);
+    }
+    sum
+}
+
+// gamma(n) is (n - 1)!, so it is exact for exact positive integers
+fn gamma(x: &Value) -> Result<Value, CalcError> {
+    match x.to_integer() {
+        Some(n) if n.is_positive() => factorial(&Value::from(n - 1)),
+        _ => float(float_gamma(x.to_f64())),
+    }
+}
+
+fn float_gamma(x: f64) -> f64 {
+    // integers are exact, and the poles at 0, -1, -2... are undefined
+    if is_integer(x) {
+        if x <= 0.0 {
+            return f64::NAN;
+        }
+        return float_factorial(x - 1.0).unwrap_or(f64::NAN);
+    }
+    // reflection formula for the left half of the plane
+    if x < 0.5 {
+        return PI / ((PI * x).sin() * float_gamma(1.0 - x));
+    }
+    let x = x - 1.0;
+    let t = x + LANCZOS_G + 0.5;
+    (2.0 * PI).sqrt() * t.powf(x + 0.5) * (-t).exp() * lanczos_sum(x)
+}
+
+// ln(|gamma(x)|), stays finite long after gamma overflows
+fn ln_gamma(x: f64) -> f64 {
+    if is_integer(x) && x <= 0.0 {
+        return f64::INFINITY;
+    }
+    if x < 0.5 {
+        return (PI / (PI * x).sin().abs()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + LANCZOS_G + 0.5;
+    0.5 * (2.0 * PI).ln() + (x + 0.5) * t.ln() - t + lanczos_sum(x).ln()
+}
+
+// n and r as exact integers where 0 <= r <= n, None if they are not exact integers
+fn exact_choose_args(name: &[u8], n: &Value, r: &Value) -> Result<Option<(BigInt, BigInt)>, CalcError> {
+    match (n.to_integer(), r.to_integer()) {
+        (Some(n), Some(r)) if r.is_negative() || r > n => Err(invalid_arg(name)),
+        (Some(n), Some(r)) => Ok(Some((n, r))),
+        _ => Ok(None),
+    }
+}
+
+// checks that n and r are integers where 0 <= r <= n
+fn check_choose_args(name: &[u8], n: f64, r: f64) -> Result<(), CalcError> {
+    if !is_integer(n) || !is_integer(r) || r < 0.0 || r > n {
+        return Err(invalid_arg(name));
+    }
+    Ok(())
+}
+
+fn combinations(n: &Value, r: &Value) -> Result<Value, CalcError> {
+    if let Some((n, r)) = exact_choose_args(b"nCr", n, r)? {
+        if let Some(result) = exact_combinations(&n, &r) {
+            return Ok(Value::from(result));
+        }
+    }
+    float_combinations(n.to_f64(), r.to_f64()).map(Value::Float)
+}
+
+fn float_combinations(n: f64, r: f64) -> Result<f64, CalcError> {
+    check_choose_args(b"nCr", n, r)?;
+    // nCr(n, r) == nCr(n, n - r), and the smaller one needs fewer steps
+    let r = r.min(n - r);
+    let mut result: f64 = 1.0;
+    // every step multiplies by at least 1, so once it overflows the rest is not worked out,
+    // this takes at most about a thousand steps however large r is
+    for k in 0..r as u64 {
+        if result.is_infinite() {
+            break;
+        }
+        result = result * (n - k as f64) / (k + 1) as f64;
+    }
+    Ok(result.round())
+}
+
+fn permutations(n: &Value, r: &Value) -> Result<Value, CalcError> {
+    if let Some((n, r)) = exact_choose_args(b"nPr", n, r)? {
+        if let Some(result) = exact_permutations(&n, &r) {
+            return Ok(Value::from(result));
+        }
+    }
+    float_permutations(n.to_f64(), r.to_f64()).map(Value::Float)
+}
+
+fn float_permutations(n: f64, r: f64) -> Result<f64, CalcError> {
+    check_choose_args(b"nPr", n, r)?;
+    let mut result: f64 = 1.0;
+    // like nCr, stops once the product overflows, which is within 171 steps
+    for k in 0..r as u64 {
+        if result.is_infinite() {
+            break;
+        }
+        result *= n - k as f64;
+    }
+    Ok(result)
+}
+
+fn gcd(mut a: f64, mut b: f64) -> f64 {
+    while b != 0.0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
+}
+
+// the args as exact integers, None if any of them is not one
+fn exact_integers(args: &[Value]) -> Option<Vec<BigInt>> {
+    args.iter().map(Value::to_integer).collect()
+}
+
+fn float_integers(name: &[u8], args: &[Value]) -> Result<Vec<f64>, CalcError> {
+    let args: Vec<f64> = args.iter().map(Value::to_f64).collect();
+    if !args.iter().all(|x| is_integer(*x)) {
+        return Err(invalid_arg(name));
+    }
+    Ok(args)
+}
+
+fn gcd_all(name: &[u8], args: &[Value]) -> Result<Value, CalcError> {
+    if let Some(integers) = exact_integers(args) {
+        return Ok(Value::from(integers.iter().fold(BigInt::zero(), |acc, x| acc.gcd(x))));
+    }
+    let args = float_integers(name, args)?;
+    float(args.iter().fold(0.0, |acc, x| gcd(acc, *x)))
+}
+
+fn lcm_all(args: &[Value]) -> Result<Value, CalcError> {
+    if let Some(integers) = exact_integers(args) {
+        return Ok(Value::from(integers.iter().fold(BigInt::one(), |acc, x| acc.lcm(x))));
+    }
+    let args = float_integers(b"lcm", args)?;
+    float(args.iter().fold(1.0, |acc, x| {
+        if acc == 0.0 || *x == 0.0 {
+            0.0
+        } else {
+            (acc / gcd(acc, *x) * x).abs()
+        }
+    }))
+}
+
+// an integer that fits in the calculators word type
+fn word_arg(calc: &Calculator, name: &[u8], x: &Value) -> Result<BigInt, CalcError> {
+    match x.whole_number() {
+        Some(n) if calc.word_type.contains(&n) => Ok(n),
+        _ => Err(invalid_arg(name)),
+    }
+}
+
+fn count_bits(calc: &Calculator, name: &[u8], x: &Value, count: fn(WordType, &BigInt) -> u64) -> Result<Value, CalcError> {
+    let n = word_arg(calc, name, x)?;
+    Ok(Value::from(BigInt::from(count(calc.word_type, &n))))
+}
+
+fn rotate(calc: &Calculator, name: &[u8], x: &Value, shift: &Value, right: bool) -> Result<Value, CalcError> {
+    let n = word_arg(calc, name, x)?;
+    let shift = shift.whole_number().ok_or_else(|| invalid_arg(name))?;
+    let shift = if right { -shift } else { shift };
+    Ok(Value::from(calc.word_type.rotate_left(&n, &shift)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_builtin, Arity, BUILTINS};
+    use crate::mathengine::angle::AngleUnit;
+    use crate::mathengine::core::{Calc, Calculator};
+    use crate::mathengine::error::ErrorKind;
+    use crate::mathengine::testing::{assert_close, error, eval, float, float_of};
+    use crate::mathengine::value::Value;
+
+    #[test]
+    fn factorials() {
+        assert_eq!(float("fact(5)"), 120.0);
+        assert_eq!(float("fact(0)"), 1.0);
+        let mut calc = Calculator::new();
+        for input in ["fact(-1)", "fact(2.5)"] {
+            assert!(matches!(error(&mut calc, input), ErrorKind::FuncInvalidArg(_)), "{input}");
+        }
+    }
+
+    #[test]
+    fn ln_is_the_natural_log() {
+        assert_close(float("ln(e)"), 1.0);
+        assert_close(float("ln(e^3)"), 3.0);
+        assert_eq!(float("ln(1)"), 0.0);
+    }
+
+    #[test]
+    fn inverse_trig_answers_in_the_angle_unit() {
+        let mut calc = Calculator::new();
+        calc.set_angle_unit(AngleUnit::Degrees);
+        assert_close(float_of(&mut calc, "asin(1)"), 90.0);
+        assert_close(float_of(&mut calc, "acos(0)"), 90.0);
+        assert_close(float_of(&mut calc, "atan(1)"), 45.0);
+        assert_close(float_of(&mut calc, "sin(90)"), 1.0);
+        calc.set_angle_unit(AngleUnit::Radians);
+        assert_close(float_of(&mut calc, "asin(1)"), std::f64::consts::FRAC_PI_2);
+    }
+
+    #[test]
+    fn every_builtin_is_found_and_reserved() {
+        let mut calc = Calculator::new();
+        for builtin in BUILTINS {
+            assert_eq!(get_builtin(builtin.name.as_bytes()).unwrap().name, builtin.name);
+            let kind = error(&mut calc, &format!("{}(x) = x", builtin.name));
+            assert!(matches!(kind, ErrorKind::FuncHardcodedReassignAttempt(_)), "{}", builtin.name);
+        }
+        assert!(get_builtin(b"nosuch").is_none());
+    }
+
+    #[test]
+    fn arity_comes_from_the_registry() {
+        let mut calc = Calculator::new();
+        assert_eq!(get_builtin(b"sin").unwrap().arity, Arity::Exact(1));
+        for input in ["sin(1, 2)", "fact()", "min()", "atan2(1)"] {
+            assert!(matches!(error(&mut calc, input), ErrorKind::FuncIncorrectArgCount(..)), "{input}");
+        }
+    }
+
+    #[test]
+    fn hyperbolic_functions() {
+        assert_close(float("sinh(1)"), 1f64.sinh());
+        assert_close(float("cosh(1)^2 - sinh(1)^2"), 1.0);
+        assert_close(float("tanh(asinh(0.5))"), 0.5f64.asinh().tanh());
+        assert_eq!(float("acosh(1)"), 0.0);
+        assert_close(float("atanh(tanh(0.3))"), 0.3);
+        assert_close(float("exp(1)"), std::f64::consts::E);
+    }
+
+    #[test]
+    fn rounding_and_sign() {
+        for (input, expected) in [
+            ("floor(-2.5)", -3.0),
+            ("ceil(-2.5)", -2.0),
+            ("round(2.5)", 3.0),
+            ("round(-2.5)", -3.0),
+            ("trunc(-2.7)", -2.0),
+            ("abs(-3)", 3.0),
+            ("sign(-3)", -1.0),
+            ("sign(0)", 0.0),
+        ] {
+            assert_eq!(float(input), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn min_and_max_take_any_number_of_args() {
+        assert_eq!(float("min(4)"), 4.0);
+        assert_eq!(float("max(1, 5, 3)"), 5.0);
+        assert_eq!(float("min(3, -1, 2, 8, 0)"), -1.0);
+        let mut calc = Calculator::new();
+        assert!(matches!(error(&mut calc, "max()"), ErrorKind::FuncIncorrectArgCount(_, Arity::AtLeast(1), 0)));
+        assert!(matches!(error(&mut calc, "min(1, 2i)"), ErrorKind::FuncComplexArg(_)));
+    }
+
+    #[test]
+    fn functions_of_two_args() {
+        assert_close(float("atan2(1, 1)"), std::f64::consts::FRAC_PI_4);
+        assert_eq!(float("hypot(3, 4)"), 5.0);
+        assert_eq!(float("log(8, 2)"), 3.0);
+        assert_close(float("cbrt(-8)"), -2.0);
+        assert_close(float("nthroot(16, 4)"), 2.0);
+        assert_close(float("nthroot(-8, 3)"), -2.0);
+    }
+
+    #[test]
+    fn gamma_and_combinatorics() {
+        assert_close(float("gamma(5)"), 24.0);
+        assert_close(float("gamma(0.5)^2"), std::f64::consts::PI);
+        assert_close(float("lgamma(3)"), 2f64.ln());
+        assert_eq!(float("nCr(5, 2)"), 10.0);
+        assert_eq!(float("nPr(5, 2)"), 20.0);
+        assert_eq!(float("gcd(12, 18, 8)"), 2.0);
+        assert_eq!(float("lcm(4, 6)"), 12.0);
+        let mut calc = Calculator::new();
+        for input in ["nCr(5, 6)", "gcd(1.5, 3)"] {
+            assert!(matches!(error(&mut calc, input), ErrorKind::FuncInvalidArg(_)), "{input}");
+        }
+    }
+
+    #[test]
+    fn huge_combinations_overflow_quickly() {
+        let mut calc = Calculator::new();
+        assert_eq!(eval(&mut calc, "nCr(1000000000, 500000000)"), Value::Float(f64::INFINITY));
+        assert_eq!(eval(&mut calc, "nPr(1000000000, 500000000)"), Value::Float(f64::INFINITY));
+        assert_eq!(eval(&mut calc, "nCr(1e300, 1e299)"), Value::Float(f64::INFINITY));
+        calc.eval("mode exact").unwrap();
+        assert_eq!(eval(&mut calc, "nCr(1000000000, 500000000)"), Value::Float(f64::INFINITY));
+        assert_eq!(eval(&mut calc, "nCr(10, 3)"), Value::from(120));
+    }
+
+    #[test]
+    fn log_rejects_bases_it_cannot_divide_by() {
+        let mut calc = Calculator::new();
+        for input in ["log(1, 1)", "log(5, 1)", "log(8, 0)", "log(8, -2)", "log(8, 1 + 0i)"] {
+            assert!(matches!(error(&mut calc, input), ErrorKind::FuncInvalidArg(_)), "{input}");
+        }
+        calc.eval("mode decimal").unwrap();
+        assert!(matches!(error(&mut calc, "log(1, 1)"), ErrorKind::FuncInvalidArg(_)));
+    }
+
+    #[test]
+    fn log_of_negative_numbers_is_complex() {
+        let mut calc = Calculator::new();
+        assert_eq!(eval(&mut calc, "log(8, 2)"), Value::Float(3.0));
+        let Value::Complex(z) = eval(&mut calc, "log(-100)") else { panic!("log(-100) is not complex") };
+        assert!((z.re - 2.0).abs() < 1e-12 && (z.im - std::f64::consts::PI / 10f64.ln()).abs() < 1e-12);
+        assert!(matches!(eval(&mut calc, "log(i, -2)"), Value::Complex(_)));
+    }
+
+    #[test]
+    fn polar_and_rect_build_complex_numbers() {
+        let mut calc = Calculator::new();
+        assert_eq!(eval(&mut calc, "rect(1, 2)"), eval(&mut calc, "1 + 2i"));
+        let Value::Float(x) = eval(&mut calc, "abs(polar(2, 1)) + arg(polar(2, 1))") else { panic!() };
+        assert!((x - 3.0).abs() < 1e-12);
+        assert!(matches!(error(&mut calc, "polar(1 + i, 2)"), ErrorKind::FuncComplexArg(_)));
+    }
+}
@@ -0,0 +1,596 @@
+use std::path::PathBuf;
+
+use super::angle::AngleUnit;
+use super::builtin::{get_builtin, BUILTINS, CONSTANTS, IF_NAME};
+use super::core::{Calc, Calculator, EvalResult, MAX_PRECISION};
+use super::decimal::RoundingMode;
+use super::error::{CalcError, ErrorKind};
+use super::format::NumberFormat;
+use super::value::{ComplexDisplay, NumberMode, RationalDisplay, Value};
+use super::word::{Overflow, Radix, WordType};
+
+pub type CommandResult = Result<EvalResult<Value, String>, CalcError>;
+
+// an input such as "deg" or "del x" that is run rather than solved
+pub struct Command {
+    pub name: &'static str,
+    // the args it takes, eg. "<name>", alternatives are separated by |
+    pub args: &'static str,
+    pub help: &'static str,
+    // None when the args do not fit, which is reported with the usage
+    pub run: fn(&mut Calculator, &[&str]) -> Option<CommandResult>,
+}
+
+#[rustfmt::skip]
+pub const COMMANDS: &[Command] = &[
+    // settings
+    Command { name: "rad", args: "", help: "use radians", run: |c, a| angle_unit(c, a, AngleUnit::Radians) },
+    Command { name: "deg", args: "", help: "use degrees", run: |c, a| angle_unit(c, a, AngleUnit::Degrees) },
+    Command { name: "grad", args: "", help: "use gradians", run: |c, a| angle_unit(c, a, AngleUnit::Gradians) },
+    Command { name: "turn", args: "", help: "use turns", run: |c, a| angle_unit(c, a, AngleUnit::Turns) },
+    Command { name: "mode", args: "[mode]", help: "show or set the number mode: float, exact, decimal or programmer", run: mode },
+    Command { name: "precision", args: "[digits]", help: "show or set the significant digits of decimal mode, at most 1000, setting it switches to decimal mode", run: precision },
+    Command { name: "rounding", args: "[mode]", help: "show or set how decimals are rounded: half-even, half-up, half-down, up, down, ceiling or floor", run: rounding },
+    Command { name: "display", args: "<format>", help: "auto, sci, eng [si], fixed <places>, sig <digits>, group on|off, fraction, decimal <places>, rect, polar, dec, hex, bin or oct", run: display },
+    Command { name: "word", args: "[type]", help: "show or set the integers of programmer mode, i8, u8, i16... up to u128", run: word },
+    Command { name: "overflow", args: "wrap|error", help: "what programmer mode does when a result does not fit", run: overflow },
+    Command { name: "recursion", args: "[limit]", help: "show or set how deeply functions can call each other", run: recursion },
+
+    // variables and functions
+    Command { name: "vars", args: "", help: "list the variables and constants", run: vars },
+    Command { name: "funcs", args: "", help: "list the user defined and registered functions", run: funcs },
+    Command { name: "show", args: "<name>", help: "show a variable or the definition of a function", run: show },
+    Command { name: "deps", args: "<function>", help: "list the variables a function reads", run: deps },
+    Command { name: "del", args: "<name>", help: "delete a variable or function", run: delete },
+    Command { name: "reset", args: "", help: "delete everything and restore the default settings", run: reset },
+
+    // history and sessions
+    Command { name: "history", args: "[clear]", help: "list or clear the previous answers", run: history },
+    Command { name: "rerun", args: "<index>", help: "solve the input of a previous answer again", run: rerun },
+    Command { name: "save", args: "<file>", help: "save the settings, variables and functions", run: save },
+    Command { name: "load", args: "<file>", help: "load a saved session", run: load },
+    Command { name: "help", args: "[command]", help: "list the commands, functions and constants", run: help },
+];
+
+pub fn get_command(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|c| c.name == name)
+}
+
+impl Command {
+    // eg. "del <name>"
+    pub fn usage(&self) -> String {
+        if self.args.is_empty() {
+            self.name.to_string()
+        } else {
+            format!("{} {}", self.name, self.args)
+        }
+    }
+}
+
+fn feedback(message: impl Into<String>) -> Option<CommandResult> {
+    Some(Ok(EvalResult::Feedback(message.into())))
+}
+
+fn error(kind: ErrorKind) -> Option<CommandResult> {
+    Some(Err(kind.into()))
+}
+
+fn cannot_parse(number: &str) -> Option<CommandResult> {
+    error(ErrorKind::CannotParseNumber(number.to_string()))
+}
+
+fn unknown_setting(setting: &str) -> Option<CommandResult> {
+    error(ErrorKind::UnknownSetting(setting.to_string()))
+}
+
+// several lines of output, indented to line up in the REPL
+fn lines(lines: Vec<String>) -> Option<CommandResult> {
+    feedback(lines.join("\n  "))
+}
+
+fn angle_unit(calc: &mut Calculator, args: &[&str], unit: AngleUnit) -> Option<CommandResult> {
+    let [] = args else { return None };
+    calc.angle_unit = unit;
+    feedback(format!("angle unit set to {unit}"))
+}
+
+fn mode(calc: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    match args {
+        [] => feedback(format!("number mode is {}", calc.number_mode)),
+        [mode] => {
+            calc.number_mode = match *mode {
+                "float" => NumberMode::Float,
+                "exact" => NumberMode::Exact,
+                "decimal" => NumberMode::Decimal,
+                "programmer" => NumberMode::Programmer,
+                _ => return unknown_setting(mode),
+            };
+            feedback(format!("number mode set to {mode}"))
+        }
+        _ => None,
+    }
+}
+
+fn precision(calc: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    match args {
+        [] => feedback(format!("precision is {} significant digits", calc.precision)),
+        // also switches to decimal mode, since that is the only mode the precision applies to
+        [digits] => match digits.parse() {
+            Ok(digits) if digits > MAX_PRECISION => too_many_digits("precision <digits>"),
+            Ok(digits) => {
+                calc.set_precision(digits);
+                calc.number_mode = NumberMode::Decimal;
+                feedback(format!("precision set to {} significant digits", calc.precision))
+            }
+            Err(_) => cannot_parse(digits),
+        },
+        _ => None,
+    }
+}
+
+// digit counts are capped, since every digit has to be worked out or written
+fn too_many_digits(usage: &str) -> Option<CommandResult> {
+    error(ErrorKind::CommandUsage(format!("{usage}, at most {MAX_PRECISION}")))
+}
+
+fn rounding(calc: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    match args {
+        [] => feedback(format!("rounding mode is {}", calc.rounding_mode)),
+        [mode] => match RoundingMode::from_name(mode) {
+            Some(mode) => {
+                calc.rounding_mode = mode;
+                feedback(format!("rounding mode set to {mode}"))
+            }
+            None => unknown_setting(mode),
+        },
+        _ => None,
+    }
+}
+
+fn display(calc: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    let set_format = |calc: &mut Calculator, format: NumberFormat, message: String| {
+        calc.number_format = format;
+        feedback(message)
+    };
+    match args {
+        // how numbers are written out, eg. "display sci" or "display fixed 2"
+        ["auto"] => set_format(calc, NumberFormat::Auto, "showing numbers as they are".to_string()),
+        ["sci"] => set_format(calc, NumberFormat::Scientific, "showing numbers in scientific notation".to_string()),
+        ["eng"] => {
+            let format = NumberFormat::Engineering { si_prefixes: false };
+            set_format(calc, format, "showing numbers in engineering notation".to_string())
+        }
+        ["eng", "si"] => {
+            let format = NumberFormat::Engineering { si_prefixes: true };
+            set_format(calc, format, "showing numbers with SI prefixes".to_string())
+        }
+        ["fixed", places] => match places.parse() {
+            Ok(places) if places > MAX_PRECISION => too_many_digits("display fixed <places>"),
+            Ok(places) => set_format(calc, NumberFormat::Fixed(places), format!("showing numbers to {places} decimal places")),
+            Err(_) => cannot_parse(places),
+        },
+        ["sig", count] => match count.parse() {
+            Ok(count) if count > MAX_PRECISION => too_many_digits("display sig <count>"),
+            Ok(count) if count > 0 => {
+                set_format(calc, NumberFormat::SignificantFigures(count), format!("showing numbers to {count} significant figures"))
+            }
+            _ => cannot_parse(count),
+        },
+        ["group", setting] => {
+            calc.digit_grouping = match *setting {
+                "on" => true,
+                "off" => false,
+                _ => return unknown_setting(setting),
+            };
+            feedback(format!("digit grouping {setting}"))
+        }
+        // how exact fractions are shown, eg. "display decimal 20"
+        ["fraction"] => {
+            calc.rational_display = RationalDisplay::Fraction;
+            feedback("showing fractions")
+        }
+        ["decimal", digits] => match digits.parse() {
+            Ok(digits) if digits > MAX_PRECISION => too_many_digits("display decimal <places>"),
+            Ok(digits) => {
+                calc.rational_display = RationalDisplay::Decimal(digits);
+                feedback(format!("showing fractions to {digits} decimal places"))
+            }
+            Err(_) => cannot_parse(digits),
+        },
+        // how complex numbers are shown, eg. "display polar"
+        ["rect"] => {
+            calc.complex_display = ComplexDisplay::Rectangular;
+            feedback("showing complex numbers as a + bi")
+        }
+        ["polar"] => {
+            calc.complex_display = ComplexDisplay::Polar;
+            feedback("showing complex numbers as magnitude ∠ angle")
+        }
+        // the base programmer mode shows integers in, eg. "display hex"
+        [name] => {
+            let radix = Radix::from_name(name)?;
+            calc.radix = radix;
+            feedback(format!("showing integers in {radix}"))
+        }
+        _ => None,
+    }
+}
+
+// the integers programmer mode works with, eg. "word u8"
+fn word(calc: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    match args {
+        [] => feedback(format!("word type is {}, overflow is {}", calc.word_type, calc.overflow)),
+        [name] => match WordType::from_name(name) {
+            Some(word_type) => {
+                calc.word_type = word_type;
+                feedback(format!("word type set to {word_type}"))
+            }
+            None => unknown_setting(name),
+        },
+        _ => None,
+    }
+}
+
+fn overflow(calc: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    let [overflow] = args else { return None };
+    calc.overflow = match *overflow {
+        "wrap" => Overflow::Wrap,
+        "error" => Overflow::Error,
+        _ => return unknown_setting(overflow),
+    };
+    feedback(format!("overflow set to {overflow}"))
+}
+
+fn recursion(calc: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    match args {
+        [] => feedback(format!("recursion limit is {}", calc.recursion_limit)),
+        [limit] => match limit.parse() {
+            Ok(limit) => {
+                calc.recursion_limit = limit;
+                feedback(format!("recursion limit set to {limit}"))
+            }
+            Err(_) => cannot_parse(limit),
+        },
+        _ => None,
+    }
+}
+
+fn vars(calc: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    let [] = args else { return None };
+    let declaration = |name: &str| if calc.is_constant(name) { "const " } else { "" };
+    lines(
+        calc.variables()
+            .iter()
+            .map(|(name, value)| format!("{}{name} = {}", declaration(name), calc.format(value)))
+            .collect(),
+    )
+}
+
+fn funcs(calc: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    let [] = args else { return None };
+    let mut functions: Vec<String> = calc
+        .functions()
+        .iter()
+        .filter_map(|(name, _)| calc.function_source(name))
+        .collect();
+    functions.extend(
+        calc.registered_functions()
+            .iter()
+            .map(|(name, arity)| format!("{name}    (registered, takes {arity} args)")),
+    );
+    if functions.is_empty() {
+        return feedback("no functions defined");
+    }
+    lines(functions)
+}
+
+fn show(calc: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    let [name] = args else { return None };
+    if let Some(source) = calc.function_source(name) {
+        return feedback(source);
+    }
+    if let Some(value) = calc.variable(name) {
+        return feedback(format!("{name} = {}", calc.format(&value)));
+    }
+    if get_builtin(name.as_bytes()).is_some() || name.as_bytes() == IF_NAME {
+        return feedback(format!("{name} is a builtin function"));
+    }
+    match calc.function_arity(name) {
+        Some(arity) => feedback(format!("{name} is a registered function that takes {arity} args")),
+        None => error(ErrorKind::UnknownName(name.to_string())),
+    }
+}
+
+fn deps(calc: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    let [name] = args else { return None };
+    match calc.function_variables(name) {
+        Some(names) if names.is_empty() => feedback(format!("{name} has no free variables")),
+        Some(names) => feedback(format!("{name} depends on {}", names.join(", "))),
+        None => error(ErrorKind::FuncDoesNotExist(name.to_string())),
+    }
+}
+
+fn delete(calc: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    let [name] = args else { return None };
+    // a name can be both a variable and a function, eg. f = 2 and f(x) = x
+    let variable = calc.remove_variable(name);
+    let function = calc.remove_function(name);
+    match (variable, function) {
+        (true, true) => feedback(format!("deleted variable and function {name}")),
+        (true, false) => feedback(format!("deleted variable {name}")),
+        (false, true) => feedback(format!("deleted function {name}")),
+        _ if calc.is_constant(name) => error(ErrorKind::ConstDeleteAttempt(name.to_string())),
+        _ if calc.function_arity(name).is_some() => error(ErrorKind::FuncHardcodedReassignAttempt(name.to_string())),
+        _ => error(ErrorKind::UnknownName(name.to_string())),
+    }
+}
+
+fn reset(calc: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    let [] = args else { return None };
+    calc.reset();
+    feedback("deleted all variables, functions and answers, and restored the default settings")
+}
+
+fn history(calc: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    match args {
+        [] if calc.history.is_empty() => feedback("history is empty"),
+        [] => lines(
+            calc.history
+                .iter()
+                .enumerate()
+                .map(|(i, entry)| format!("ans{} = {}    ({})", i + 1, calc.format(&entry.value), entry.input))
+                .collect(),
+        ),
+        ["clear"] => {
+            calc.history.clear();
+            feedback("cleared history")
+        }
+        _ => None,
+    }
+}
+
+// solves the input of a previous answer again, eg. "rerun 2"
+fn rerun(calc: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    let [index] = args else { return None };
+    let input = match index.parse().ok().and_then(|i| calc.history.get(i)) {
+        Some(entry) => entry.input.clone(),
+        None => return error(ErrorKind::VarDoesNotExist(format!("ans{index}"))),
+    };
+    Some(calc.eval(&input))
+}
+
+// the session is a script of commands and assignments, eg. "save work.calc"
+fn save(calc: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    let [path] = args else { return None };
+    match calc.save_session(home_path(path)) {
+        Ok(()) => feedback(format!("saved session to {path}")),
+        Err(e) => Some(Err(e)),
+    }
+}
+
+fn load(calc: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    let [path] = args else { return None };
+    match calc.load_session(home_path(path)) {
+        Ok(()) => feedback(format!("loaded session from {path}")),
+        Err(e) => Some(Err(e)),
+    }
+}
+
+// a path starting with ~/ is in the home directory, as it would be in a shell
+fn home_path(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn help(_: &mut Calculator, args: &[&str]) -> Option<CommandResult> {
+    match args {
+        [] => {
+            let width = COMMANDS.iter().map(|c| c.usage().len()).max().unwrap_or(0);
+            let mut help: Vec<String> = COMMANDS.iter().map(|c| format!("{:width$}  {}", c.usage(), c.help)).collect();
+            let mut functions: Vec<&str> = BUILTINS.iter().map(|b| b.name).collect();
+            functions.push("if");
+            help.push(String::new());
+            help.push(format!("functions: {}", functions.join(", ")));
+            let constants: Vec<&str> = CONSTANTS.iter().map(|c| c.name).collect();
+            help.push(format!("constants: {}, declare more with const name = value", constants.join(", ")));
+            lines(help)
+        }
+        [name] => match get_command(name) {
+            Some(command) => feedback(format!("{}  {}", command.usage(), command.help)),
+            None => error(ErrorKind::UnknownCommand(name.to_string())),
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathengine::builtin::Arity;
+    use crate::mathengine::testing::{error, eval, feedback, TempFile};
+
+    #[test]
+    fn history_lists_newest_first() {
+        let mut calc = Calculator::new();
+        assert_eq!(feedback(&mut calc, "history"), "history is empty");
+        calc.eval("1+1").unwrap();
+        calc.eval("2*3").unwrap();
+        assert_eq!(feedback(&mut calc, "history"), "ans1 = 6    (2*3)\n  ans2 = 2    (1+1)");
+        assert_eq!(feedback(&mut calc, "history clear"), "cleared history");
+        assert!(matches!(error(&mut calc, "ans"), ErrorKind::VarDoesNotExist(_)));
+        assert!(matches!(error(&mut calc, "history all"), ErrorKind::CommandUsage(_)));
+    }
+
+    #[test]
+    fn rerun_solves_an_answer_again() {
+        let mut calc = Calculator::new();
+        calc.eval("x = 2").unwrap();
+        calc.eval("x^2").unwrap();
+        calc.eval("x = 3").unwrap();
+        calc.eval("1").unwrap();
+        assert_eq!(eval(&mut calc, "rerun 2"), Value::Float(9.0));
+        assert_eq!(calc.history.get(1).unwrap().input, "x^2");
+        for input in ["rerun 9", "rerun 0", "rerun x"] {
+            assert!(matches!(error(&mut calc, input), ErrorKind::VarDoesNotExist(_)), "{input}");
+        }
+    }
+
+    #[test]
+    fn listing_variables_and_functions() {
+        let mut calc = Calculator::new();
+        assert_eq!(feedback(&mut calc, "funcs"), "no functions defined");
+        calc.eval("x = 2").unwrap();
+        calc.eval("f(a, b) = a + b").unwrap();
+        let vars = feedback(&mut calc, "vars");
+        assert!(vars.starts_with("const e = 2.71828182845905\n"));
+        assert!(vars.contains("\n  const pi = 3.14159265358979\n"));
+        assert!(vars.ends_with("\n  x = 2"));
+        assert!(feedback(&mut calc, "funcs").contains("f(a, b) = a + b"));
+        assert!(matches!(error(&mut calc, "vars x"), ErrorKind::CommandUsage(_)));
+    }
+
+    #[test]
+    fn show_finds_any_name() {
+        let mut calc = Calculator::new();
+        calc.eval("x = 2").unwrap();
+        calc.eval("f(a, b) = a + b").unwrap();
+        calc.register_fn("native", Arity::Exact(2), |a| Ok(a[0])).unwrap();
+        assert_eq!(feedback(&mut calc, "show x"), "x = 2");
+        assert_eq!(feedback(&mut calc, "show f"), "f(a, b) = a + b");
+        assert_eq!(feedback(&mut calc, "show pi"), "pi = 3.14159265358979");
+        assert_eq!(feedback(&mut calc, "show sin"), "sin is a builtin function");
+        assert_eq!(feedback(&mut calc, "show if"), "if is a builtin function");
+        assert!(feedback(&mut calc, "show native").starts_with("native is a registered function"));
+        assert!(matches!(error(&mut calc, "show nope"), ErrorKind::UnknownName(_)));
+    }
+
+    #[test]
+    fn del_removes_variables_and_functions() {
+        let mut calc = Calculator::new();
+        calc.eval("x = 2").unwrap();
+        calc.eval("f(a) = a").unwrap();
+        calc.eval("f = 3").unwrap();
+        assert_eq!(feedback(&mut calc, "del x"), "deleted variable x");
+        assert_eq!(feedback(&mut calc, "del f"), "deleted variable and function f");
+        assert!(calc.variable("f").is_none() && calc.function_source("f").is_none());
+        assert!(matches!(error(&mut calc, "del x"), ErrorKind::UnknownName(_)));
+        assert!(matches!(error(&mut calc, "del"), ErrorKind::CommandUsage(_)));
+    }
+
+    #[test]
+    fn reset_restores_the_initial_state() {
+        let mut calc = Calculator::new();
+        for input in ["x = 2", "f(a) = a", "deg", "display sci", "1 + 1"] {
+            calc.eval(input).unwrap();
+        }
+        calc.eval("reset").unwrap();
+        assert!(calc.variable("x").is_none());
+        assert!(calc.functions().is_empty());
+        assert_eq!(calc.history().count(), 0);
+        assert_eq!(calc.angle_unit(), AngleUnit::Radians);
+        assert_eq!(calc.number_format(), NumberFormat::Auto);
+        assert_eq!(calc.variables(), Calculator::new().variables());
+    }
+
+    #[test]
+    fn help_lists_commands_functions_and_constants() {
+        let mut calc = Calculator::new();
+        let help = feedback(&mut calc, "help");
+        for command in COMMANDS {
+            assert!(help.contains(&command.usage()), "{}", command.name);
+        }
+        for builtin in BUILTINS {
+            assert!(help.contains(builtin.name), "{}", builtin.name);
+        }
+        assert!(help.contains("constants: pi, e, tau, i, j"));
+        assert_eq!(feedback(&mut calc, "help del"), "del <name>  delete a variable or function");
+        assert!(matches!(error(&mut calc, "help nope"), ErrorKind::UnknownCommand(_)));
+    }
+
+    #[test]
+    fn absolute_paths_are_arguments() {
+        let file = TempFile::new("absolute.calc", "");
+        assert!(file.path().starts_with('/'));
+        let mut calc = Calculator::new();
+        calc.eval("x = 7").unwrap();
+        assert!(feedback(&mut calc, &format!("save {}", file.path())).starts_with("saved session"));
+        let mut other = Calculator::new();
+        other.eval(&format!("load {}", file.path())).unwrap();
+        assert_eq!(other.variable("x"), Some(Value::from(7)));
+    }
+
+    #[test]
+    fn home_paths_are_arguments() {
+        let mut calc = Calculator::new();
+        for command in ["save ~/rustcalc-missing/work.calc", "load ~/rustcalc-missing/work.calc"] {
+            match error(&mut calc, command) {
+                ErrorKind::FileError(path, _) => assert!(path.ends_with("rustcalc-missing/work.calc"), "{path}"),
+                kind => panic!("{command} gave {kind:?}"),
+            }
+        }
+        if let Some(home) = std::env::var_os("HOME") {
+            assert_eq!(home_path("~/work.calc"), PathBuf::from(home).join("work.calc"));
+        }
+        assert_eq!(home_path("work.calc"), PathBuf::from("work.calc"));
+    }
+
+    #[test]
+    fn del_refuses_constants() {
+        let mut calc = Calculator::new();
+        calc.eval("const g = 2").unwrap();
+        assert!(matches!(error(&mut calc, "del g"), ErrorKind::ConstDeleteAttempt(_)));
+        assert!(matches!(error(&mut calc, "del pi"), ErrorKind::ConstDeleteAttempt(_)));
+        assert!(matches!(error(&mut calc, "g = 5"), ErrorKind::ConstReassignAttempt(_)));
+        assert_eq!(calc.variable("g"), Some(Value::Float(2.0)));
+    }
+
+    #[test]
+    fn command_names_cannot_be_assigned() {
+        let mut calc = Calculator::new();
+        for input in ["rad = 2", "mode = 2", "const vars = 1", "show(x) = x", "del(x) = x"] {
+            assert!(matches!(error(&mut calc, input), ErrorKind::CommandNameReassignAttempt(_)), "{input}");
+        }
+        assert!(calc.set_variable("help", 1.0).is_err());
+        // so a bare command name always runs the command
+        calc.eval("deg").unwrap();
+        assert!(matches!(calc.eval("rad").unwrap(), EvalResult::Feedback(_)));
+    }
+
+    #[test]
+    fn operators_after_a_command_name_make_an_expression() {
+        let mut calc = Calculator::new();
+        assert!(matches!(error(&mut calc, "history + 1"), ErrorKind::VarDoesNotExist(_)));
+        assert!(matches!(error(&mut calc, "mode ~ 2"), ErrorKind::InvalidTokenSeq));
+        assert!(matches!(error(&mut calc, "mode / 2"), ErrorKind::VarDoesNotExist(_)));
+        assert!(matches!(error(&mut calc, "mode fast"), ErrorKind::UnknownSetting(_)));
+        assert!(matches!(error(&mut calc, "mode exact float"), ErrorKind::CommandUsage(_)));
+    }
+
+    #[test]
+    fn load_stops_scripts_that_load_themselves() {
+        let file = TempFile::new("self.calc", "");
+        std::fs::write(&file.0, format!("x = 1\nload {}\n", file.path())).unwrap();
+        let mut calc = Calculator::new();
+        match error(&mut calc, &format!("load {}", file.path())) {
+            ErrorKind::ScriptError(_, 2, kind) => assert!(matches!(*kind, ErrorKind::ScriptTooDeep(_))),
+            kind => panic!("{kind:?}"),
+        }
+        assert_eq!(calc.script_depth, 0);
+        assert_eq!(calc.variable("x"), Some(Value::Float(1.0)));
+    }
+
+    #[test]
+    fn precision_is_capped() {
+        let mut calc = Calculator::new();
+        assert!(matches!(error(&mut calc, "precision 100000000"), ErrorKind::CommandUsage(_)));
+        assert_eq!(calc.precision(), 50);
+        assert_eq!(calc.number_mode(), NumberMode::Float);
+        calc.eval("precision 1000").unwrap();
+        assert_eq!(calc.precision(), 1000);
+        assert_eq!(calc.number_mode(), NumberMode::Decimal);
+        calc.set_precision(5000);
+        assert_eq!(calc.precision(), 1000);
+    }
+}
@@ -0,0 +1,161 @@
+use std::fs;
+use std::path::Path;
+
+use super::angle::AngleUnit;
+use super::core::{Calculator, MAX_PRECISION};
+use super::error::{CalcError, ErrorKind};
+
+impl Calculator {
+    /// Applies settings written one per line as `name = value`, eg.
+    ///
+    /// ```text
+    /// # the angle unit, rad, deg, grad or turn
+    /// angle = deg
+    /// mode = decimal
+    /// precision = 30
+    /// display = fixed 4
+    /// history = 500
+    /// ```
+    ///
+    /// `mode`, `rounding`, `display`, `word`, `overflow` and `recursion` take the same values as the
+    /// commands of the same name, `precision` does not switch to decimal mode and `history` is
+    /// how many answers are kept. Blank lines and lines starting with `#` are skipped.
+    ///
+    /// Stops at the first line that fails with [`ErrorKind::ScriptError`], which names the source
+    /// and the line number.
+    pub fn apply_config(&mut self, config: &str, source: &str) -> Result<(), CalcError> {
+        for (i, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.apply_setting(line)
+                .map_err(|kind| ErrorKind::ScriptError(source.to_string(), i + 1, Box::new(kind)))?;
+        }
+        Ok(())
+    }
+
+    /// Reads a config file and applies it, see [`Calculator::apply_config`].
+    pub fn load_config<P: AsRef<Path>>(&mut self, path: P) -> Result<(), CalcError> {
+        let path = path.as_ref();
+        let config = fs::read_to_string(path).map_err(|e| ErrorKind::FileError(path.display().to_string(), e.to_string()))?;
+        self.apply_config(&config, &path.display().to_string())
+    }
+
+    fn apply_setting(&mut self, line: &str) -> Result<(), ErrorKind> {
+        let Some((name, value)) = line.split_once('=') else {
+            return Err(ErrorKind::ConfigSyntax(line.to_string()));
+        };
+        let (name, value) = (name.trim(), value.trim());
+        let command = match name {
+            "angle" if AngleUnit::from_name(value.as_bytes()).is_some() => value.to_string(),
+            "angle" => return Err(ErrorKind::UnknownSetting(value.to_string())),
+            "precision" => {
+                let digits = value.parse().map_err(|_| ErrorKind::CannotParseNumber(value.to_string()))?;
+                if digits > MAX_PRECISION {
+                    return Err(ErrorKind::CommandUsage(format!("precision = <digits>, at most {MAX_PRECISION}")));
+                }
+                self.set_precision(digits);
+                return Ok(());
+            }
+            "history" => {
+                let size = value.parse().map_err(|_| ErrorKind::CannotParseNumber(value.to_string()))?;
+                self.set_history_size(size);
+                return Ok(());
+            }
+            "mode" | "rounding" | "display" | "word" | "overflow" | "recursion" => format!("{name} {value}"),
+            _ => return Err(ErrorKind::UnknownSetting(name.to_string())),
+        };
+        match self.run_command(&command) {
+            Some(result) => result.map(|_| ()).map_err(|e| e.kind),
+            None => Err(ErrorKind::UnknownSetting(value.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathengine::decimal::RoundingMode;
+    use crate::mathengine::format::NumberFormat;
+    use crate::mathengine::testing::{eval, TempFile};
+    use crate::mathengine::value::{NumberMode, Value};
+    use crate::mathengine::word::{Overflow, WordType};
+
+    // the error a config fails with, and the line it names
+    fn config_error(config: &str) -> (usize, ErrorKind) {
+        match Calculator::new().apply_config(config, "config").unwrap_err().kind {
+            ErrorKind::ScriptError(source, line, kind) if source == "config" => (line, *kind),
+            kind => panic!("{kind:?}"),
+        }
+    }
+
+    #[test]
+    fn settings_are_applied() {
+        let config = "# comment\n\nangle = deg\nmode = exact\nprecision = 30\nrounding = floor\ndisplay = fixed 4\n\
+                      history = 5\nword = u16\noverflow = error\nrecursion = 50\n";
+        let mut calc = Calculator::new();
+        calc.apply_config(config, "config").unwrap();
+        assert_eq!(calc.angle_unit(), AngleUnit::Degrees);
+        // precision on its own does not switch to decimal mode
+        assert_eq!(calc.number_mode(), NumberMode::Exact);
+        assert_eq!(calc.precision(), 30);
+        assert_eq!(calc.rounding_mode(), RoundingMode::Floor);
+        assert_eq!(calc.number_format(), NumberFormat::Fixed(4));
+        assert_eq!(calc.history_size(), 5);
+        assert_eq!(calc.word_type(), WordType::new(16, false).unwrap());
+        assert_eq!(calc.overflow(), Overflow::Error);
+        assert_eq!(calc.recursion_limit(), 50);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        assert!(matches!(config_error("angle = deg\nangle degrees"), (2, ErrorKind::ConfigSyntax(_))));
+        assert!(matches!(config_error("angle = degrees"), (1, ErrorKind::UnknownSetting(_))));
+        assert!(matches!(config_error("\n\ncolour = red"), (3, ErrorKind::UnknownSetting(_))));
+        assert!(matches!(config_error("precision = lots"), (1, ErrorKind::CannotParseNumber(_))));
+        assert!(matches!(config_error("display = sideways"), (1, ErrorKind::CommandUsage(_))));
+        // settings before the mistake are kept
+        let mut calc = Calculator::new();
+        assert!(calc.apply_config("angle = grad\nmode = fast\nrecursion = 5", "config").is_err());
+        assert_eq!(calc.angle_unit(), AngleUnit::Gradians);
+        assert_eq!(calc.recursion_limit(), 1000);
+    }
+
+    #[test]
+    fn config_files() {
+        let file = TempFile::new("config", "angle = turn\n");
+        let mut calc = Calculator::new();
+        calc.load_config(&file.0).unwrap();
+        assert_eq!(calc.angle_unit(), AngleUnit::Turns);
+        let kind = calc.load_config("/nonexistent/rustcalc/config").unwrap_err().kind;
+        assert!(matches!(kind, ErrorKind::FileError(..)));
+    }
+
+    #[test]
+    fn preludes_define_constants_and_functions() {
+        let prelude = TempFile::new("prelude.calc", "# shared constants\nconst g = 9.80665\nweight(m) = m * g\n");
+        let mut calc = Calculator::new();
+        calc.run_file(&prelude.0).unwrap();
+        assert!(calc.is_constant("g"));
+        assert_eq!(eval(&mut calc, "weight(2)"), Value::Float(19.6133));
+
+        let broken = TempFile::new("broken.calc", "a = 1\nb = a +\n");
+        match calc.run_file(&broken.0).unwrap_err().kind {
+            ErrorKind::ScriptError(source, 2, _) => assert_eq!(source, broken.path()),
+            kind => panic!("{kind:?}"),
+        }
+    }
+
+    #[test]
+    fn digits_are_capped() {
+        let mut calc = Calculator::new();
+        for config in ["precision = 100000000", "display = fixed 100000000"] {
+            let kind = calc.apply_config(config, "<test>").unwrap_err().kind;
+            assert!(matches!(kind, ErrorKind::ScriptError(_, 1, kind) if matches!(*kind, ErrorKind::CommandUsage(_))));
+        }
+        calc.apply_config("precision = 1000\ndisplay = fixed 1000", "<test>").unwrap();
+        assert_eq!(calc.precision(), 1000);
+        assert_eq!(calc.number_format(), NumberFormat::Fixed(1000));
+    }
+}
@@ -1,120 +1,788 @@
-use crate::mathengine::token::{tokenize, Token::{self, *}};
-use std::f64::consts::{E, PI, TAU};
-use std::collections::HashMap;
-use super::error::CalcError;
-use super::math_function::{Function, FunctionHandling};
+use crate::mathengine::token::{tokenize, Span, SpannedToken, Token::{self, *}};
+use std::collections::{HashMap, HashSet};
+use super::angle::AngleUnit;
+use super::command::{get_command, CommandResult};
+use super::error::{CalcError, ErrorKind};
+use super::format::{format_value, FormatOptions, NumberFormat};
+use super::history::{answer_index, History, HistoryEntry};
+use super::builtin::{get_builtin, get_constant, is_reserved_name, Arity, CONSTANTS, IF_ARITY, IF_NAME};
+use super::decimal::{Decimal, DecimalContext, RoundingMode};
+use super::math_function::{Function, FunctionHandling, NativeFunction};
+use super::parse::parse;
+use super::provider::VariableProvider;
 use super::solve::Solver;
-use super::token::TokenHandling;
 use super::validate::{is_valid_lhs_function, validate_token_list};
+use super::value::{ComplexDisplay, NumberMode, RationalDisplay, Value};
+use super::word::{Overflow, Radix, WordType};
+
+// how many previous answers are kept
+const HISTORY_SIZE: usize = 100;
+
+// how deeply user defined functions can call each other by default
+const RECURSION_LIMIT: usize = 1000;
+
+// how many significant digits decimal mode keeps by default
+const PRECISION: usize = 50;
+
+// the most significant digits decimal mode keeps, and the most digits answers are shown to, the
+// series behind pi, sqrt or exp get slow quickly past this
+pub(crate) const MAX_PRECISION: usize = 1000;
+
+// declares a variable that cannot be reassigned, eg. `const g = 9.80665`
+const CONST_KEYWORD: &[u8] = b"const";
 
 pub struct Calculator {
-    pub prev_answers: Vec<Token>,
-    pub variables: HashMap<Vec<u8>, Token>,
-    pub functions: HashMap<Vec<u8>, Function>,
-    pub use_radians: bool,
+    pub(crate) history: History,
+    pub(crate) variables: HashMap<Vec<u8>, Token>,
+    // the variables declared with const, their values are kept with the other variables
+    pub(crate) constants: HashSet<Vec<u8>>,
+    // set while a saved session runs, so that its const declarations replace the current ones
+    pub(crate) loading_session: bool,
+    // how many scripts are running inside each other, eg. a session that loads another
+    pub(crate) script_depth: usize,
+    pub(crate) functions: HashMap<Vec<u8>, Function>,
+    pub(crate) native_functions: HashMap<Vec<u8>, NativeFunction>,
+    pub(crate) providers: Vec<Box<dyn VariableProvider>>,
+    pub(crate) angle_unit: AngleUnit,
+    pub(crate) recursion_limit: usize,
+    pub(crate) number_mode: NumberMode,
+    pub(crate) rational_display: RationalDisplay,
+    pub(crate) complex_display: ComplexDisplay,
+    pub(crate) number_format: NumberFormat,
+    pub(crate) digit_grouping: bool,
+    pub(crate) precision: usize,
+    pub(crate) rounding_mode: RoundingMode,
+    pub(crate) word_type: WordType,
+    pub(crate) overflow: Overflow,
+    pub(crate) radix: Radix,
+}
+
+impl Default for Calculator {
+    fn default() -> Calculator {
+        Calculator::new()
+    }
 }
 
 impl Calculator {
+    /// Creates a calculator with the constants `pi`, `e` and `tau` defined, using radians.
     pub fn new() -> Calculator {
-        let mut calc_engine = Calculator {
-            prev_answers: Vec::new(),
+        Calculator {
+            history: History::new(HISTORY_SIZE),
             variables: HashMap::<Vec<u8>, Token>::new(),
+            constants: HashSet::<Vec<u8>>::new(),
+            loading_session: false,
+            script_depth: 0,
             functions: HashMap::<Vec<u8>, Function>::new(),
-            use_radians: true,
+            native_functions: HashMap::<Vec<u8>, NativeFunction>::new(),
+            providers: Vec::new(),
+            angle_unit: AngleUnit::Radians,
+            recursion_limit: RECURSION_LIMIT,
+            number_mode: NumberMode::Float,
+            rational_display: RationalDisplay::Fraction,
+            complex_display: ComplexDisplay::Rectangular,
+            number_format: NumberFormat::Auto,
+            digit_grouping: false,
+            precision: PRECISION,
+            rounding_mode: RoundingMode::HalfEven,
+            word_type: WordType::new(64, true).unwrap(),
+            overflow: Overflow::Wrap,
+            radix: Radix::Decimal,
+        }
+    }
+
+    /// Deletes every variable, function and previous answer and restores the default settings.
+    /// Registered rust functions and variable providers are kept.
+    pub fn reset(&mut self) {
+        let native_functions = std::mem::take(&mut self.native_functions);
+        let providers = std::mem::take(&mut self.providers);
+        // a script can reset the calculator, and is still running afterwards
+        *self = Calculator {
+            native_functions,
+            providers,
+            loading_session: self.loading_session,
+            script_depth: self.script_depth,
+            ..Calculator::new()
         };
-        calc_engine.prev_answers.push(Number(0.0));
-        calc_engine
-            .variables
-            .insert("pi".as_bytes().to_vec(), Number(PI));
-        calc_engine
-            .variables
-            .insert("e".as_bytes().to_vec(), Number(E));
-        calc_engine
+    }
+
+    /// The value of a variable, this includes the answer history (`ans`, `ans1`, `ans2`...).
+    pub fn variable(&self, name: &str) -> Option<Value> {
+        self.get_variable(name.as_bytes())
+    }
+
+    /// Creates or reassigns a variable. Constants and the answers (`ans`, `ans1`, `ans2`...) cannot
+    /// be assigned.
+    pub fn set_variable<V>(&mut self, name: &str, value: V) -> Result<(), CalcError>
+    where
+        V: Into<Value>,
+    {
+        let var_name = self.variable_name(name)?;
+        self.check_assignable(&var_name, false)?;
+        self.variables.insert(var_name, Number(value.into()));
+        Ok(())
+    }
+
+    /// Declares a constant, a variable that cannot be reassigned, like `const name = value`.
+    ///
+    /// A variable of the same name becomes the constant. A name that is already a constant, such
+    /// as `pi` or one declared before, cannot be declared again.
+    pub fn set_constant<V>(&mut self, name: &str, value: V) -> Result<(), CalcError>
+    where
+        V: Into<Value>,
+    {
+        let var_name = self.variable_name(name)?;
+        self.check_assignable(&var_name, true)?;
+        self.variables.insert(var_name.clone(), Number(value.into()));
+        self.constants.insert(var_name);
+        Ok(())
+    }
+
+    /// Whether a name is a builtin constant such as `pi`, or was declared with `const`.
+    pub fn is_constant(&self, name: &str) -> bool {
+        get_constant(name.as_bytes()).is_some() || self.constants.contains(name.as_bytes())
+    }
+
+    /// Removes a variable, returns false if it did not exist or is a constant.
+    pub fn remove_variable(&mut self, name: &str) -> bool {
+        !self.constants.contains(name.as_bytes()) && self.variables.remove(name.as_bytes()).is_some()
+    }
+
+    /// Every variable and its value, sorted by name. This includes the constants.
+    pub fn variables(&self) -> Vec<(String, Value)> {
+        let mut variables: Vec<(String, Value)> = self
             .variables
-            .insert("tau".as_bytes().to_vec(), Number(TAU));
+            .iter()
+            .filter_map(|(name, value)| match value {
+                Number(n) => Some((String::from_utf8_lossy(name).into_owned(), n.clone())),
+                _ => None,
+            })
+            .collect();
+        for constant in CONSTANTS {
+            if !self.variables.contains_key(constant.name.as_bytes()) {
+                variables.push((constant.name.to_string(), (constant.value)(self)));
+            }
+        }
+        variables.sort_by(|a, b| a.0.cmp(&b.0));
+        variables
+    }
+
+    /// Adds a source of variables that are not stored in the calculator.
+    ///
+    /// Names are looked up in the local variables first, then the answer history, then each
+    /// provider in the order they were added.
+    pub fn add_variable_provider<P>(&mut self, provider: P)
+    where
+        P: VariableProvider + 'static,
+    {
+        self.providers.push(Box::new(provider));
+    }
+
+    /// Creates or replaces a function from its definition, eg. `f(x, y) = x^2 + y`.
+    ///
+    /// Variables in the body are looked up each time the function is called, `$name` uses the
+    /// value `name` has when the function is defined instead.
+    pub fn define_function(&mut self, definition: &str) -> Result<(), CalcError> {
+        let tokens = tokenize(definition.as_bytes())?;
+        validate_token_list(&tokens)?;
+        match tokens.iter().position(|t| t.token == Assignment) {
+            // a function lhs is at least a name and brackets
+            Some(i) if i > 1 => self.assign(definition, &tokens[..i], &tokens[i + 1..]).map(|_| ()),
+            _ => Err(CalcError::new(ErrorKind::FuncNoName, tokens[0].span)),
+        }
+    }
+
+    /// Removes a user defined function, returns false if it did not exist.
+    pub fn remove_function(&mut self, name: &str) -> bool {
+        self.functions.remove(name.as_bytes()).is_some()
+    }
+
+    /// The names of the user defined functions and how many args each takes, sorted by name.
+    pub fn functions(&self) -> Vec<(String, usize)> {
+        let mut functions: Vec<(String, usize)> = self
+            .functions
+            .values()
+            .map(|f| (String::from_utf8_lossy(&f.name).into_owned(), f.arg_count))
+            .collect();
+        functions.sort();
+        functions
+    }
+
+    /// The definition of a user defined function, eg. `f(x) = x^2`, with the values of any `$name`
+    /// written in.
+    pub fn function_source(&self, name: &str) -> Option<String> {
+        self.functions.get(name.as_bytes()).map(|f| f.source.clone())
+    }
+
+    /// The variables a user defined function reads when it is called, sorted by name.
+    ///
+    /// Angle unit suffixes and values captured with `$name` are not included.
+    pub fn function_variables(&self, name: &str) -> Option<Vec<String>> {
+        let function = self.functions.get(name.as_bytes())?;
+        let mut names: Vec<String> = function
+            .func
+            .variables()
+            .iter()
+            .filter(|name| AngleUnit::from_name(name).is_none())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect();
+        names.sort();
+        Some(names)
+    }
+
+    /// Makes a rust closure callable from expressions as `name(args...)`.
+    ///
+    /// The closure is only called with an arg count that `arity` accepts, an `Err` it returns is
+    /// reported as [`ErrorKind::FuncFailed`]. Registering a name again replaces the previous
+    /// closure or user defined function, builtin names cannot be registered.
+    pub fn register_fn<F>(&mut self, name: &str, arity: Arity, func: F) -> Result<(), CalcError>
+    where
+        F: Fn(&[f64]) -> Result<f64, String> + 'static,
+    {
+        let tokens = tokenize(name.as_bytes())?;
+        match &tokens[..] {
+            [SpannedToken { token: Variable(fn_name), .. }] if fn_name == name.as_bytes() => (),
+            _ => return Err(ErrorKind::InvalidName(name.to_string()).into()),
+        }
+        if is_reserved_name(name.as_bytes()) {
+            return Err(ErrorKind::FuncHardcodedReassignAttempt(name.to_string()).into());
+        }
+        self.functions.remove(name.as_bytes());
+        self.native_functions.insert(
+            name.as_bytes().to_vec(),
+            NativeFunction {
+                arity,
+                func: Box::new(func),
+            },
+        );
+        Ok(())
+    }
+
+    /// Removes a function registered with [`Calculator::register_fn`], returns false if it did not exist.
+    pub fn unregister_fn(&mut self, name: &str) -> bool {
+        self.native_functions.remove(name.as_bytes()).is_some()
+    }
+
+    /// The names of the registered rust functions and their arities, sorted by name.
+    pub fn registered_functions(&self) -> Vec<(String, Arity)> {
+        let mut functions: Vec<(String, Arity)> = self
+            .native_functions
+            .iter()
+            .map(|(name, native)| (String::from_utf8_lossy(name).into_owned(), native.arity))
+            .collect();
+        functions.sort_by(|a, b| a.0.cmp(&b.0));
+        functions
+    }
 
-        calc_engine
+    /// How many args a builtin, registered or user defined function accepts.
+    pub fn function_arity(&self, name: &str) -> Option<Arity> {
+        let name = name.as_bytes();
+        if let Some(builtin) = get_builtin(name) {
+            return Some(builtin.arity);
+        }
+        if name == IF_NAME {
+            return Some(IF_ARITY);
+        }
+        if let Some(native) = self.native_functions.get(name) {
+            return Some(native.arity);
+        }
+        self.functions.get(name).map(|f| f.arity())
+    }
+
+    /// The unit trig functions take and inverse trig functions return.
+    pub fn angle_unit(&self) -> AngleUnit {
+        self.angle_unit
+    }
+
+    pub fn set_angle_unit(&mut self, unit: AngleUnit) {
+        self.angle_unit = unit;
+    }
+
+    /// How deeply user defined functions can call each other, including calling themselves.
+    pub fn recursion_limit(&self) -> usize {
+        self.recursion_limit
+    }
+
+    pub fn set_recursion_limit(&mut self, limit: usize) {
+        self.recursion_limit = limit;
+    }
+
+    pub fn number_mode(&self) -> NumberMode {
+        self.number_mode
+    }
+
+    /// Switches between floating point numbers, exact fractions and big decimals. Values that
+    /// are already stored are kept as they are, but are converted to the mode when used.
+    pub fn set_number_mode(&mut self, mode: NumberMode) {
+        self.number_mode = mode;
+    }
+
+    /// How many significant digits results are rounded to in decimal mode.
+    pub fn precision(&self) -> usize {
+        self.precision
+    }
+
+    /// Sets the significant digits kept in decimal mode, this is at least 1 and at most 1000.
+    pub fn set_precision(&mut self, digits: usize) {
+        self.precision = digits.clamp(1, MAX_PRECISION);
+    }
+
+    pub fn rounding_mode(&self) -> RoundingMode {
+        self.rounding_mode
+    }
+
+    pub fn set_rounding_mode(&mut self, mode: RoundingMode) {
+        self.rounding_mode = mode;
+    }
+
+    pub fn rational_display(&self) -> RationalDisplay {
+        self.rational_display
+    }
+
+    pub fn set_rational_display(&mut self, display: RationalDisplay) {
+        self.rational_display = display;
+    }
+
+    /// The integers programmer mode works with, this is also the word that `popcount`, `clz`,
+    /// `ctz`, `rotl` and `rotr` look at in every mode.
+    pub fn word_type(&self) -> WordType {
+        self.word_type
+    }
+
+    pub fn set_word_type(&mut self, word_type: WordType) {
+        self.word_type = word_type;
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.overflow = overflow;
+    }
+
+    /// The base integers are shown in, in programmer mode.
+    pub fn radix(&self) -> Radix {
+        self.radix
+    }
+
+    pub fn set_radix(&mut self, radix: Radix) {
+        self.radix = radix;
+    }
+
+    pub fn complex_display(&self) -> ComplexDisplay {
+        self.complex_display
+    }
+
+    pub fn set_complex_display(&mut self, display: ComplexDisplay) {
+        self.complex_display = display;
+    }
+
+    /// How answers and variables are displayed, programmer mode shows integers in its radix instead.
+    pub fn number_format(&self) -> NumberFormat {
+        self.number_format
+    }
+
+    pub fn set_number_format(&mut self, format: NumberFormat) {
+        self.number_format = format;
+    }
+
+    /// Whether the whole part of numbers is grouped in thousands, eg. `1,234,567.5`.
+    pub fn digit_grouping(&self) -> bool {
+        self.digit_grouping
+    }
+
+    pub fn set_digit_grouping(&mut self, grouping: bool) {
+        self.digit_grouping = grouping;
+    }
+
+    /// Formats a value using the calculators display settings.
+    pub fn format(&self, value: &Value) -> String {
+        // only programmer mode needs the integer, which can take a long time to find for a huge decimal
+        if self.number_mode == NumberMode::Programmer {
+            if let Some(n) = value.to_integer() {
+                return self.word_type.format(&n, self.radix);
+            }
+        }
+        let options = FormatOptions {
+            number_format: self.number_format,
+            grouping: self.digit_grouping,
+            rational_display: self.rational_display,
+            complex_display: self.complex_display,
+            angle_unit: self.angle_unit,
+        };
+        format_value(value, &options)
+    }
+
+    /// Previous answers, newest first.
+    pub fn history(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.history.iter()
+    }
+
+    /// How many previous answers are kept, 100 by default.
+    pub fn history_size(&self) -> usize {
+        self.history.capacity()
+    }
+
+    pub fn set_history_size(&mut self, size: usize) {
+        self.history.set_capacity(size);
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    // looks a variable up by name, this includes the answer history (ans, ans1, ans2...)
+    pub(crate) fn get_variable(&self, name: &[u8]) -> Option<Value> {
+        if let Some(Number(n)) = self.variables.get(name) {
+            return Some(n.clone());
+        }
+        if let Some(constant) = get_constant(name) {
+            return Some((constant.value)(self));
+        }
+        if let Some(entry) = answer_index(name).and_then(|index| self.history.get(index)) {
+            return Some(entry.value.clone());
+        }
+        let name = std::str::from_utf8(name).ok()?;
+        self.providers.iter().find_map(|provider| provider.get(name)).map(Value::Float)
+    }
+
+    // how a stored value, literal or result is used in the current number mode
+    // floats stay floats in every mode, they are what exact and decimal mode fall back to
+    pub(crate) fn normalize(&self, value: Value) -> Value {
+        match (self.number_mode, value) {
+            (_, Value::Float(x)) => Value::Float(x),
+            // eg. i * i is -1
+            (_, Value::Complex(z)) if z.im == 0.0 => Value::Float(z.re),
+            (_, Value::Complex(z)) => Value::Complex(z),
+            (NumberMode::Float, value) => Value::Float(value.to_f64()),
+            (NumberMode::Exact | NumberMode::Programmer, Value::Decimal(d)) => Value::Rational(d.to_rational()),
+            (NumberMode::Decimal, Value::Rational(r)) => Value::Decimal(Decimal::from_rational(&r, self.decimal_context())),
+            (NumberMode::Decimal, Value::Decimal(d)) => Value::Decimal(d.round(self.decimal_context())),
+            (_, value) => value,
+        }
+    }
+
+    pub(crate) fn decimal_context(&self) -> DecimalContext {
+        DecimalContext {
+            digits: self.precision,
+            rounding: self.rounding_mode,
+        }
+    }
+
+    // a variable is read only if any provider says so
+    fn is_read_only_variable(&self, name: &[u8]) -> bool {
+        match std::str::from_utf8(name) {
+            Ok(name) => self.providers.iter().any(|provider| provider.is_read_only(name)),
+            Err(_) => false,
+        }
+    }
+
+    // the name as a variable token would have it, or an error if it is not a valid variable name
+    fn variable_name(&self, name: &str) -> Result<Vec<u8>, CalcError> {
+        let tokens = tokenize(name.as_bytes())?;
+        match &tokens[..] {
+            [SpannedToken { token: Variable(var_name), .. }] if var_name == name.as_bytes() => Ok(var_name.clone()),
+            _ => Err(ErrorKind::InvalidName(name.to_string()).into()),
+        }
+    }
+
+    // whether a variable can be given a new value, only a session being loaded can declare a user
+    // constant again
+    fn check_assignable(&self, name: &[u8], declaring: bool) -> Result<(), ErrorKind> {
+        if name == CONST_KEYWORD {
+            return Err(ErrorKind::InvalidName(String::from_utf8_lossy(name).into_owned()));
+        }
+        // the command would run instead of the variable being read
+        if is_command_name(name) {
+            return Err(ErrorKind::CommandNameReassignAttempt(String::from_utf8_lossy(name).into_owned()));
+        }
+        // a variable would hide the answer history for the rest of the session
+        if answer_index(name).is_some() {
+            return Err(ErrorKind::VarReadOnly(String::from_utf8_lossy(name).into_owned()));
+        }
+        let redeclaring = declaring && self.loading_session;
+        if get_constant(name).is_some() || (!redeclaring && self.constants.contains(name)) {
+            return Err(ErrorKind::ConstReassignAttempt(String::from_utf8_lossy(name).into_owned()));
+        }
+        if self.is_read_only_variable(name) {
+            return Err(ErrorKind::VarReadOnly(String::from_utf8_lossy(name).into_owned()));
+        }
+        Ok(())
+    }
+
+    // handles inputs that are commands rather than expressions, returns None if input is not a command
+    pub(crate) fn run_command(&mut self, input: &str) -> Option<CommandResult> {
+        let words: Vec<&str> = input.split_whitespace().collect();
+        let command = get_command(words.first()?)?;
+        // `mode = 2` or `history + 1` are left to fail as expressions, which say why, but
+        // `save /tmp/work.calc` or `save ~/work.calc` is a path rather than a division or a not
+        let is_operator = |word: &str| word == "/" || word.starts_with(|c: char| "=+-*%^&|<>!~,()".contains(c));
+        if words.get(1).is_some_and(|word| is_operator(word) && !word.starts_with("~/")) {
+            return None;
+        }
+        let result = (command.run)(self, &words[1..]);
+        Some(result.unwrap_or_else(|| Err(ErrorKind::CommandUsage(command.usage()).into())))
     }
 }
 
+// commands are looked up before variables, so their names cannot be used for anything else
+fn is_command_name(name: &[u8]) -> bool {
+    std::str::from_utf8(name).ok().and_then(get_command).is_some()
+}
+
+/// What a successful [`Calc::eval`] produced.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum EvalResult<A, F> {
+    /// The value of an expression.
     Answer(A),
+    /// A message confirming an assignment or command.
     Feedback(F),
 }
 
 pub trait Calc {
-    fn eval(&mut self, input: &str) -> Result<EvalResult<f64, String>, CalcError>;
+    /// Solves an expression, assigns a variable or function, or runs a command.
+    fn eval(&mut self, input: &str) -> Result<EvalResult<Value, String>, CalcError>;
 }
 
 impl Calc for Calculator {
-    fn eval(&mut self, input: &str) -> Result<EvalResult<f64, String>, CalcError> {
-        // remove whitespace from input
-        let trimmed_input: Vec<u8> = input.chars().filter(|c| !c.is_whitespace()).collect::<String>().bytes().collect();
+    fn eval(&mut self, input: &str) -> Result<EvalResult<Value, String>, CalcError> {
+        if let Some(result) = self.run_command(input) {
+            return result;
+        }
 
         // turn string input into a list of tokens
-        let mut tokens = tokenize(&trimmed_input)?;
+        let tokens = tokenize(input.as_bytes())?;
+
+        // `const name = value`, the rest is an ordinary variable assignment
+        if let [SpannedToken { token: Variable(keyword), span }, rest @ ..] = &tokens[..] {
+            if keyword == CONST_KEYWORD && matches!(rest.first(), Some(SpannedToken { token: Variable(_), .. })) {
+                validate_token_list(rest)?;
+                return match rest {
+                    [SpannedToken { token: Variable(name), span: name_span }, SpannedToken { token: Assignment, .. }, rhs @ ..] => {
+                        self.assign_variable(name, *name_span, rhs, true)
+                    }
+                    _ => Err(CalcError::new(ErrorKind::ConstDeclaration, *span)),
+                };
+            }
+        }
 
         // make sure token list is a valid equation or assignment
         validate_token_list(&tokens)?;
 
         // if creating/reassigning a variable/function (expression contains a '=')
-        if tokens.contains(&Assignment) {
+        if let Some(i) = tokens.iter().position(|t| t.token == Assignment) {
             // split the expression into the parts before and after the '='
-            let parts: Vec<&[Token]> = tokens.split(|t| t == &Assignment).collect();
-            let mut lhs = parts[0].to_vec();
-            let mut rhs = parts[1].to_vec();
-
-            // check if lhs is a variable
-            if lhs.len() == 1 {
-                if let Variable(name) = &lhs[0] {
-                    // lhs is a variable, assign value to new variable
-                    // resolve variables on the rhs
-                    self.resolve_variables(&mut rhs)?;
-                    let value;
-                    match self.solve(rhs, 0) {
-                        Ok(answer) => value = answer[0].clone(),
-                        Err(e) => return Err(e),
-                    }
+            self.assign(input, &tokens[..i], &tokens[i + 1..])
+        // otherwise just solve it
+        } else {
+            let answer = self.solve(&parse(&tokens)?, &[])?;
 
-                    self.variables.insert(name.clone(), value);
-                    return Ok(EvalResult::Feedback("assigned value to variable".to_string()));
-                } else {
-                    return Err(CalcError::LhsMustBeVarOrFunc);
-                }
+            self.history.push(input, answer.clone());
+            Ok(EvalResult::Answer(answer))
+        }
+    }
+}
+
+impl Calculator {
+    // creates or reassigns the variable or function on the lhs of a '='
+    fn assign(&mut self, input: &str, lhs: &[SpannedToken], rhs: &[SpannedToken]) -> Result<EvalResult<Value, String>, CalcError> {
+        let mut lhs = lhs.to_vec();
+        let mut rhs = rhs.to_vec();
+
+        // check if lhs is a variable
+        if lhs.len() == 1 {
+            if let Variable(name) = &lhs[0].token {
+                self.assign_variable(name, lhs[0].span, &rhs, false)
             } else {
-                // make sure lhs is valid function 
-                is_valid_lhs_function(&lhs)?;
-                // lhs is a function, assign value to new function
-                match self.create_function(&mut lhs, &mut rhs) {
-                    Ok(func) => {
-                        let func_name = func.name.clone();
-                        self.functions.insert(func_name, func);
-                        return Ok(EvalResult::Feedback("created function".to_string()));
-                    }
-                    Err(e) => {
-                        return Err(e);
-                    }
+                Err(CalcError::new(ErrorKind::LhsMustBeVarOrFunc, lhs[0].span))
+            }
+        } else {
+            // make sure lhs is valid function 
+            if let FunctionName(name) = &lhs[0].token {
+                if is_command_name(name) {
+                    return Err(CalcError::new(ErrorKind::CommandNameReassignAttempt(String::from_utf8_lossy(name).into_owned()), lhs[0].span));
                 }
             }
-        // otherwise just solve it
+            is_valid_lhs_function(&lhs, |name| {
+                is_reserved_name(name) || name == CONST_KEYWORD || self.native_functions.contains_key(name)
+            })?;
+            // lhs is a function, assign value to new function
+            let func = self.create_function(input, &mut lhs, &mut rhs)?;
+            self.functions.insert(func.name.clone(), func);
+            Ok(EvalResult::Feedback("created function".to_string()))
+        }
+    }
+
+    // solves the rhs and stores it in a variable, or declares a constant
+    fn assign_variable(&mut self, name: &[u8], span: Span, rhs: &[SpannedToken], constant: bool) -> Result<EvalResult<Value, String>, CalcError> {
+        self.check_assignable(name, constant).map_err(|kind| CalcError::new(kind, span))?;
+        let value = self.solve(&parse(rhs)?, &[])?;
+
+        self.variables.insert(name.to_vec(), Number(value));
+        if constant {
+            self.constants.insert(name.to_vec());
+            Ok(EvalResult::Feedback("declared constant".to_string()))
         } else {
-            // resolve variables on the right side
-            self.resolve_variables(&mut tokens)?;
+            Ok(EvalResult::Feedback("assigned value to variable".to_string()))
+        }
+    }
+}
 
-            let answer;
-            match self.solve(tokens, 0) {
-                Ok(a) => answer = a[0].clone(),
-                Err(e) => return Err(e),
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathengine::testing::{error, eval};
 
-            if let Number(n) = answer {
-                self.prev_answers[0] = Number(n);
-                self.variables
-                    .insert(b"ans".to_vec(), self.prev_answers[0].clone());
-                return Ok(EvalResult::Answer(n));
-            } else {
-                return Err(CalcError::FailedToSolveEquation);
-            }
+    #[test]
+    fn eval_answers_or_gives_feedback() {
+        let mut calc = Calculator::new();
+        assert!(matches!(calc.eval("2 + 2"), Ok(EvalResult::Answer(Value::Float(x))) if x == 4.0));
+        assert!(matches!(calc.eval("x = 3"), Ok(EvalResult::Feedback(_))));
+        assert!(matches!(calc.eval("f(a) = a * x"), Ok(EvalResult::Feedback(_))));
+        assert!(matches!(calc.eval("deg"), Ok(EvalResult::Feedback(_))));
+        let e = calc.eval("1 +").unwrap_err();
+        assert!(matches!(e.kind, ErrorKind::InvalidLastToken(_)));
+        assert!(matches!(error(&mut calc, ""), ErrorKind::NoInput));
+    }
+
+    #[test]
+    fn variables_through_the_api() {
+        let mut calc = Calculator::new();
+        calc.set_variable("x", 2.5).unwrap();
+        calc.set_variable("n", Value::from(7)).unwrap();
+        assert_eq!(calc.variable("x"), Some(Value::Float(2.5)));
+        assert_eq!(eval(&mut calc, "x * 2"), Value::Float(5.0));
+        let names: Vec<String> = calc.variables().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["e", "i", "j", "n", "pi", "tau", "x"]);
+        for name in ["2x", "x y", "", "x+1"] {
+            assert!(matches!(calc.set_variable(name, 1.0).unwrap_err().kind, ErrorKind::InvalidName(_)), "{name}");
+        }
+        assert!(calc.remove_variable("x"));
+        assert!(!calc.remove_variable("x"));
+        assert_eq!(calc.variable("x"), None);
+    }
+
+    #[test]
+    fn functions_through_the_api() {
+        let mut calc = Calculator::new();
+        calc.define_function("f(x, y) = x^2 + y * k").unwrap();
+        calc.set_variable("k", 10.0).unwrap();
+        assert_eq!(eval(&mut calc, "f(3, 1)"), Value::Float(19.0));
+        assert_eq!(calc.functions(), [("f".to_string(), 2)]);
+        assert_eq!(calc.function_source("f").as_deref(), Some("f(x, y) = x^2 + y * k"));
+        assert_eq!(calc.function_variables("f"), Some(vec!["k".to_string()]));
+        assert!(matches!(calc.define_function("x = 2").unwrap_err().kind, ErrorKind::FuncNoName));
+        assert!(matches!(calc.define_function("sqrt(x) = x").unwrap_err().kind, ErrorKind::FuncHardcodedReassignAttempt(_)));
+        assert!(calc.remove_function("f"));
+        assert_eq!(calc.function_source("f"), None);
+        assert!(matches!(error(&mut calc, "f(3, 1)"), ErrorKind::FuncDoesNotExist(_)));
+    }
+
+    #[test]
+    fn registered_closures_are_callable() {
+        let mut calc = Calculator::new();
+        calc.register_fn("lerp", Arity::Exact(3), |a| Ok(a[0] + (a[1] - a[0]) * a[2])).unwrap();
+        calc.register_fn("total", Arity::AtLeast(0), |a| Ok(a.iter().sum())).unwrap();
+        assert_eq!(eval(&mut calc, "lerp(10, 20, 0.25)"), Value::Float(12.5));
+        assert_eq!(eval(&mut calc, "total()"), Value::Float(0.0));
+        assert_eq!(eval(&mut calc, "total(1, 2, 3, 4)"), Value::Float(10.0));
+        assert_eq!(calc.function_arity("lerp"), Some(Arity::Exact(3)));
+        assert_eq!(
+            calc.registered_functions(),
+            [("lerp".to_string(), Arity::Exact(3)), ("total".to_string(), Arity::AtLeast(0))]
+        );
+        assert!(matches!(error(&mut calc, "lerp(1, 2)"), ErrorKind::FuncIncorrectArgCount(_, Arity::Exact(3), 2)));
+        assert!(matches!(error(&mut calc, "total(i)"), ErrorKind::FuncComplexArg(_)));
+    }
+
+    #[test]
+    fn registered_closures_can_fail() {
+        let mut calc = Calculator::new();
+        let sensor = |a: &[f64]| if a[0] < 4.0 { Ok(a[0] * 1.5) } else { Err("no such sensor".to_string()) };
+        calc.register_fn("sensor", Arity::Exact(1), sensor).unwrap();
+        assert_eq!(eval(&mut calc, "sensor(2)"), Value::Float(3.0));
+        match error(&mut calc, "sensor(7)") {
+            ErrorKind::FuncFailed(name, message) => assert_eq!((&name[..], &message[..]), ("sensor", "no such sensor")),
+            kind => panic!("{kind:?}"),
         }
     }
+
+    #[test]
+    fn registered_names_are_reserved() {
+        let mut calc = Calculator::new();
+        let kind = |result: Result<(), CalcError>| result.unwrap_err().kind;
+        assert!(matches!(kind(calc.register_fn("sin", Arity::Exact(1), |a| Ok(a[0]))), ErrorKind::FuncHardcodedReassignAttempt(_)));
+        assert!(matches!(kind(calc.register_fn("if", Arity::Exact(3), |a| Ok(a[0]))), ErrorKind::FuncHardcodedReassignAttempt(_)));
+        assert!(matches!(kind(calc.register_fn("2f", Arity::Exact(1), |a| Ok(a[0]))), ErrorKind::InvalidName(_)));
+        // registering replaces a user defined function, which cannot then be defined over it
+        calc.eval("twice(x) = 2x").unwrap();
+        calc.register_fn("twice", Arity::Exact(1), |a| Ok(a[0] * 2.0)).unwrap();
+        assert!(calc.function_source("twice").is_none());
+        assert!(matches!(error(&mut calc, "twice(x) = 3x"), ErrorKind::FuncHardcodedReassignAttempt(_)));
+        assert!(calc.unregister_fn("twice"));
+        assert!(matches!(error(&mut calc, "twice(1)"), ErrorKind::FuncDoesNotExist(_)));
+    }
+
+    #[test]
+    fn builtin_constants_cannot_be_reassigned() {
+        let mut calc = Calculator::new();
+        assert!(matches!(error(&mut calc, "pi = 3"), ErrorKind::ConstReassignAttempt(_)));
+        assert!(matches!(error(&mut calc, "const e = 3"), ErrorKind::ConstReassignAttempt(_)));
+        assert!(calc.set_variable("tau", 1.0).is_err());
+        assert!(calc.set_constant("i", 1.0).is_err());
+        assert_eq!(calc.variable("pi"), Some(Value::Float(std::f64::consts::PI)));
+    }
+
+    #[test]
+    fn user_constants_cannot_be_reassigned() {
+        let mut calc = Calculator::new();
+        calc.eval("const g = 9.80665").unwrap();
+        assert!(calc.is_constant("g"));
+        assert!(matches!(error(&mut calc, "g = 1"), ErrorKind::ConstReassignAttempt(_)));
+        assert!(matches!(error(&mut calc, "const g = 2"), ErrorKind::ConstReassignAttempt(_)));
+        assert!(calc.set_variable("g", 1.0).is_err());
+        assert!(calc.set_constant("g", 1.0).is_err());
+        assert!(!calc.remove_variable("g"));
+        assert_eq!(calc.variable("g"), Some(Value::Float(9.80665)));
+
+        // ordinary variables stay mutable, and can become constants
+        calc.eval("x = 1").unwrap();
+        calc.eval("x = 2").unwrap();
+        calc.eval("const x = 3").unwrap();
+        assert!(matches!(error(&mut calc, "x = 4"), ErrorKind::ConstReassignAttempt(_)));
+    }
+
+    #[test]
+    fn const_is_reserved() {
+        let mut calc = Calculator::new();
+        assert!(matches!(error(&mut calc, "const = 2"), ErrorKind::InvalidName(_)));
+        assert!(matches!(error(&mut calc, "const const = 2"), ErrorKind::InvalidName(_)));
+        assert!(matches!(error(&mut calc, "const(x) = x"), ErrorKind::FuncHardcodedReassignAttempt(_)));
+        assert!(matches!(error(&mut calc, "const x"), ErrorKind::ConstDeclaration));
+        assert!(calc.set_variable("const", 1.0).is_err());
+    }
+
+    #[test]
+    fn constants_are_used_like_variables() {
+        let mut calc = Calculator::new();
+        calc.eval("const g = 9.8").unwrap();
+        calc.eval("weight(m) = m * g").unwrap();
+        assert_eq!(eval(&mut calc, "weight(2)"), Value::Float(19.6));
+        calc.eval("const half_g = g / 2").unwrap();
+        assert_eq!(calc.variable("half_g"), Some(Value::Float(4.9)));
+        // a declaration that fails declares nothing
+        assert!(matches!(error(&mut calc, "const k = nope"), ErrorKind::VarDoesNotExist(_)));
+        assert!(!calc.is_constant("k"));
+        calc.eval("k = 1").unwrap();
+    }
+
+    #[test]
+    fn reset_deletes_user_constants() {
+        let mut calc = Calculator::new();
+        calc.eval("const g = 2").unwrap();
+        calc.reset();
+        assert!(!calc.is_constant("g"));
+        calc.eval("g = 3").unwrap();
+    }
 }
@@ -0,0 +1,804 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+use super::token::MAX_LITERAL_EXPONENT;
+
+/// A decimal floating point number, `mantissa * 10^exponent`.
+///
+/// Results are rounded to a number of significant digits chosen by the calculator, see
+/// [`Calculator::set_precision`](crate::Calculator::set_precision).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Decimal {
+    mantissa: BigInt,
+    exponent: i64,
+}
+
+/// How a decimal result is rounded to the precision.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RoundingMode {
+    /// To the nearest, ties go to the even neighbour. Also known as bankers rounding.
+    HalfEven,
+    /// To the nearest, ties go away from zero.
+    HalfUp,
+    /// To the nearest, ties go towards zero.
+    HalfDown,
+    /// Away from zero.
+    Up,
+    /// Towards zero, ie. truncation.
+    Down,
+    /// Towards positive infinity.
+    Ceiling,
+    /// Towards negative infinity.
+    Floor,
+}
+
+// the precision and rounding mode that decimal operations round their results to
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DecimalContext {
+    pub(crate) digits: usize,
+    pub(crate) rounding: RoundingMode,
+}
+
+impl DecimalContext {
+    // the context used for intermediate results, extra digits keep rounding errors out of the answer
+    pub(crate) fn working(self, guard_digits: usize) -> DecimalContext {
+        DecimalContext {
+            digits: self.digits + GUARD_DIGITS + guard_digits,
+            rounding: RoundingMode::HalfEven,
+        }
+    }
+}
+
+const GUARD_DIGITS: usize = 10;
+
+// arguments this far from 1 are too large or too small to work with, these fall back to floats
+const MAX_ARGUMENT_EXPONENT: i64 = 10_000;
+
+impl RoundingMode {
+    pub fn from_name(name: &str) -> Option<RoundingMode> {
+        match name {
+            "half-even" => Some(RoundingMode::HalfEven),
+            "half-up" => Some(RoundingMode::HalfUp),
+            "half-down" => Some(RoundingMode::HalfDown),
+            "up" => Some(RoundingMode::Up),
+            "down" => Some(RoundingMode::Down),
+            "ceiling" => Some(RoundingMode::Ceiling),
+            "floor" => Some(RoundingMode::Floor),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RoundingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoundingMode::HalfEven => write!(f, "half-even"),
+            RoundingMode::HalfUp => write!(f, "half-up"),
+            RoundingMode::HalfDown => write!(f, "half-down"),
+            RoundingMode::Up => write!(f, "up"),
+            RoundingMode::Down => write!(f, "down"),
+            RoundingMode::Ceiling => write!(f, "ceiling"),
+            RoundingMode::Floor => write!(f, "floor"),
+        }
+    }
+}
+
+fn pow10(n: u64) -> BigInt {
+    BigInt::from(10).pow(n as u32)
+}
+
+fn digit_count(n: &BigInt) -> i64 {
+    n.magnitude().to_str_radix(10).len() as i64
+}
+
+// rounds mantissa * 10^exponent to ctx.digits significant digits
+// sticky says that the true value is a little further from zero than mantissa, by less than one
+// unit in its last digit
+fn round_digits(mantissa: BigInt, exponent: i64, sticky: bool, ctx: DecimalContext) -> Decimal {
+    if mantissa.is_zero() && !sticky {
+        return Decimal::zero();
+    }
+    let (mut mantissa, mut exponent) = (mantissa, exponent);
+    let mut extra = digit_count(&mantissa) - ctx.digits as i64;
+    if extra <= 0 {
+        if !sticky {
+            return Decimal::new(mantissa, exponent);
+        }
+        // make room for the sticky part below the last kept digit
+        mantissa *= 10;
+        exponent -= 1;
+        extra = 1;
+    }
+
+    let negative = mantissa.is_negative();
+    let unit = pow10(extra as u64);
+    let (kept, dropped) = mantissa.abs().div_rem(&unit);
+    let half = &unit / 2;
+    let nonzero = sticky || !dropped.is_zero();
+    let tie = dropped == half && !sticky;
+    let above_half = dropped > half || (dropped == half && sticky);
+    let round_up = match ctx.rounding {
+        RoundingMode::Down => false,
+        RoundingMode::Up => nonzero,
+        RoundingMode::Ceiling => nonzero && !negative,
+        RoundingMode::Floor => nonzero && negative,
+        RoundingMode::HalfUp => above_half || tie,
+        RoundingMode::HalfDown => above_half,
+        RoundingMode::HalfEven => above_half || (tie && kept.is_odd()),
+    };
+    let mut kept = if round_up { kept + 1 } else { kept };
+    let mut exponent = exponent + extra;
+    // 999 rounding up to 1000 gains a digit
+    if digit_count(&kept) > ctx.digits as i64 {
+        kept /= 10;
+        exponent += 1;
+    }
+    Decimal::new(if negative { -kept } else { kept }, exponent)
+}
+
+// rounds numer / denom * 10^exponent to ctx.digits significant digits
+fn round_quotient(numer: &BigInt, denom: &BigInt, exponent: i64, ctx: DecimalContext) -> Decimal {
+    if numer.is_zero() {
+        return Decimal::zero();
+    }
+    // scale so the quotient has at least ctx.digits + 1 digits
+    let shift = ctx.digits as i64 + 2 - (digit_count(numer) - digit_count(denom));
+    let (numer, denom) = if shift >= 0 {
+        (numer * pow10(shift as u64), denom.clone())
+    } else {
+        (numer.clone(), denom * pow10(shift.unsigned_abs()))
+    };
+    let (quotient, remainder) = numer.div_rem(&denom);
+    let quotient = if denom.is_negative() { -quotient } else { quotient };
+    round_digits(quotient, exponent - shift, !remainder.is_zero(), ctx)
+}
+
+impl Decimal {
+    // trailing zeros are moved into the exponent, so equal numbers compare equal
+    fn new(mantissa: BigInt, exponent: i64) -> Decimal {
+        if mantissa.is_zero() {
+            return Decimal::zero();
+        }
+        let (mut mantissa, mut exponent) = (mantissa, exponent);
+        let ten = BigInt::from(10);
+        loop {
+            let (quotient, remainder) = mantissa.div_rem(&ten);
+            if !remainder.is_zero() {
+                break;
+            }
+            mantissa = quotient;
+            exponent += 1;
+        }
+        Decimal { mantissa, exponent }
+    }
+
+    pub(crate) fn zero() -> Decimal {
+        Decimal {
+            mantissa: BigInt::zero(),
+            exponent: 0,
+        }
+    }
+
+    pub(crate) fn one() -> Decimal {
+        Decimal::from_integer(BigInt::one())
+    }
+
+    pub(crate) fn from_integer(n: BigInt) -> Decimal {
+        Decimal::new(n, 0)
+    }
+
+    pub(crate) fn from_rational(r: &BigRational, ctx: DecimalContext) -> Decimal {
+        round_quotient(r.numer(), r.denom(), 0, ctx)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa.is_zero()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.mantissa.is_negative()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.exponent >= 0
+    }
+
+    // the mantissa and exponent, for formatting
+    pub(crate) fn parts(&self) -> (&BigInt, i64) {
+        (&self.mantissa, self.exponent)
+    }
+
+    // the exponent of the leading digit, eg. 2 for 123.4
+    fn magnitude(&self) -> i64 {
+        self.exponent + digit_count(&self.mantissa) - 1
+    }
+
+    pub(crate) fn round(&self, ctx: DecimalContext) -> Decimal {
+        round_digits(self.mantissa.clone(), self.exponent, false, ctx)
+    }
+
+    pub fn to_rational(&self) -> BigRational {
+        if self.exponent >= 0 {
+            BigRational::from_integer(&self.mantissa * pow10(self.exponent as u64))
+        } else {
+            BigRational::new(self.mantissa.clone(), pow10(self.exponent.unsigned_abs()))
+        }
+    }
+
+    pub fn to_integer(&self) -> Option<BigInt> {
+        self.is_integer().then(|| self.to_rational().to_integer())
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        format!("{}e{}", self.mantissa, self.exponent).parse().unwrap_or(f64::NAN)
+    }
+
+    pub(crate) fn neg(&self) -> Decimal {
+        Decimal {
+            mantissa: -&self.mantissa,
+            exponent: self.exponent,
+        }
+    }
+
+    pub(crate) fn abs(&self) -> Decimal {
+        Decimal {
+            mantissa: self.mantissa.abs(),
+            exponent: self.exponent,
+        }
+    }
+
+    pub(crate) fn signum(&self) -> Decimal {
+        Decimal::from_integer(self.mantissa.signum())
+    }
+
+    // the integer part rounded with a function on the rational value, eg. floor
+    fn to_whole(&self, f: fn(&BigRational) -> BigRational) -> Decimal {
+        if self.is_integer() {
+            return self.clone();
+        }
+        Decimal::from_integer(f(&self.to_rational()).to_integer())
+    }
+
+    pub(crate) fn floor(&self) -> Decimal {
+        self.to_whole(BigRational::floor)
+    }
+
+    pub(crate) fn ceil(&self) -> Decimal {
+        self.to_whole(BigRational::ceil)
+    }
+
+    // halfway cases round away from zero
+    pub(crate) fn round_whole(&self) -> Decimal {
+        self.to_whole(BigRational::round)
+    }
+
+    pub(crate) fn trunc(&self) -> Decimal {
+        self.to_whole(BigRational::trunc)
+    }
+
+    pub(crate) fn add(&self, other: &Decimal, ctx: DecimalContext) -> Decimal {
+        if self.is_zero() {
+            return other.round(ctx);
+        }
+        if other.is_zero() {
+            return self.round(ctx);
+        }
+        let (big, small) = if self.magnitude() >= other.magnitude() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        // a number far below the last digit of the other only matters for rounding, so it is
+        // replaced with a smaller stand in instead of lining up thousands of zeros
+        let lowest = big.exponent.min(big.magnitude() - ctx.digits as i64) - 2;
+        let small = if small.magnitude() < lowest {
+            Decimal {
+                mantissa: small.mantissa.signum(),
+                exponent: lowest,
+            }
+        } else {
+            small.clone()
+        };
+        let exponent = big.exponent.min(small.exponent);
+        let align = |d: &Decimal| &d.mantissa * pow10((d.exponent - exponent) as u64);
+        round_digits(align(big) + align(&small), exponent, false, ctx)
+    }
+
+    pub(crate) fn sub(&self, other: &Decimal, ctx: DecimalContext) -> Decimal {
+        self.add(&other.neg(), ctx)
+    }
+
+    pub(crate) fn mul(&self, other: &Decimal, ctx: DecimalContext) -> Decimal {
+        round_digits(&self.mantissa * &other.mantissa, self.exponent + other.exponent, false, ctx)
+    }
+
+    // None when dividing by zero
+    pub(crate) fn div(&self, other: &Decimal, ctx: DecimalContext) -> Option<Decimal> {
+        if other.is_zero() {
+            return None;
+        }
+        Some(round_quotient(&self.mantissa, &other.mantissa, self.exponent - other.exponent, ctx))
+    }
+
+    // the remainder of truncating division, with the sign of self like f64's %
+    pub(crate) fn rem(&self, other: &Decimal, ctx: DecimalContext) -> Option<Decimal> {
+        if other.is_zero() {
+            return None;
+        }
+        let remainder = self.to_rational() % other.to_rational();
+        Some(Decimal::from_rational(&remainder, ctx))
+    }
+
+    pub(crate) fn cmp(&self, other: &Decimal) -> Ordering {
+        let sign = |d: &Decimal| d.mantissa.signum();
+        if sign(self) != sign(other) || self.is_zero() {
+            return sign(self).cmp(&sign(other));
+        }
+        // both have the same sign, so the one with the larger leading digit is further from zero
+        let by_magnitude = self.magnitude().cmp(&other.magnitude());
+        let ordering = if by_magnitude != Ordering::Equal {
+            by_magnitude
+        } else {
+            self.abs().to_rational().cmp(&other.abs().to_rational())
+        };
+        if self.is_negative() {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+
+    fn is_too_large(&self) -> bool {
+        self.magnitude().abs() > MAX_ARGUMENT_EXPONENT
+    }
+
+    // x^n by repeated squaring
+    pub(crate) fn powi(&self, n: &BigInt, ctx: DecimalContext) -> Option<Decimal> {
+        let n_abs = n.abs().to_u64()?;
+        let bits = 64 - n_abs.leading_zeros() as usize;
+        let work = ctx.working(bits);
+        let mut result = Decimal::one();
+        let mut base = self.round(work);
+        let mut remaining = n_abs;
+        while remaining > 0 {
+            if remaining & 1 == 1 {
+                result = result.mul(&base, work);
+            }
+            base = base.mul(&base, work);
+            remaining >>= 1;
+            if result.magnitude().abs() > MAX_ARGUMENT_EXPONENT * 100 {
+                return None;
+            }
+        }
+        if n.is_negative() {
+            result = Decimal::one().div(&result, work)?;
+        }
+        Some(result.round(ctx))
+    }
+
+    // x^y for any y, only defined for negative x when y is an integer
+    pub(crate) fn pow(&self, exponent: &Decimal, ctx: DecimalContext) -> Option<Decimal> {
+        if let Some(n) = exponent.to_integer() {
+            if n.bits() < 64 {
+                return self.powi(&n, ctx);
+            }
+        }
+        if self.is_zero() {
+            return (!exponent.is_negative()).then(Decimal::zero);
+        }
+        let work = ctx.working(exponent.magnitude().max(0) as usize);
+        let ln = self.ln(work)?;
+        Some(ln.mul(exponent, work).exp(work)?.round(ctx))
+    }
+
+    pub(crate) fn sqrt(&self, ctx: DecimalContext) -> Option<Decimal> {
+        if self.is_negative() {
+            return None;
+        }
+        if self.is_zero() {
+            return Some(Decimal::zero());
+        }
+        // scale so the root has enough digits and the exponent halves evenly
+        let mut shift = (2 * (ctx.digits as i64 + 2) - digit_count(&self.mantissa)).max(0);
+        if (self.exponent - shift) % 2 != 0 {
+            shift += 1;
+        }
+        let scaled = &self.mantissa * pow10(shift as u64);
+        let root = scaled.sqrt();
+        let exact = &root * &root == scaled;
+        Some(round_digits(root, (self.exponent - shift) / 2, !exact, ctx))
+    }
+
+    // the nth root, odd roots of negative numbers are real
+    pub(crate) fn nth_root(&self, n: &Decimal, ctx: DecimalContext) -> Option<Decimal> {
+        if n.is_zero() {
+            return None;
+        }
+        let odd = n.to_integer().is_some_and(|n| n.is_odd());
+        if self.is_negative() && !odd {
+            return None;
+        }
+        let work = ctx.working(0);
+        let root = self.abs().pow(&Decimal::one().div(n, work)?, work)?;
+        Some(if self.is_negative() { root.neg() } else { root }.round(ctx))
+    }
+
+    pub(crate) fn exp(&self, ctx: DecimalContext) -> Option<Decimal> {
+        if self.is_zero() {
+            return Some(Decimal::one());
+        }
+        // results that could not be written back as a literal fall back to floats, exp(x) has about
+        // x / ln(10) digits
+        if self.to_f64().abs() > MAX_LITERAL_EXPONENT as f64 * std::f64::consts::LN_10 {
+            return None;
+        }
+        // exp(x) = exp(x / 2^k)^(2^k), halving until the series converges quickly
+        let halvings = (self.to_f64().abs() / 0.01).log2().ceil().max(0.0) as u64;
+        let work = ctx.working(halvings as usize / 3 + 1);
+        let scale = BigInt::from(5).pow(halvings as u32);
+        let x = round_digits(&self.mantissa * scale, self.exponent - halvings as i64, false, work);
+
+        let mut sum = Decimal::one();
+        let mut term = Decimal::one();
+        for n in 1.. {
+            term = term.mul(&x, work).div(&Decimal::from_integer(n.into()), work)?;
+            if term.is_zero() || term.magnitude() < sum.magnitude() - work.digits as i64 {
+                break;
+            }
+            sum = sum.add(&term, work);
+        }
+        for _ in 0..halvings {
+            sum = sum.mul(&sum, work);
+        }
+        let result = sum.round(ctx);
+        (result.exponent.unsigned_abs() <= MAX_LITERAL_EXPONENT).then_some(result)
+    }
+
+    pub(crate) fn ln(&self, ctx: DecimalContext) -> Option<Decimal> {
+        if self.is_negative() || self.is_zero() || self.is_too_large() {
+            return None;
+        }
+        // x = y * 10^k with 1 <= y < 10, so ln(x) = ln(y) + k ln(10)
+        let k = self.magnitude();
+        let work = ctx.working(digit_count(&BigInt::from(k)) as usize);
+        let y = Decimal::new(self.mantissa.clone(), self.exponent - k);
+        let mut result = ln_reduced(&y, work)?;
+        if k != 0 {
+            let ln10 = ln_reduced(&Decimal::from_integer(10.into()), work)?;
+            result = result.add(&ln10.mul(&Decimal::from_integer(k.into()), work), work);
+        }
+        Some(result.round(ctx))
+    }
+
+    pub(crate) fn log(&self, base: &Decimal, ctx: DecimalContext) -> Option<Decimal> {
+        let work = ctx.working(0);
+        Some(self.ln(work)?.div(&base.ln(work)?, work)?.round(ctx))
+    }
+
+    pub(crate) fn pi(ctx: DecimalContext) -> Decimal {
+        // machin's formula, pi = 16 atan(1/5) - 4 atan(1/239), in fixed point
+        let work = ctx.working(0);
+        let unity = pow10(work.digits as u64);
+        let pi = (atan_inverse(5, &unity) * 16) - (atan_inverse(239, &unity) * 4);
+        round_digits(pi, -(work.digits as i64), false, ctx)
+    }
+
+    pub(crate) fn tau(ctx: DecimalContext) -> Decimal {
+        Decimal::pi(ctx.working(0)).mul(&Decimal::from_integer(2.into()), ctx)
+    }
+
+    pub(crate) fn e(ctx: DecimalContext) -> Decimal {
+        Decimal::one().exp(ctx).unwrap_or_else(Decimal::zero)
+    }
+
+    pub(crate) fn sin(&self, ctx: DecimalContext) -> Option<Decimal> {
+        let work = ctx.working(0);
+        let x = self.reduce_angle(work)?;
+        // sin(x) = x - x^3/3! + x^5/5! ...
+        let x_squared = x.mul(&x, work);
+        let mut sum = x.clone();
+        let mut term = x;
+        for n in 1.. {
+            let divisor = Decimal::from_integer(BigInt::from((2 * n) * (2 * n + 1)));
+            term = term.mul(&x_squared, work).div(&divisor, work)?.neg();
+            if term.is_zero() || term.magnitude() < sum.magnitude() - work.digits as i64 {
+                break;
+            }
+            sum = sum.add(&term, work);
+        }
+        Some(sum.round(ctx))
+    }
+
+    pub(crate) fn cos(&self, ctx: DecimalContext) -> Option<Decimal> {
+        let work = ctx.working(0);
+        let x = self.reduce_angle(work)?;
+        // cos(x) = 1 - x^2/2! + x^4/4! ...
+        let x_squared = x.mul(&x, work);
+        let mut sum = Decimal::one();
+        let mut term = Decimal::one();
+        for n in 1.. {
+            let divisor = Decimal::from_integer(BigInt::from((2 * n - 1) * (2 * n)));
+            term = term.mul(&x_squared, work).div(&divisor, work)?.neg();
+            if term.is_zero() || term.magnitude() < sum.magnitude() - work.digits as i64 {
+                break;
+            }
+            sum = sum.add(&term, work);
+        }
+        Some(sum.round(ctx))
+    }
+
+    pub(crate) fn tan(&self, ctx: DecimalContext) -> Option<Decimal> {
+        let work = ctx.working(0);
+        Some(self.sin(work)?.div(&self.cos(work)?, work)?.round(ctx))
+    }
+
+    // the same angle between -pi and pi
+    fn reduce_angle(&self, ctx: DecimalContext) -> Option<Decimal> {
+        if self.is_too_large() {
+            return None;
+        }
+        // every digit before the decimal point needs another digit of tau to keep the remainder precise
+        let work = ctx.working(self.magnitude().max(0) as usize);
+        let tau = Decimal::tau(work);
+        let turns = Decimal::from_rational(&(self.to_rational() / tau.to_rational()).round(), work);
+        Some(self.sub(&turns.mul(&tau, work), work).round(ctx))
+    }
+
+    pub(crate) fn atan(&self, ctx: DecimalContext) -> Option<Decimal> {
+        if self.is_zero() {
+            return Some(Decimal::zero());
+        }
+        let work = ctx.working(2);
+        // atan(x) = pi/2 - atan(1/x) for x > 1, and the negative of that for x < -1
+        if self.abs().cmp(&Decimal::one()) == Ordering::Greater {
+            let half_pi = Decimal::pi(work).div(&Decimal::from_integer(2.into()), work)?;
+            let half_pi = if self.is_negative() { half_pi.neg() } else { half_pi };
+            let inverse = Decimal::one().div(self, work)?.atan(work)?;
+            return Some(half_pi.sub(&inverse, work).round(ctx));
+        }
+        // atan(x) = 2 atan(x / (1 + sqrt(1 + x^2))), until x is small enough for the series
+        let mut x = self.round(work);
+        let mut doublings = 0;
+        let tenth = Decimal::new(BigInt::one(), -1);
+        while x.abs().cmp(&tenth) == Ordering::Greater {
+            let root = Decimal::one().add(&x.mul(&x, work), work).sqrt(work)?;
+            x = x.div(&Decimal::one().add(&root, work), work)?;
+            doublings += 1;
+        }
+        // atan(x) = x - x^3/3 + x^5/5 ...
+        let x_squared = x.mul(&x, work);
+        let mut sum = x.clone();
+        let mut power = x;
+        for n in 1.. {
+            power = power.mul(&x_squared, work).neg();
+            let term = power.div(&Decimal::from_integer(BigInt::from(2 * n + 1)), work)?;
+            if term.is_zero() || term.magnitude() < sum.magnitude() - work.digits as i64 {
+                break;
+            }
+            sum = sum.add(&term, work);
+        }
+        let scale = Decimal::from_integer(BigInt::one() << doublings);
+        Some(sum.mul(&scale, work).round(ctx))
+    }
+
+    pub(crate) fn asin(&self, ctx: DecimalContext) -> Option<Decimal> {
+        let work = ctx.working(0);
+        match self.abs().cmp(&Decimal::one()) {
+            Ordering::Greater => None,
+            // asin(1) is pi/2, which the formula below would divide by zero to get
+            Ordering::Equal => {
+                let half_pi = Decimal::pi(work).div(&Decimal::from_integer(2.into()), work)?;
+                Some(if self.is_negative() { half_pi.neg() } else { half_pi }.round(ctx))
+            }
+            // asin(x) = atan(x / sqrt(1 - x^2))
+            Ordering::Less => {
+                let root = Decimal::one().sub(&self.mul(self, work), work).sqrt(work)?;
+                Some(self.div(&root, work)?.atan(work)?.round(ctx))
+            }
+        }
+    }
+
+    pub(crate) fn acos(&self, ctx: DecimalContext) -> Option<Decimal> {
+        let work = ctx.working(0);
+        let half_pi = Decimal::pi(work).div(&Decimal::from_integer(2.into()), work)?;
+        Some(half_pi.sub(&self.asin(work)?, work).round(ctx))
+    }
+
+    // the angle of the point (x, y), self is y
+    pub(crate) fn atan2(&self, x: &Decimal, ctx: DecimalContext) -> Option<Decimal> {
+        let work = ctx.working(0);
+        let pi = Decimal::pi(work);
+        let angle = if x.is_zero() {
+            match self.mantissa.signum().to_i32() {
+                Some(0) => Decimal::zero(),
+                _ => pi.div(&Decimal::from_integer(2.into()), work)?,
+            }
+        } else {
+            let angle = self.div(x, work)?.atan(work)?.abs();
+            if x.is_negative() {
+                pi.sub(&angle, work)
+            } else {
+                angle
+            }
+        };
+        Some(if self.is_negative() { angle.neg() } else { angle }.round(ctx))
+    }
+
+    pub(crate) fn hypot(&self, other: &Decimal, ctx: DecimalContext) -> Option<Decimal> {
+        let work = ctx.working(0);
+        let sum = self.mul(self, work).add(&other.mul(other, work), work);
+        Some(sum.sqrt(work)?.round(ctx))
+    }
+}
+
+// ln(y) for 1 <= y <= 10
+fn ln_reduced(y: &Decimal, ctx: DecimalContext) -> Option<Decimal> {
+    // ln(y) = 2^k ln(y^(1/2^k)), taking square roots brings y close to 1
+    const ROOTS: u32 = 8;
+    let work = ctx.working(3);
+    let mut t = y.round(work);
+    for _ in 0..ROOTS {
+        t = t.sqrt(work)?;
+    }
+    // ln(t) = 2 atanh(z) = 2 (z + z^3/3 + z^5/5 ...) where z = (t - 1) / (t + 1)
+    let one = Decimal::one();
+    let z = t.sub(&one, work).div(&t.add(&one, work), work)?;
+    let z_squared = z.mul(&z, work);
+    let mut sum = z.clone();
+    let mut power = z;
+    for n in 1.. {
+        power = power.mul(&z_squared, work);
+        let term = power.div(&Decimal::from_integer(BigInt::from(2 * n + 1)), work)?;
+        if term.is_zero() || term.magnitude() < sum.magnitude() - work.digits as i64 {
+            break;
+        }
+        sum = sum.add(&term, work);
+    }
+    let scale = Decimal::from_integer(BigInt::from(2u64 << ROOTS));
+    Some(sum.mul(&scale, work).round(ctx))
+}
+
+// atan(1/n) * unity, for machin's formula
+fn atan_inverse(n: u64, unity: &BigInt) -> BigInt {
+    let n_squared = BigInt::from(n * n);
+    let mut power = unity / n;
+    let mut sum = power.clone();
+    let mut k: u64 = 1;
+    while !power.is_zero() {
+        power /= &n_squared;
+        let term = &power / (2 * k + 1);
+        if k % 2 == 1 {
+            sum -= term;
+        } else {
+            sum += term;
+        }
+        k += 1;
+    }
+    sum
+}
+
+// plain notation for numbers of a reasonable size, eg. 0.001 or 12345.6789
+// and scientific notation otherwise, eg. 1.5e-30 or 6.02214076e+23
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        let sign = if self.is_negative() { "-" } else { "" };
+        let digits = self.mantissa.magnitude().to_str_radix(10);
+        let magnitude = self.magnitude();
+        if !(-7..=49).contains(&magnitude) {
+            let (first, rest) = digits.split_at(1);
+            let point = if rest.is_empty() { "" } else { "." };
+            let exponent_sign = if magnitude < 0 { "-" } else { "+" };
+            return write!(f, "{sign}{first}{point}{rest}e{exponent_sign}{}", magnitude.abs());
+        }
+        if self.exponent >= 0 {
+            write!(f, "{sign}{digits}{}", "0".repeat(self.exponent as usize))
+        } else if magnitude >= 0 {
+            let (whole, fraction) = digits.split_at(magnitude as usize + 1);
+            write!(f, "{sign}{whole}.{fraction}")
+        } else {
+            write!(f, "{sign}0.{}{digits}", "0".repeat((-magnitude - 1) as usize))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathengine::core::{Calc, Calculator, EvalResult};
+    use crate::mathengine::testing::shown;
+    use crate::mathengine::value::{NumberMode, Value};
+
+    fn decimal(digits: usize) -> Calculator {
+        let mut calc = Calculator::new();
+        calc.eval(&format!("precision {digits}")).unwrap();
+        calc
+    }
+
+    #[test]
+    fn setting_the_precision_switches_to_decimals() {
+        let mut calc = decimal(50);
+        assert_eq!(calc.number_mode(), NumberMode::Decimal);
+        assert_eq!(shown(&mut calc, "1/3"), format!("0.{}", "3".repeat(50)));
+        assert!(matches!(calc.eval("1/3").unwrap(), EvalResult::Answer(Value::Decimal(_))));
+        assert_eq!(shown(&mut calc, "0.1 + 0.2"), "0.3");
+        assert_eq!(shown(&mut calc, "10^40 / 4"), "2500000000000000000000000000000000000000");
+    }
+
+    #[test]
+    fn functions_and_constants_to_the_precision() {
+        let mut calc = decimal(100);
+        assert_eq!(
+            shown(&mut calc, "sqrt(2)"),
+            "1.414213562373095048801688724209698078569671875376948073176679737990732478462107038850387534327641573"
+        );
+        let mut calc = decimal(30);
+        for (input, expected) in [
+            ("pi", "3.14159265358979323846264338328"),
+            ("e", "2.71828182845904523536028747135"),
+            ("tau", "6.28318530717958647692528676656"),
+            ("exp(1)", "2.71828182845904523536028747135"),
+            ("ln(2)", "0.693147180559945309417232121458"),
+            ("sin(1)", "0.84147098480789650665250232163"),
+            ("cos(pi)", "-1"),
+        ] {
+            assert_eq!(shown(&mut calc, input), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn rounding_modes() {
+        let mut calc = decimal(5);
+        for (mode, two_thirds, minus_one_third) in [
+            (RoundingMode::HalfEven, "0.66667", "-0.33333"),
+            (RoundingMode::Down, "0.66666", "-0.33333"),
+            (RoundingMode::Up, "0.66667", "-0.33334"),
+            (RoundingMode::Ceiling, "0.66667", "-0.33333"),
+            (RoundingMode::Floor, "0.66666", "-0.33334"),
+        ] {
+            calc.set_rounding_mode(mode);
+            assert_eq!(shown(&mut calc, "2/3"), two_thirds, "{mode}");
+            assert_eq!(shown(&mut calc, "-1/3"), minus_one_third, "{mode}");
+        }
+        // ties
+        for (mode, expected) in [(RoundingMode::HalfEven, "1"), (RoundingMode::HalfUp, "1.0001"), (RoundingMode::HalfDown, "1")] {
+            calc.set_rounding_mode(mode);
+            assert_eq!(shown(&mut calc, "1.00005"), expected, "{mode}");
+            assert_eq!(shown(&mut calc, "1.00015"), if mode == RoundingMode::HalfDown { "1.0001" } else { "1.0002" }, "{mode}");
+        }
+    }
+
+    #[test]
+    fn rounding_mode_names() {
+        for mode in [
+            RoundingMode::HalfEven,
+            RoundingMode::HalfUp,
+            RoundingMode::HalfDown,
+            RoundingMode::Up,
+            RoundingMode::Down,
+            RoundingMode::Ceiling,
+            RoundingMode::Floor,
+        ] {
+            assert_eq!(RoundingMode::from_name(&mode.to_string()), Some(mode));
+        }
+        let mut calc = Calculator::new();
+        calc.eval("rounding floor").unwrap();
+        assert_eq!(calc.rounding_mode(), RoundingMode::Floor);
+        assert!(calc.eval("rounding ceil").is_err());
+    }
+
+    #[test]
+    fn results_too_large_to_write_fall_back_to_floats() {
+        let mut calc = decimal(20);
+        assert!(matches!(calc.eval("exp(20000)").unwrap(), EvalResult::Answer(Value::Decimal(_))));
+        assert_eq!(calc.eval("exp(10^8)").unwrap(), EvalResult::Answer(Value::Float(f64::INFINITY)));
+        assert_eq!(calc.eval("exp(-30000)").unwrap(), EvalResult::Answer(Value::Float(0.0)));
+    }
+}
@@ -1,54 +1,179 @@
 use std::fmt;
-use super::token::Token;
+use super::builtin::Arity;
+use super::token::Span;
+use super::word::WordType;
 
+// an error and the part of the input that caused it
 #[derive(Clone, Debug)]
-pub enum CalcError {
+pub struct CalcError {
+    pub kind: ErrorKind,
+    pub span: Option<Span>,
+}
+
+impl CalcError {
+    pub fn new(kind: ErrorKind, span: Span) -> CalcError {
+        CalcError {
+            kind,
+            span: Some(span),
+        }
+    }
+
+    // points an existing error at a different part of the input
+    pub fn with_span(mut self, span: Span) -> CalcError {
+        self.span = Some(span);
+        self
+    }
+}
+
+impl From<ErrorKind> for CalcError {
+    fn from(kind: ErrorKind) -> CalcError {
+        CalcError { kind, span: None }
+    }
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl std::error::Error for CalcError {}
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum ErrorKind {
     FuncNoName,
-    FuncIncorrectArgCount(usize),
-    FuncDoesNotExist(Vec<u8>),
+    FuncIncorrectArgCount(String, Arity, usize),
+    FuncDoesNotExist(String),
     FuncArgsNotInBrackets,
     FuncExpectedComma,
     FuncExpectedArg,
-    FuncHardcodedReassignAttempt(Vec<u8>),
-    CannotParseNumber(Vec<u8>),
-    CannotParseOperator(Vec<u8>),
-    VarDoesNotExist(Vec<u8>),
+    FuncHardcodedReassignAttempt(String),
+    FuncInvalidArg(String),
+    FuncFailed(String, String),
+    FuncComplexArg(String),
+    FuncDuplicateArg(String),
+    CannotParseNumber(String),
+    InvalidDigit(String, u32),
+    MissingDigits(String),
+    MisplacedDigitSeparator(String),
+    NumberTooLarge(String),
+    CannotParseOperator(String),
+    VarDoesNotExist(String),
+    VarReadOnly(String),
+    ConstReassignAttempt(String),
+    ConstDeleteAttempt(String),
+    ConstDeclaration,
+    InvalidName(String),
     NoInput,
     TooManyAssignmentOps,
-    InvalidFirstToken(Token),
-    InvalidLastToken(Token),
+    InvalidFirstToken(String),
+    InvalidLastToken(String),
     InvalidTokenSeq,
     ImbalancedBrackets,
     MisplacedComma,
     LhsMustBeVarOrFunc,
     FailedToSolveEquation,
-    TooMuchRecursion,
+    TooMuchRecursion(usize),
+    DivisionByZero,
+    UnknownSetting(String),
+    OperatorComplexArg(String),
+    NotAnInteger(String),
+    Overflow(WordType),
+    ResultTooLarge,
+    FileError(String, String),
+    ScriptError(String, usize, Box<ErrorKind>),
+    ConfigSyntax(String),
+    UnknownName(String),
+    UnknownCommand(String),
+    CommandNameReassignAttempt(String),
+    ScriptTooDeep(usize),
+    CommandUsage(String),
 }
 
-impl fmt::Display for CalcError {
+impl fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let msg = match self {
-            CalcError::FuncNoName => format!("function has no name"),
-            CalcError::FuncIncorrectArgCount(num) => format!("function recieved {num} args"),
-            CalcError::FuncDoesNotExist(name) => format!("function \"{}\" does not exist", String::from_utf8_lossy(&name)),
-            CalcError::FuncArgsNotInBrackets => format!("function args must be inside function brackets"),
-            CalcError::FuncExpectedComma => format!("function expected comma"),
-            CalcError::FuncExpectedArg => format!("function expected argument"),
-            CalcError::FuncHardcodedReassignAttempt(name) => format!("cannot reassign base function \"{}\"", String::from_utf8_lossy(&name)),
-            CalcError::CannotParseNumber(name) => format!("string \"{}\" cannot be parsed as a number", String::from_utf8_lossy(&name)),
-            CalcError::CannotParseOperator(name) => format!("string \"{}\" cannot be parsed as an operator", String::from_utf8_lossy(&name)),
-            CalcError::VarDoesNotExist(name) => format!("variable \"{}\" does not exist", String::from_utf8_lossy(&name)),
-            CalcError::NoInput => format!(""),
-            CalcError::TooManyAssignmentOps => format!("too many assignment (=) operators"),
-            CalcError::InvalidFirstToken(token) => format!("{:?} cannot be the first token", token),
-            CalcError::InvalidLastToken(token) => format!("{:?} cannot be the last token", token),
-            CalcError::InvalidTokenSeq => format!("invalid token sequence"),
-            CalcError::ImbalancedBrackets => format!("imbalanced brackets"),
-            CalcError::MisplacedComma => format!("misplaced comma"),
-            CalcError::LhsMustBeVarOrFunc => format!("LHS must be a variable or a function"),
-            CalcError::FailedToSolveEquation => format!("failed to solve expression"),
-            CalcError::TooMuchRecursion => format!("exceeded recursion depth limit"),
-        };
-        write!(f, "{}", msg)
-    }
-}
\ No newline at end of file
+        match self {
+            ErrorKind::FuncNoName => write!(f, "function has no name"),
+            ErrorKind::FuncIncorrectArgCount(name, expected, num) => write!(f, "function \"{name}\" expects {expected} args but recieved {num}"),
+            ErrorKind::FuncDoesNotExist(name) => write!(f, "function \"{name}\" does not exist"),
+            ErrorKind::FuncArgsNotInBrackets => write!(f, "function args must be inside function brackets"),
+            ErrorKind::FuncExpectedComma => write!(f, "function expected comma"),
+            ErrorKind::FuncExpectedArg => write!(f, "function expected argument"),
+            ErrorKind::FuncHardcodedReassignAttempt(name) => write!(f, "cannot reassign base function \"{name}\""),
+            ErrorKind::FuncInvalidArg(name) => write!(f, "invalid argument for function \"{name}\""),
+            ErrorKind::FuncFailed(name, msg) => write!(f, "function \"{name}\" failed: {msg}"),
+            ErrorKind::FuncComplexArg(name) => write!(f, "function \"{name}\" does not take complex numbers"),
+            ErrorKind::FuncDuplicateArg(name) => write!(f, "function has more than one parameter named \"{name}\""),
+            ErrorKind::CannotParseNumber(name) => write!(f, "string \"{name}\" cannot be parsed as a number"),
+            ErrorKind::InvalidDigit(name, radix) => write!(f, "number \"{name}\" has a digit that is not valid in base {radix}"),
+            ErrorKind::MissingDigits(name) => write!(f, "number \"{name}\" has no digits"),
+            ErrorKind::MisplacedDigitSeparator(name) => write!(f, "digit separator '_' in \"{name}\" must be between two digits"),
+            ErrorKind::NumberTooLarge(name) => write!(f, "number \"{name}\" is too large"),
+            ErrorKind::CannotParseOperator(name) => write!(f, "string \"{name}\" cannot be parsed as an operator"),
+            ErrorKind::VarDoesNotExist(name) => write!(f, "variable \"{name}\" does not exist"),
+            ErrorKind::VarReadOnly(name) => write!(f, "variable \"{name}\" is read only"),
+            ErrorKind::ConstReassignAttempt(name) => write!(f, "cannot reassign constant \"{name}\""),
+            ErrorKind::ConstDeleteAttempt(name) => write!(f, "cannot delete constant \"{name}\""),
+            ErrorKind::ConstDeclaration => write!(f, "a constant is declared as \"const name = value\""),
+            ErrorKind::InvalidName(name) => write!(f, "\"{name}\" is not a valid name"),
+            ErrorKind::NoInput => write!(f, ""),
+            ErrorKind::TooManyAssignmentOps => write!(f, "too many assignment (=) operators"),
+            ErrorKind::InvalidFirstToken(token) => write!(f, "\"{token}\" cannot be the first token"),
+            ErrorKind::InvalidLastToken(token) => write!(f, "\"{token}\" cannot be the last token"),
+            ErrorKind::InvalidTokenSeq => write!(f, "invalid token sequence"),
+            ErrorKind::ImbalancedBrackets => write!(f, "imbalanced brackets"),
+            ErrorKind::MisplacedComma => write!(f, "misplaced comma"),
+            ErrorKind::LhsMustBeVarOrFunc => write!(f, "LHS must be a variable or a function"),
+            ErrorKind::FailedToSolveEquation => write!(f, "failed to solve expression"),
+            ErrorKind::DivisionByZero => write!(f, "division by zero"),
+            ErrorKind::UnknownSetting(setting) => write!(f, "unknown setting \"{setting}\""),
+            ErrorKind::OperatorComplexArg(op) => write!(f, "operator \"{op}\" does not work with complex numbers"),
+            ErrorKind::NotAnInteger(value) => write!(f, "\"{value}\" is not an integer"),
+            ErrorKind::Overflow(word) => write!(f, "result does not fit in {word}"),
+            ErrorKind::ResultTooLarge => write!(f, "result is too large to work out exactly"),
+            ErrorKind::FileError(path, message) => write!(f, "{path}: {message}"),
+            ErrorKind::ScriptError(source, line, kind) => write!(f, "{source}:{line}: {kind}"),
+            ErrorKind::UnknownName(name) => write!(f, "\"{name}\" is not a variable or function"),
+            ErrorKind::UnknownCommand(name) => write!(f, "\"{name}\" is not a command"),
+            ErrorKind::CommandNameReassignAttempt(name) => write!(f, "\"{name}\" is a command and cannot be assigned"),
+            ErrorKind::ScriptTooDeep(limit) => write!(f, "scripts run each other deeper than the limit of {limit}"),
+            ErrorKind::CommandUsage(usage) => write!(f, "usage: {usage}"),
+            ErrorKind::ConfigSyntax(line) => write!(f, "expected \"name = value\" but found \"{line}\""),
+            ErrorKind::TooMuchRecursion(limit) => write!(f, "function calls nested deeper than the recursion limit of {limit}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_are_kept_or_replaced() {
+        assert!(CalcError::from(ErrorKind::DivisionByZero).span.is_none());
+        let e = CalcError::new(ErrorKind::InvalidTokenSeq, Span::new(2, 5));
+        assert_eq!(e.span, Some(Span::new(2, 5)));
+        assert_eq!(e.with_span(Span::new(0, 1)).span, Some(Span::new(0, 1)));
+    }
+
+    #[test]
+    fn messages_name_what_went_wrong() {
+        let unknown = ErrorKind::VarDoesNotExist("y".to_string());
+        assert_eq!(CalcError::from(unknown.clone()).to_string(), "variable \"y\" does not exist");
+        let script = ErrorKind::ScriptError("prelude.calc".to_string(), 4, Box::new(unknown));
+        assert_eq!(script.to_string(), "prelude.calc:4: variable \"y\" does not exist");
+        let limit = ErrorKind::TooMuchRecursion(1000);
+        assert_eq!(limit.to_string(), "function calls nested deeper than the recursion limit of 1000");
+        let word = WordType::new(8, true).unwrap();
+        assert_eq!(ErrorKind::Overflow(word).to_string(), "result does not fit in i8");
+    }
+
+    #[test]
+    fn errors_can_be_boxed() {
+        fn divide() -> Result<(), Box<dyn std::error::Error>> {
+            Err(CalcError::from(ErrorKind::DivisionByZero))?
+        }
+        assert_eq!(divide().unwrap_err().to_string(), "division by zero");
+    }
+}
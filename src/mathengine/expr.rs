@@ -0,0 +1,247 @@
+use super::value::Value;
+use super::token::{
+    Span,
+    Token::{self, *},
+};
+
+// a parsed expression, built from a token list by parse::parse
+// span covers the whole expression in the input it was parsed from
+#[derive(Debug)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Expr {
+        Expr { kind, span }
+    }
+
+    // the names of the variables the expression reads, in the order they first appear
+    pub fn variables(&self) -> Vec<Vec<u8>> {
+        let mut names = Vec::new();
+        self.collect_variables(&mut names);
+        names
+    }
+
+    // walks the tree with an explicit stack, deep expressions would overflow the rust stack
+    fn collect_variables(&self, names: &mut Vec<Vec<u8>>) {
+        let mut stack = vec![self];
+        while let Some(expr) = stack.pop() {
+            match &expr.kind {
+                ExprKind::Variable(name) if !names.contains(name) => names.push(name.clone()),
+                ExprKind::Unary(_, operand) => stack.push(operand),
+                // children are pushed in reverse so they are visited left to right
+                ExprKind::Binary(_, lhs, rhs) => stack.extend([rhs.as_ref(), lhs.as_ref()]),
+                ExprKind::Call(_, args) => stack.extend(args.iter().rev()),
+                ExprKind::If(condition, then, otherwise) => {
+                    stack.extend([otherwise.as_ref(), then.as_ref(), condition.as_ref()])
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+// dropping the boxes one inside the other would recurse as deep as the tree, so the children
+// are moved onto a list and dropped one at a time
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut children = Vec::new();
+        take_children(&mut self.kind, &mut children);
+        while let Some(mut child) = children.pop() {
+            take_children(&mut child.kind, &mut children);
+        }
+    }
+}
+
+fn take_children(kind: &mut ExprKind, children: &mut Vec<Expr>) {
+    if !matches!(kind, ExprKind::Unary(..) | ExprKind::Binary(..) | ExprKind::Call(..) | ExprKind::If(..)) {
+        return;
+    }
+    match std::mem::replace(kind, ExprKind::FunctionArg(0)) {
+        ExprKind::Unary(_, operand) => children.push(*operand),
+        ExprKind::Binary(_, lhs, rhs) => children.extend([*lhs, *rhs]),
+        ExprKind::Call(_, args) => children.extend(args),
+        ExprKind::If(condition, then, otherwise) => children.extend([*condition, *then, *otherwise]),
+        _ => (),
+    }
+}
+
+#[derive(Debug)]
+pub enum ExprKind {
+    Number(Value),
+    Variable(Vec<u8>),
+    FunctionArg(usize),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Vec<u8>, Vec<Expr>),
+    // if(condition, then, otherwise), only the branch that is picked gets solved
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOp {
+    Plus,   // +
+    Negate, // -
+    Not,    // !
+    BitNot, // ~
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Add,    // +
+    Sub,    // -
+    Mul,    // *
+    Div,    // /
+    Mod,    // %
+    Pow,    // ^
+    Shl,    // <<
+    Shr,    // >>
+    BitAnd, // &
+    BitOr,  // |
+    BitXor, // ^^
+
+    Less,         // <
+    LessEqual,    // <=
+    Greater,      // >
+    GreaterEqual, // >=
+    Equal,        // ==
+    NotEqual,     // !=
+
+    And, // &&
+    Or,  // ||
+}
+
+impl UnaryOp {
+    // -2^2 is -(2^2), but -2*3 is (-2)*3
+    pub const PRECEDENCE: u8 = 8;
+
+    pub fn from_token(token: &Token) -> Option<UnaryOp> {
+        match token {
+            Addition => Some(UnaryOp::Plus),
+            Subtraction => Some(UnaryOp::Negate),
+            LogicalNot => Some(UnaryOp::Not),
+            BitwiseNot => Some(UnaryOp::BitNot),
+            _ => None,
+        }
+    }
+}
+
+impl BinaryOp {
+    pub fn from_token(token: &Token) -> Option<BinaryOp> {
+        match token {
+            Addition => Some(BinaryOp::Add),
+            Subtraction => Some(BinaryOp::Sub),
+            Multiplication => Some(BinaryOp::Mul),
+            Division => Some(BinaryOp::Div),
+            Modulation => Some(BinaryOp::Mod),
+            Exponentation => Some(BinaryOp::Pow),
+            BitwiseLeftShift => Some(BinaryOp::Shl),
+            BitwiseRightShift => Some(BinaryOp::Shr),
+            BitwiseAnd => Some(BinaryOp::BitAnd),
+            BitwiseOr => Some(BinaryOp::BitOr),
+            BitwiseXor => Some(BinaryOp::BitXor),
+            LessThan => Some(BinaryOp::Less),
+            LessThanOrEqual => Some(BinaryOp::LessEqual),
+            GreaterThan => Some(BinaryOp::Greater),
+            GreaterThanOrEqual => Some(BinaryOp::GreaterEqual),
+            Equal => Some(BinaryOp::Equal),
+            NotEqual => Some(BinaryOp::NotEqual),
+            LogicalAnd => Some(BinaryOp::And),
+            LogicalOr => Some(BinaryOp::Or),
+            _ => None,
+        }
+    }
+
+    // how the operator is written, for error messages
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+            BinaryOp::Pow => "^",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^^",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        }
+    }
+
+    // higher binds tighter, unary signs sit between multiplication and exponentiation
+    // comparisons and then logic bind loosest, so x + 1 < 2y || y == 0 compares the sums first
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Equal | BinaryOp::NotEqual => 3,
+            BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual => 4,
+            BinaryOp::Add | BinaryOp::Sub => 5,
+            BinaryOp::Shl
+            | BinaryOp::Shr
+            | BinaryOp::BitAnd
+            | BinaryOp::BitOr
+            | BinaryOp::BitXor => 6,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 7,
+            BinaryOp::Pow => 9,
+        }
+    }
+
+    // 2^3^2 is 2^(3^2)
+    pub fn is_right_assoc(self) -> bool {
+        self == BinaryOp::Pow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathengine::parse::parse;
+    use crate::mathengine::token::tokenize;
+
+    fn variables(input: &str) -> Vec<String> {
+        let expr = parse(&tokenize(input.as_bytes()).unwrap()).unwrap();
+        expr.variables().iter().map(|name| String::from_utf8_lossy(name).into_owned()).collect()
+    }
+
+    // -(-(-(...x))) as deep as depth, built by hand so the parser is not involved
+    fn deep_tree(depth: usize) -> Expr {
+        let span = Span::new(0, 1);
+        let mut expr = Expr::new(ExprKind::Variable(b"x".to_vec()), span);
+        for _ in 0..depth {
+            expr = Expr::new(ExprKind::Unary(UnaryOp::Negate, Box::new(expr)), span);
+        }
+        expr
+    }
+
+    #[test]
+    fn variables_in_the_order_they_appear() {
+        assert_eq!(variables("b * a + f(c, a) - b"), ["b", "a", "c"]);
+        assert_eq!(variables("if(x > 0, y, -z)"), ["x", "y", "z"]);
+        assert!(variables("2 + sin(3)").is_empty());
+    }
+
+    #[test]
+    fn deep_trees_are_walked_and_dropped_without_recursion() {
+        let expr = deep_tree(1_000_000);
+        assert_eq!(expr.variables(), [b"x".to_vec()]);
+        drop(expr);
+        let mut sum = Expr::new(ExprKind::Number(Value::from(0)), Span::new(0, 1));
+        for _ in 0..1_000_000 {
+            let one = Expr::new(ExprKind::Number(Value::from(1)), Span::new(0, 1));
+            sum = Expr::new(ExprKind::Binary(BinaryOp::Add, Box::new(sum), Box::new(one)), Span::new(0, 1));
+        }
+        assert!(sum.variables().is_empty());
+    }
+}
@@ -0,0 +1,388 @@
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::Signed;
+
+use super::angle::AngleUnit;
+use super::decimal::{Decimal, DecimalContext, RoundingMode};
+use super::value::{ComplexDisplay, RationalDisplay, Value};
+
+/// How answers and variables are displayed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NumberFormat {
+    /// Floats to 15 significant digits, so `0.1 + 0.2` shows as `0.3`, and exact values as they
+    /// are. Very large and very small numbers are shown in scientific notation.
+    Auto,
+    /// This many digits after the decimal point, eg. `3.14` for 2
+    Fixed(usize),
+    /// eg. `1.2345e+4`
+    Scientific,
+    /// Scientific notation with the exponent a multiple of 3, eg. `12.345e+3`, or with an SI prefix
+    /// in place of the exponent, eg. `12.345k`
+    Engineering { si_prefixes: bool },
+    /// Rounded to this many significant digits, eg. `3.14` for 3
+    SignificantFigures(usize),
+}
+
+// everything that decides how a value is written out
+#[derive(Clone, Copy)]
+pub(crate) struct FormatOptions {
+    pub(crate) number_format: NumberFormat,
+    pub(crate) grouping: bool,
+    pub(crate) rational_display: RationalDisplay,
+    pub(crate) complex_display: ComplexDisplay,
+    pub(crate) angle_unit: AngleUnit,
+}
+
+// how many significant digits floats and fractions that do not end are shown with, which is as many
+// as an f64 reliably holds
+const SHOWN_DIGITS: usize = 15;
+
+// the most significant digits an f64 has, more than this would only show binary noise
+const FLOAT_DIGITS: usize = 17;
+
+// auto and significant figures use plain notation for leading digits in this range, the same as
+// decimals do
+const PLAIN_EXPONENTS: std::ops::RangeInclusive<i64> = -7..=49;
+
+// from quecto (10^-30) to quetta (10^30)
+const SI_PREFIXES: [&str; 21] = ["q", "r", "y", "z", "a", "f", "p", "n", "µ", "m", "", "k", "M", "G", "T", "P", "E", "Z", "Y", "R", "Q"];
+
+// a real number written out in decimal, eg. 123.4 is "1234" with exponent 2
+#[derive(Clone, Debug)]
+struct Digits {
+    negative: bool,
+    // the significant digits, without leading or trailing zeros, empty for zero
+    digits: String,
+    // the power of ten of the first digit
+    exponent: i64,
+}
+
+impl Digits {
+    fn new(negative: bool, digits: &str, exponent: i64) -> Digits {
+        let trimmed = digits.trim_start_matches('0');
+        let exponent = exponent - (digits.len() - trimmed.len()) as i64;
+        let digits = trimmed.trim_end_matches('0');
+        Digits {
+            negative: negative && !digits.is_empty(),
+            digits: digits.to_string(),
+            exponent: if digits.is_empty() { 0 } else { exponent },
+        }
+    }
+
+    fn from_decimal(d: &Decimal) -> Digits {
+        let (mantissa, exponent) = d.parts();
+        let digits = mantissa.magnitude().to_string();
+        let exponent = exponent + digits.len() as i64 - 1;
+        Digits::new(mantissa.is_negative(), &digits, exponent)
+    }
+
+    // the value to at least significant digits, decimals are always exact
+    fn from_value(value: &Value, significant: usize) -> Digits {
+        let significant = significant.max(1);
+        match value {
+            Value::Float(x) => {
+                // eg. "1.2345e21"
+                let written = format!("{:.*e}", significant.min(FLOAT_DIGITS) - 1, x.abs());
+                let (mantissa, exponent) = written.split_once('e').unwrap();
+                Digits::new(x.is_sign_negative(), &mantissa.replace('.', ""), exponent.parse().unwrap())
+            }
+            Value::Rational(r) if r.is_integer() => Digits::from_decimal(&Decimal::from_integer(r.numer().clone())),
+            Value::Rational(r) => {
+                // cutting off the digits after the last one kept and then rounding half up on it
+                // gives the same result as rounding the exact fraction
+                let ctx = DecimalContext { digits: significant + 1, rounding: RoundingMode::Down };
+                Digits::from_decimal(&Decimal::from_rational(r, ctx)).keep(significant as i64)
+            }
+            Value::Decimal(d) => Digits::from_decimal(d),
+            Value::Complex(_) => unreachable!("complex numbers are formatted part by part"),
+        }
+    }
+
+    // rounds half away from zero to count significant digits
+    fn keep(self, count: i64) -> Digits {
+        if count >= self.digits.len() as i64 {
+            return self;
+        }
+        if count < 0 {
+            return Digits::new(false, "", 0);
+        }
+        let mut kept = self.digits.as_bytes()[..count as usize].to_vec();
+        let mut exponent = self.exponent;
+        if self.digits.as_bytes()[count as usize] >= b'5' {
+            // carry the one through any nines, so 9.96 to 2 digits is 10
+            let mut i = kept.len();
+            loop {
+                if i == 0 {
+                    kept.insert(0, b'1');
+                    exponent += 1;
+                    break;
+                }
+                i -= 1;
+                if kept[i] == b'9' {
+                    kept[i] = b'0';
+                } else {
+                    kept[i] += 1;
+                    break;
+                }
+            }
+        }
+        Digits::new(self.negative, std::str::from_utf8(&kept).unwrap(), exponent)
+    }
+
+    // rounds to places digits after the decimal point
+    fn keep_places(self, places: usize) -> Digits {
+        let count = self.exponent + 1 + places as i64;
+        self.keep(count)
+    }
+
+    // eg. 1234.5, padded with zeros to at least min_fraction digits after the point
+    fn plain(&self, min_fraction: usize, grouping: bool) -> String {
+        let (whole, mut fraction) = if self.exponent < 0 {
+            ("0".to_string(), format!("{}{}", "0".repeat((-self.exponent - 1) as usize), self.digits))
+        } else {
+            let whole_length = self.exponent as usize + 1;
+            if self.digits.len() <= whole_length {
+                (format!("{}{}", self.digits, "0".repeat(whole_length - self.digits.len())), String::new())
+            } else {
+                let (whole, fraction) = self.digits.split_at(whole_length);
+                (whole.to_string(), fraction.to_string())
+            }
+        };
+        if fraction.len() < min_fraction {
+            fraction.push_str(&"0".repeat(min_fraction - fraction.len()));
+        }
+        let whole = if grouping { group_thousands(&whole) } else { whole };
+        let sign = if self.negative { "-" } else { "" };
+        if fraction.is_empty() {
+            format!("{sign}{whole}")
+        } else {
+            format!("{sign}{whole}.{fraction}")
+        }
+    }
+
+    // eg. 1.2345e+4, the exponent is a multiple of step and the mantissa is padded with zeros to at
+    // least min_digits significant digits
+    fn scientific(&self, step: i64, min_digits: usize, si_prefixes: bool) -> String {
+        let exponent = self.exponent.div_euclid(step) * step;
+        let mantissa = Digits { exponent: self.exponent - exponent, ..self.clone() };
+        let min_fraction = (min_digits as i64 - mantissa.exponent - 1).max(0) as usize;
+        let mantissa = mantissa.plain(min_fraction, false);
+        let prefix = (exponent / 3 + 10) as usize;
+        if si_prefixes && exponent % 3 == 0 && prefix < SI_PREFIXES.len() {
+            return format!("{mantissa}{}", SI_PREFIXES[prefix]);
+        }
+        let sign = if exponent < 0 { "-" } else { "+" };
+        format!("{mantissa}e{sign}{}", exponent.abs())
+    }
+}
+
+// 1234567 is 1,234,567
+fn group_thousands(whole: &str) -> String {
+    let mut grouped = String::new();
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+// how many significant digits a fraction needs to be rounded to places after the point, at least
+fn digits_for_places(r: &BigRational, places: usize) -> usize {
+    let length = |n: &BigInt| n.magnitude().to_string().len() as i64;
+    (length(r.numer()) - length(r.denom()) + 2 + places as i64).max(1) as usize
+}
+
+pub(crate) fn format_value(value: &Value, options: &FormatOptions) -> String {
+    match value {
+        Value::Complex(z) => format_complex(*z, options),
+        _ => format_real(value, options),
+    }
+}
+
+fn format_real(value: &Value, options: &FormatOptions) -> String {
+    if let Value::Float(x) = value {
+        if !x.is_finite() {
+            return x.to_string();
+        }
+    }
+    let grouping = options.grouping;
+    match options.number_format {
+        NumberFormat::Auto => match (value, options.rational_display) {
+            (Value::Rational(r), RationalDisplay::Fraction) if !r.is_integer() => {
+                let numer = Digits::from_decimal(&Decimal::from_integer(r.numer().clone()));
+                let denom = Digits::from_decimal(&Decimal::from_integer(r.denom().clone()));
+                format!("{}/{}", numer.plain(0, grouping), denom.plain(0, grouping))
+            }
+            // integers are always shown in full
+            (Value::Rational(_), RationalDisplay::Fraction) => Digits::from_value(value, 1).plain(0, grouping),
+            (Value::Rational(r), RationalDisplay::Decimal(places)) => {
+                Digits::from_value(value, digits_for_places(r, places)).keep_places(places).plain(0, grouping)
+            }
+            _ => {
+                let digits = Digits::from_value(value, SHOWN_DIGITS);
+                if PLAIN_EXPONENTS.contains(&digits.exponent) {
+                    digits.plain(0, grouping)
+                } else {
+                    digits.scientific(1, 0, false)
+                }
+            }
+        },
+        NumberFormat::Fixed(places) => {
+            let significant = match value {
+                Value::Rational(r) => digits_for_places(r, places),
+                _ => FLOAT_DIGITS,
+            };
+            Digits::from_value(value, significant).keep_places(places).plain(places, grouping)
+        }
+        NumberFormat::Scientific => Digits::from_value(value, SHOWN_DIGITS).scientific(1, 0, false),
+        NumberFormat::Engineering { si_prefixes } => Digits::from_value(value, SHOWN_DIGITS).scientific(3, 0, si_prefixes),
+        NumberFormat::SignificantFigures(count) => {
+            let count = count.max(1);
+            let digits = Digits::from_value(value, count).keep(count as i64);
+            if digits.digits.is_empty() {
+                return "0".to_string();
+            }
+            if PLAIN_EXPONENTS.contains(&digits.exponent) {
+                // trailing zeros are significant, so 2.5 to 3 figures is 2.50
+                let min_fraction = (count as i64 - digits.exponent - 1).max(0) as usize;
+                digits.plain(min_fraction, grouping)
+            } else {
+                digits.scientific(1, count, false)
+            }
+        }
+    }
+}
+
+fn format_complex(z: Complex64, options: &FormatOptions) -> String {
+    let part = |x: f64| format_real(&Value::Float(x), options);
+    match options.complex_display {
+        ComplexDisplay::Rectangular => {
+            // i and -i rather than 1i and -1i
+            let im = part(z.im.abs());
+            let im = if im == "1" { String::new() } else { im };
+            let sign = if z.im < 0.0 { "-" } else { "+" };
+            if z.re == 0.0 {
+                format!("{}{im}i", if z.im < 0.0 { "-" } else { "" })
+            } else {
+                format!("{} {sign} {im}i", part(z.re))
+            }
+        }
+        ComplexDisplay::Polar => format!("{} ∠ {}", part(z.norm()), part(options.angle_unit.radians_to_unit(z.arg()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathengine::core::{Calc, Calculator};
+    use crate::mathengine::error::ErrorKind;
+    use crate::mathengine::testing::{error, feedback, shown};
+    use crate::mathengine::value::NumberMode;
+
+    fn showing(format: NumberFormat, cases: &[(&str, &str)]) {
+        let mut calc = Calculator::new();
+        calc.set_number_format(format);
+        for (input, expected) in cases {
+            assert_eq!(shown(&mut calc, input), *expected, "{input} in {format:?}");
+        }
+    }
+
+    #[test]
+    fn auto() {
+        showing(
+            NumberFormat::Auto,
+            &[
+                ("0.1 + 0.2", "0.3"),
+                ("1/3", "0.333333333333333"),
+                ("1e21", "1000000000000000000000"),
+                ("1e-9", "1e-9"),
+                ("-2.5", "-2.5"),
+            ],
+        );
+    }
+
+    #[test]
+    fn fixed_places() {
+        showing(NumberFormat::Fixed(2), &[("pi", "3.14"), ("1234567.891", "1234567.89"), ("2", "2.00"), ("0.005", "0.01")]);
+        showing(NumberFormat::Fixed(0), &[("2.5", "3"), ("-1.4", "-1")]);
+    }
+
+    #[test]
+    fn scientific() {
+        showing(NumberFormat::Scientific, &[("12345", "1.2345e+4"), ("-0.00012", "-1.2e-4"), ("0", "0e+0"), ("1", "1e+0")]);
+    }
+
+    #[test]
+    fn engineering() {
+        let plain = NumberFormat::Engineering { si_prefixes: false };
+        showing(plain, &[("12345", "12.345e+3"), ("0.00012", "120e-6"), ("1", "1e+0")]);
+        let si = NumberFormat::Engineering { si_prefixes: true };
+        // past the largest prefix the exponent is written out
+        showing(si, &[("12345", "12.345k"), ("0.00012", "120µ"), ("2.5e9", "2.5G"), ("1", "1"), ("1e40", "10e+39")]);
+    }
+
+    #[test]
+    fn significant_figures() {
+        let cases = [("pi", "3.14"), ("123456", "123000"), ("0.000123456", "0.000123"), ("-2", "-2.00")];
+        showing(NumberFormat::SignificantFigures(3), &cases);
+    }
+
+    #[test]
+    fn thousands_grouping() {
+        let mut calc = Calculator::new();
+        calc.set_digit_grouping(true);
+        assert_eq!(shown(&mut calc, "1234567"), "1,234,567");
+        assert_eq!(shown(&mut calc, "-1234.5"), "-1,234.5");
+        assert_eq!(shown(&mut calc, "999"), "999");
+        calc.set_number_format(NumberFormat::Fixed(2));
+        assert_eq!(shown(&mut calc, "1234567.891"), "1,234,567.89");
+        calc.eval("display group off").unwrap();
+        assert!(!calc.digit_grouping());
+        assert_eq!(shown(&mut calc, "1234567.891"), "1234567.89");
+    }
+
+    #[test]
+    fn exact_values_in_each_format() {
+        let mut calc = Calculator::new();
+        calc.set_number_mode(NumberMode::Exact);
+        assert_eq!(shown(&mut calc, "2^70"), "1180591620717411303424");
+        calc.set_number_format(NumberFormat::Fixed(1));
+        assert_eq!(shown(&mut calc, "1/3"), "0.3");
+        calc.set_number_format(NumberFormat::Scientific);
+        assert_eq!(shown(&mut calc, "2^70"), "1.180591620717411303424e+21");
+    }
+
+    #[test]
+    fn commands_set_the_format_and_listings_use_it() {
+        let mut calc = Calculator::new();
+        for (command, format) in [
+            ("display fixed 1", NumberFormat::Fixed(1)),
+            ("display sci", NumberFormat::Scientific),
+            ("display eng", NumberFormat::Engineering { si_prefixes: false }),
+            ("display eng si", NumberFormat::Engineering { si_prefixes: true }),
+            ("display sig 4", NumberFormat::SignificantFigures(4)),
+            ("display auto", NumberFormat::Auto),
+        ] {
+            calc.eval(command).unwrap();
+            assert_eq!(calc.number_format(), format, "{command}");
+        }
+        calc.eval("x = 1234.5678").unwrap();
+        calc.eval("display fixed 1").unwrap();
+        assert_eq!(feedback(&mut calc, "show x"), "x = 1234.6");
+        assert!(feedback(&mut calc, "vars").ends_with("x = 1234.6"));
+    }
+
+    #[test]
+    fn places_and_figures_are_capped() {
+        let mut calc = Calculator::new();
+        for command in ["display fixed 100000000", "display sig 100000000"] {
+            assert!(matches!(error(&mut calc, command), ErrorKind::CommandUsage(_)), "{command}");
+        }
+        assert_eq!(calc.number_format(), NumberFormat::Auto);
+        calc.eval("display fixed 1000").unwrap();
+        assert_eq!(calc.number_format(), NumberFormat::Fixed(1000));
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+
+use super::value::Value;
+
+// a previous answer and the input that produced it
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    pub input: String,
+    pub value: Value,
+}
+
+// the most recent answers, newest first, capped at capacity entries
+pub struct History {
+    entries: VecDeque<HistoryEntry>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        History {
+            entries: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, input: &str, value: Value) {
+        self.entries.push_front(HistoryEntry {
+            input: input.trim().to_string(),
+            value,
+        });
+        self.entries.truncate(self.capacity);
+    }
+
+    // 1 is the most recent answer
+    pub fn get(&self, index: usize) -> Option<&HistoryEntry> {
+        index.checked_sub(1).and_then(|i| self.entries.get(i))
+    }
+
+    // newest first
+    pub fn iter(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // drops the oldest answers that no longer fit
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.entries.truncate(capacity);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+// ans is the most recent answer, same as ans1, ans2 is the one before it and so on
+pub fn answer_index(name: &[u8]) -> Option<usize> {
+    let digits = name.strip_prefix(b"ans")?;
+    if digits.is_empty() {
+        return Some(1);
+    }
+    if !digits.iter().all(u8::is_ascii_digit) || digits[0] == b'0' {
+        return None;
+    }
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathengine::core::{Calc, Calculator};
+    use crate::mathengine::error::ErrorKind;
+    use crate::mathengine::testing::{error, eval};
+
+    #[test]
+    fn answer_names() {
+        assert_eq!(answer_index(b"ans"), Some(1));
+        assert_eq!(answer_index(b"ans1"), Some(1));
+        assert_eq!(answer_index(b"ans12"), Some(12));
+        assert_eq!(answer_index(b"ans0"), None);
+        assert_eq!(answer_index(b"ans01"), None);
+        assert_eq!(answer_index(b"answer"), None);
+        assert_eq!(answer_index(b"an"), None);
+    }
+
+    #[test]
+    fn newest_first_and_capped() {
+        let mut history = History::new(2);
+        history.push(" 1+1 ", Value::Float(2.0));
+        history.push("2*3", Value::Float(6.0));
+        history.push("3^2", Value::Float(9.0));
+        assert_eq!(history.get(1).unwrap().value, Value::Float(9.0));
+        assert_eq!(history.get(2).unwrap().input, "2*3");
+        assert!(history.get(3).is_none());
+        assert!(history.get(0).is_none());
+        history.set_capacity(1);
+        assert_eq!(history.iter().count(), 1);
+    }
+
+    #[test]
+    fn answers_can_be_used_but_not_assigned() {
+        let mut calc = Calculator::new();
+        calc.eval("1+1").unwrap();
+        calc.eval("2*5").unwrap();
+        assert_eq!(eval(&mut calc, "ans + ans2"), Value::Float(12.0));
+        assert!(matches!(error(&mut calc, "ans1 = 3"), ErrorKind::VarReadOnly(_)));
+        assert!(matches!(error(&mut calc, "ans = 5"), ErrorKind::VarReadOnly(_)));
+        assert!(matches!(error(&mut calc, "const ans2 = 5"), ErrorKind::VarReadOnly(_)));
+        assert!(calc.set_variable("ans3", 1.0).is_err());
+        assert_eq!(calc.variable("ans1"), Some(Value::Float(12.0)));
+        assert!(matches!(error(&mut calc, "ans9"), ErrorKind::VarDoesNotExist(_)));
+    }
+}
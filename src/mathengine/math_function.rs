@@ -1,55 +1,63 @@
-use std::f64::consts::PI;
-
 use crate::mathengine::token::Token::*;
 
 use super::{
+    builtin::{get_builtin, Arity},
     core::Calculator,
-    error::CalcError,
+    error::{CalcError, ErrorKind},
+    expr::Expr,
+    parse::parse,
     solve::Solver,
-    token::{Token, TokenHandling},
+    token::{SpannedToken, TokenHandling},
+    value::Value,
 };
 
-#[derive(Clone)]
 pub struct Function {
     pub name: Vec<u8>,
     pub arg_count: usize,
-    pub func: Vec<Token>,
+    pub func: Expr,
+    // the definition, eg. f(x) = x^2, with any captured values written in
+    pub source: String,
 }
 
 impl Function {
-    fn new() -> Function {
-        Function {
-            name: Vec::new(),
-            arg_count: 0,
-            func: Vec::new(),
-        }
+    pub fn arity(&self) -> Arity {
+        Arity::Exact(self.arg_count)
     }
 }
 
+pub type NativeFn = Box<dyn Fn(&[f64]) -> Result<f64, String>>;
+
+// a function written in rust and registered by the application embedding the calculator
+pub struct NativeFunction {
+    pub arity: Arity,
+    pub func: NativeFn,
+}
+
 pub trait FunctionHandling {
     fn create_function(
         &mut self,
-        lhs: &mut Vec<Token>,
-        rhs: &mut Vec<Token>,
+        input: &str,
+        lhs: &mut [SpannedToken],
+        rhs: &mut [SpannedToken],
     ) -> Result<Function, CalcError>;
-    fn solve_function(&mut self, name: &[u8], args: &[Token], depth: i32) -> Result<Token, CalcError>;
+    fn solve_function(&self, name: &[u8], args: &[Value]) -> Result<Value, CalcError>;
 }
 
 impl FunctionHandling for Calculator {
     fn create_function(
         &mut self,
-        lhs: &mut Vec<Token>,
-        rhs: &mut Vec<Token>,
+        input: &str,
+        lhs: &mut [SpannedToken],
+        rhs: &mut [SpannedToken],
     ) -> Result<Function, CalcError> {
         let mut arg_count: usize = 0;
-        let mut func = Function::new();
-        for i in 0..lhs.len() {
-            if let Variable(arg_name) = &lhs[i] {
+        for spanned in lhs.iter() {
+            if let Variable(arg_name) = &spanned.token {
                 // replace cooresponding rhs variable(s) with a function argument index
-                for j in 0..rhs.len() {
-                    if let Variable(var_name) = &rhs[j] {
+                for rhs_token in rhs.iter_mut() {
+                    if let Variable(var_name) = &rhs_token.token {
                         if arg_name == var_name {
-                            rhs[j] = FunctionArg(arg_count);
+                            rhs_token.token = FunctionArg(arg_count);
                         }
                     }
                 }
@@ -58,58 +66,125 @@ impl FunctionHandling for Calculator {
             }
         }
 
-        self.resolve_variables(rhs)?;
+        // other variables are looked up each time the function is called, unless captured with $
+        let unresolved = rhs.to_vec();
+        self.resolve_captures(rhs)?;
 
-        // assign proper values to func
-        if let FunctionName(func_name) = &lhs[0] {
-            func.name = func_name.clone();
-        } else {
-            return Err(CalcError::FuncNoName);
-        }
-        func.arg_count = arg_count;
-        func.func = rhs.to_vec();
+        let name = match &lhs[0].token {
+            FunctionName(func_name) => func_name.clone(),
+            _ => return Err(CalcError::new(ErrorKind::FuncNoName, lhs[0].span)),
+        };
 
-        Ok(func)
+        Ok(Function {
+            name,
+            arg_count,
+            func: parse(rhs)?,
+            source: function_source(input, lhs, &unresolved, rhs),
+        })
     }
 
-    fn solve_function(&mut self, name: &[u8], arg_slice: &[Token], depth: i32) -> Result<Token, CalcError> {
-        let mut args: Vec<f64> = Vec::new();
+    fn solve_function(&self, name: &[u8], args: &[Value]) -> Result<Value, CalcError> {
+        if let Some(builtin) = get_builtin(name) {
+            if !builtin.arity.accepts(args.len()) {
+                return Err(ErrorKind::FuncIncorrectArgCount(String::from_utf8_lossy(name).into_owned(), builtin.arity, args.len()).into());
+            }
+            if !builtin.complex && args.iter().any(Value::is_complex) {
+                return Err(ErrorKind::FuncComplexArg(String::from_utf8_lossy(name).into_owned()).into());
+            }
+            return (builtin.func)(self, args);
+        }
 
-        for arg in arg_slice.split(|t| t == &Comma) {
-            if let Number(n) = self.solve(arg.to_vec(), depth + 1)?[0].clone() {
-                args.push(n);
+        if let Some(native) = self.native_functions.get(name) {
+            if !native.arity.accepts(args.len()) {
+                return Err(ErrorKind::FuncIncorrectArgCount(String::from_utf8_lossy(name).into_owned(), native.arity, args.len()).into());
+            }
+            // registered functions work with floats
+            if args.iter().any(Value::is_complex) {
+                return Err(ErrorKind::FuncComplexArg(String::from_utf8_lossy(name).into_owned()).into());
             }
+            let args: Vec<f64> = args.iter().map(Value::to_f64).collect();
+            return (native.func)(&args)
+                .map(Value::Float)
+                .map_err(|msg| ErrorKind::FuncFailed(String::from_utf8_lossy(name).into_owned(), msg).into());
         }
 
-        let angle_mode = if self.use_radians { 1.0 } else { PI / 180.0 };
-        match name {
-            b"sin" => return Ok(Number((args[0] * angle_mode).sin())),
-            b"cos" => return Ok(Number((args[0] * angle_mode).cos())),
-            b"tan" => return Ok(Number((args[0] * angle_mode).tan())),
-            b"asin" => return Ok(Number((args[0] * angle_mode).asin())),
-            b"acos" => return Ok(Number((args[0] * angle_mode).acos())),
-            b"atan" => return Ok(Number((args[0] * angle_mode).atan())),
-            b"sqrt" => return Ok(Number((args[0]).sqrt())),
-            b"log" => return Ok(Number((args[0]).log(10.0))),
-            b"ln" => return Ok(Number((args[0]).log2())),
-            _ => match self.functions.get(name) {
-                Some(function) => {
-                    if function.arg_count != args.len() {
-                        return Err(CalcError::FuncIncorrectArgCount(function.arg_count));
-                    }
-                    let mut expr = function.func.clone();
-                    for token in &mut expr {
-                        if let FunctionArg(index) = token {
-                            *token = Number(args[*index]);
-                        }
-                    }
-                    match self.solve(expr, depth + 1) {
-                        Ok(answer) => return Ok(answer[0].clone()),
-                        Err(e) => return Err(e),
-                    };
+        match self.functions.get(name) {
+            Some(function) => {
+                if function.arg_count != args.len() {
+                    return Err(ErrorKind::FuncIncorrectArgCount(String::from_utf8_lossy(name).into_owned(), function.arity(), args.len()).into());
                 }
-                None => return Err(CalcError::FuncDoesNotExist(name.to_vec())),
-            },
+                self.solve(&function.func, args)
+            }
+            None => Err(ErrorKind::FuncDoesNotExist(String::from_utf8_lossy(name).into_owned()).into()),
         }
     }
 }
+
+// the definition as it was written, with each $name replaced by the value it captured so that
+// defining it again gives the same function
+fn function_source(input: &str, lhs: &[SpannedToken], unresolved: &[SpannedToken], resolved: &[SpannedToken]) -> String {
+    let input = input.as_bytes();
+    let mut source = Vec::new();
+    let mut copied = lhs[0].span.start;
+    for (token, value) in unresolved.iter().zip(resolved) {
+        if let (Captured(_), Number(value)) = (&token.token, &value.token) {
+            source.extend_from_slice(&input[copied..token.span.start]);
+            source.extend_from_slice(format!("({value})").as_bytes());
+            copied = token.span.end;
+        }
+    }
+    source.extend_from_slice(&input[copied..]);
+    String::from_utf8_lossy(&source).trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mathengine::core::{Calc, Calculator};
+    use crate::mathengine::error::ErrorKind;
+    use crate::mathengine::testing::{error, eval, feedback};
+    use crate::mathengine::value::Value;
+
+    #[test]
+    fn variables_are_read_when_called() {
+        let mut calc = Calculator::new();
+        calc.eval("k = 2").unwrap();
+        calc.eval("f(x) = x * k").unwrap();
+        calc.eval("k = 3").unwrap();
+        assert_eq!(eval(&mut calc, "f(2)"), Value::Float(6.0));
+        // a variable can be defined after the function that uses it
+        calc.eval("h(x) = later * x").unwrap();
+        assert!(matches!(error(&mut calc, "h(2)"), ErrorKind::VarDoesNotExist(_)));
+        calc.eval("later = 4").unwrap();
+        assert_eq!(eval(&mut calc, "h(2)"), Value::Float(8.0));
+        // parameters shadow variables of the same name
+        calc.eval("x = 100").unwrap();
+        assert_eq!(eval(&mut calc, "f(1)"), Value::Float(3.0));
+        // so each parameter needs its own name
+        assert!(matches!(error(&mut calc, "g(x, x) = x"), ErrorKind::FuncDuplicateArg(_)));
+        assert_eq!(calc.function_source("g"), None);
+    }
+
+    #[test]
+    fn captures_keep_the_value_at_definition() {
+        let mut calc = Calculator::new();
+        calc.eval("k = -1.5").unwrap();
+        calc.eval("p(x) = x - $k").unwrap();
+        calc.eval("k = 10").unwrap();
+        assert_eq!(eval(&mut calc, "p(1)"), Value::Float(2.5));
+        assert_eq!(calc.function_source("p").as_deref(), Some("p(x) = x - (-1.5)"));
+        assert!(matches!(error(&mut calc, "q(x) = x * $nope"), ErrorKind::VarDoesNotExist(_)));
+        assert!(calc.function_source("q").is_none());
+    }
+
+    #[test]
+    fn free_variables_are_listed() {
+        let mut calc = Calculator::new();
+        calc.eval("k = 1").unwrap();
+        calc.eval("f(x) = x * k + y + sin(30deg) + $k").unwrap();
+        assert_eq!(calc.function_variables("f"), Some(vec!["k".to_string(), "y".to_string()]));
+        assert_eq!(feedback(&mut calc, "deps f"), "f depends on k, y");
+        calc.eval("g(x) = x^2").unwrap();
+        assert_eq!(feedback(&mut calc, "deps g"), "g has no free variables");
+        assert!(matches!(error(&mut calc, "deps nope"), ErrorKind::FuncDoesNotExist(_)));
+    }
+}
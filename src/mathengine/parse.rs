@@ -0,0 +1,387 @@
+use super::builtin::{IF_ARITY, IF_NAME};
+use super::error::{CalcError, ErrorKind};
+use super::expr::{BinaryOp, Expr, ExprKind, UnaryOp};
+use super::token::{
+    Span, SpannedToken,
+    Token::{self, *},
+};
+
+// turns a token list into an expression tree
+//
+// an operand written directly after another operand is multiplied with it,
+// eg. 2pi, 3(x+1), (a)(b), 2sin(x). this implicit multiplication has the same
+// precedence as '*' and is left associative, so:
+//   1/2x     is (1/2)*x, not 1/(2x)
+//   2^3x     is (2^3)*x
+//   2sin(x)^2 is 2*(sin(x)^2)
+//   -2x      is (-2)*x
+// a name followed by '(' is always a function call, so x(1+2) calls x, use x*(1+2) instead
+// a number directly after e is an exponent, so 2e3 is 2000 but 2e is 2*e
+pub fn parse(tokens: &[SpannedToken]) -> Result<Expr, CalcError> {
+    if tokens.is_empty() {
+        return Err(ErrorKind::NoInput.into());
+    }
+    Parser {
+        tokens,
+        pos: 0,
+        operands: Vec::new(),
+        pending: Vec::new(),
+    }
+    .parse()
+}
+
+// an operator or bracket whose operands have not all been parsed yet
+enum Pending {
+    Unary(UnaryOp, Span),
+    Binary(BinaryOp),
+    // an opening bracket and its span
+    Group(Span),
+    // a function name, its span and the args parsed so far
+    Call(Vec<u8>, Span, Vec<Expr>),
+}
+
+impl Pending {
+    // an operator is applied before a following binary operator that does not bind tighter,
+    // a right associative operator lets another of itself bind first
+    fn binding(&self) -> Option<u8> {
+        match self {
+            Pending::Unary(..) => Some(UnaryOp::PRECEDENCE),
+            Pending::Binary(op) if op.is_right_assoc() => Some(op.precedence() - 1),
+            Pending::Binary(op) => Some(op.precedence()),
+            Pending::Group(_) | Pending::Call(..) => None,
+        }
+    }
+}
+
+// operator precedence parsing with explicit stacks, so deeply nested input such as
+// ((((1)))) or ----1 does not recurse on the rust stack
+struct Parser<'a> {
+    tokens: &'a [SpannedToken],
+    pos: usize,
+    operands: Vec<Expr>,
+    pending: Vec<Pending>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos).map(|t| &t.token)
+    }
+
+    fn next(&mut self) -> Option<&'a SpannedToken> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    // an error pointing at the current token, or the last token if all have been consumed
+    fn error(&self, kind: ErrorKind) -> CalcError {
+        let index = self.pos.min(self.tokens.len() - 1);
+        CalcError::new(kind, self.tokens[index].span)
+    }
+
+    fn parse(mut self) -> Result<Expr, CalcError> {
+        loop {
+            self.parse_operand()?;
+
+            // binary operators, an operand written directly after another is multiplied with it
+            loop {
+                let op = match self.peek() {
+                    Some(token) if starts_operand(token) => Some(BinaryOp::Mul),
+                    Some(token) => BinaryOp::from_token(token).inspect(|_| self.pos += 1),
+                    None => None,
+                };
+                if let Some(op) = op {
+                    self.reduce(op.precedence());
+                    self.pending.push(Pending::Binary(op));
+                    break;
+                }
+
+                // anything else ends the operand that is open, then closes a bracket or an arg
+                self.reduce(0);
+                match (self.pending.pop(), self.peek()) {
+                    (None, None) => return Ok(self.operands.pop().unwrap()),
+                    // every token must be consumed, otherwise something was left dangling
+                    (None, Some(RightBracket)) => return Err(self.error(ErrorKind::ImbalancedBrackets)),
+                    (None, Some(Comma)) => return Err(self.error(ErrorKind::MisplacedComma)),
+                    (None, Some(_)) => return Err(self.error(ErrorKind::InvalidTokenSeq)),
+                    (Some(Pending::Group(left_span)), Some(RightBracket)) => {
+                        let right_span = self.next().unwrap().span;
+                        // the brackets are part of the expression, so errors underline them too
+                        self.operands.last_mut().unwrap().span = left_span.to(right_span);
+                    }
+                    (Some(Pending::Group(_)), Some(Comma)) => return Err(self.error(ErrorKind::MisplacedComma)),
+                    (Some(Pending::Group(_)), _) => return Err(self.error(ErrorKind::ImbalancedBrackets)),
+                    (Some(Pending::Call(name, span, mut args)), Some(RightBracket)) => {
+                        args.push(self.operands.pop().unwrap());
+                        let right_span = self.next().unwrap().span;
+                        self.operands.push(call(name, args, span.to(right_span))?);
+                    }
+                    (Some(Pending::Call(name, span, mut args)), Some(Comma)) => {
+                        args.push(self.operands.pop().unwrap());
+                        self.pos += 1;
+                        self.pending.push(Pending::Call(name, span, args));
+                        self.expect_arg()?;
+                        break;
+                    }
+                    (Some(Pending::Call(..)), None) => return Err(self.error(ErrorKind::ImbalancedBrackets)),
+                    (Some(Pending::Call(..)), Some(_)) => return Err(self.error(ErrorKind::FuncExpectedComma)),
+                    (Some(Pending::Unary(..) | Pending::Binary(_)), _) => unreachable!("reduce applies every operator"),
+                }
+            }
+        }
+    }
+
+    // parses the unary operators and opening brackets in front of an operand, and the operand
+    fn parse_operand(&mut self) -> Result<(), CalcError> {
+        loop {
+            let Some(spanned) = self.next() else {
+                return Err(self.error(ErrorKind::InvalidLastToken(self.tokens[self.tokens.len() - 1].token.to_string())));
+            };
+            let span = spanned.span;
+            if let Some(op) = UnaryOp::from_token(&spanned.token) {
+                self.pending.push(Pending::Unary(op, span));
+                continue;
+            }
+            let expr = match &spanned.token {
+                Number(n) => Expr::new(ExprKind::Number(n.clone()), span),
+                // outside of a function definition a capture is just the variables current value
+                Variable(name) | Captured(name) => Expr::new(ExprKind::Variable(name.clone()), span),
+                FunctionArg(index) => Expr::new(ExprKind::FunctionArg(*index), span),
+                LeftBracket => {
+                    self.pending.push(Pending::Group(span));
+                    continue;
+                }
+                FunctionName(name) => {
+                    if self.peek() != Some(&LeftBracket) {
+                        return Err(self.error(ErrorKind::FuncArgsNotInBrackets));
+                    }
+                    self.pos += 1;
+                    if self.peek() == Some(&RightBracket) {
+                        let right_span = self.next().unwrap().span;
+                        call(name.clone(), Vec::new(), span.to(right_span))?
+                    } else {
+                        self.pending.push(Pending::Call(name.clone(), span, Vec::new()));
+                        self.expect_arg()?;
+                        continue;
+                    }
+                }
+                _ => return Err(CalcError::new(ErrorKind::InvalidTokenSeq, span)),
+            };
+            self.operands.push(expr);
+            return Ok(());
+        }
+    }
+
+    // after an opening bracket or a comma of a function call
+    fn expect_arg(&self) -> Result<(), CalcError> {
+        match self.peek() {
+            Some(Comma) | Some(RightBracket) => Err(self.error(ErrorKind::FuncExpectedArg)),
+            _ => Ok(()),
+        }
+    }
+
+    // applies the pending operators that bind at least as tightly as precedence
+    fn reduce(&mut self, precedence: u8) {
+        while self.pending.last().and_then(Pending::binding).is_some_and(|binding| binding >= precedence) {
+            let expr = match self.pending.pop().unwrap() {
+                Pending::Unary(op, op_span) => {
+                    let operand = self.operands.pop().unwrap();
+                    let span = op_span.to(operand.span);
+                    Expr::new(ExprKind::Unary(op, Box::new(operand)), span)
+                }
+                Pending::Binary(op) => {
+                    let rhs = self.operands.pop().unwrap();
+                    let lhs = self.operands.pop().unwrap();
+                    let span = lhs.span.to(rhs.span);
+                    Expr::new(ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)), span)
+                }
+                Pending::Group(_) | Pending::Call(..) => unreachable!("brackets have no binding"),
+            };
+            self.operands.push(expr);
+        }
+    }
+}
+
+fn call(name: Vec<u8>, args: Vec<Expr>, span: Span) -> Result<Expr, CalcError> {
+    if name == IF_NAME {
+        return parse_if(args, span);
+    }
+    Ok(Expr::new(ExprKind::Call(name, args), span))
+}
+
+// if is written like a function, but only the branch it picks is solved
+fn parse_if(args: Vec<Expr>, span: Span) -> Result<Expr, CalcError> {
+    match <[Expr; 3]>::try_from(args) {
+        Ok([condition, then, otherwise]) => {
+            let kind = ExprKind::If(Box::new(condition), Box::new(then), Box::new(otherwise));
+            Ok(Expr::new(kind, span))
+        }
+        Err(args) => {
+            let kind = ErrorKind::FuncIncorrectArgCount(String::from_utf8_lossy(IF_NAME).into_owned(), IF_ARITY, args.len());
+            Err(CalcError::new(kind, span))
+        }
+    }
+}
+
+fn starts_operand(token: &Token) -> bool {
+    matches!(token, Number(_) | Variable(_) | Captured(_) | FunctionArg(_) | FunctionName(_) | LeftBracket)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathengine::core::{Calc, Calculator};
+    use crate::mathengine::testing::{answer, eval};
+    use crate::mathengine::token::tokenize;
+    use crate::mathengine::value::Value;
+
+    // the tree written out with prefix operators, eg. (+ 1 (* 2 3))
+    fn tree(input: &str) -> String {
+        fn write(expr: &Expr) -> String {
+            match &expr.kind {
+                ExprKind::Number(n) => n.to_string(),
+                ExprKind::Variable(name) => String::from_utf8_lossy(name).into_owned(),
+                ExprKind::FunctionArg(index) => format!("#{index}"),
+                ExprKind::Unary(op, operand) => format!("({op:?} {})", write(operand)),
+                ExprKind::Binary(op, lhs, rhs) => format!("({} {} {})", op.symbol(), write(lhs), write(rhs)),
+                ExprKind::Call(name, args) => {
+                    let args: Vec<String> = args.iter().map(write).collect();
+                    format!("({} {})", String::from_utf8_lossy(name), args.join(" "))
+                }
+                ExprKind::If(condition, then, otherwise) => {
+                    format!("(if {} {} {})", write(condition), write(then), write(otherwise))
+                }
+            }
+        }
+        write(&parse(&tokenize(input.as_bytes()).unwrap()).unwrap())
+    }
+
+    fn parse_error(input: &str) -> ErrorKind {
+        parse(&tokenize(input.as_bytes()).unwrap()).unwrap_err().kind
+    }
+
+    #[test]
+    fn builds_a_tree() {
+        assert_eq!(tree("1 + 2 * 3"), "(+ 1 (* 2 3))");
+        assert_eq!(tree("(1 + 2) * 3"), "(* (+ 1 2) 3)");
+        assert_eq!(tree("8 / 4 / 2"), "(/ (/ 8 4) 2)");
+        assert_eq!(tree("x - y % 2"), "(- x (% y 2))");
+        assert_eq!(tree("max(x, 2, sin(y))"), "(max x 2 (sin y))");
+        assert_eq!(tree("f()"), "(f )");
+        assert_eq!(tree("if(x < 1, 2, 3)"), "(if (< x 1) 2 3)");
+    }
+
+    #[test]
+    fn misplaced_operators_are_errors() {
+        assert!(matches!(parse_error("2 *"), ErrorKind::InvalidLastToken(_)));
+        assert!(matches!(parse_error("* 2"), ErrorKind::InvalidTokenSeq));
+        assert!(matches!(parse_error("(1 + 2"), ErrorKind::ImbalancedBrackets));
+        assert!(matches!(parse_error("1 + 2)"), ErrorKind::ImbalancedBrackets));
+        assert!(matches!(parse_error("(1, 2)"), ErrorKind::MisplacedComma));
+        assert!(matches!(parse_error("1, 2"), ErrorKind::MisplacedComma));
+        assert!(matches!(parse_error("f(, 1)"), ErrorKind::FuncExpectedArg));
+        assert!(matches!(parse_error("f(1,)"), ErrorKind::FuncExpectedArg));
+        assert!(matches!(parse_error("f(1"), ErrorKind::ImbalancedBrackets));
+        assert!(matches!(parse_error("if(1, 2)"), ErrorKind::FuncIncorrectArgCount(..)));
+        assert!(matches!(parse(&[]).unwrap_err().kind, ErrorKind::NoInput));
+    }
+
+    #[test]
+    fn powers_are_right_associative() {
+        assert_eq!(tree("2 ^ 3 ^ 2"), "(^ 2 (^ 3 2))");
+        assert_eq!(answer("2^3^2"), Value::Float(512.0));
+        assert_eq!(tree("2 ^ -3 ^ 2"), "(^ 2 (Negate (^ 3 2)))");
+    }
+
+    #[test]
+    fn unary_signs_bind_looser_than_powers() {
+        assert_eq!(tree("-2 ^ 2"), "(Negate (^ 2 2))");
+        assert_eq!(answer("-2^2"), Value::Float(-4.0));
+        assert_eq!(answer("(-2)^2"), Value::Float(4.0));
+        assert_eq!(tree("-2 * 3"), "(* (Negate 2) 3)");
+        assert_eq!(tree("2 * -3"), "(* 2 (Negate 3))");
+        assert_eq!(tree("2 ^ -1"), "(^ 2 (Negate 1))");
+    }
+
+    #[test]
+    fn comparisons_and_logic_bind_looser_than_arithmetic() {
+        assert_eq!(tree("1 + 1 == 2"), "(== (+ 1 1) 2)");
+        assert_eq!(tree("a < b == c >= d"), "(== (< a b) (>= c d))");
+        assert_eq!(tree("a || b && c == d"), "(|| a (&& b (== c d)))");
+        assert_eq!(tree("a && b || c"), "(|| (&& a b) c)");
+        assert_eq!(tree("1 | 2 == 3"), "(== (| 1 2) 3)");
+        assert_eq!(tree("!a + 1"), "(+ (Not a) 1)");
+        assert_eq!(tree("!a == b"), "(== (Not a) b)");
+    }
+
+    #[test]
+    fn unary_signs_take_any_operand() {
+        assert_eq!(tree("-x ^ 2"), "(Negate (^ x 2))");
+        assert_eq!(tree("-sin(x)"), "(Negate (sin x))");
+        assert_eq!(tree("-(1 + x)"), "(Negate (+ 1 x))");
+        assert_eq!(tree("+-+3"), "(Plus (Negate (Plus 3)))");
+        assert_eq!(answer("--2"), Value::Float(2.0));
+        let mut calc = Calculator::new();
+        calc.eval("x = 3").unwrap();
+        assert_eq!(eval(&mut calc, "-x^2"), Value::Float(-9.0));
+        assert_eq!(eval(&mut calc, "2 - -x"), Value::Float(5.0));
+    }
+
+    #[test]
+    fn juxtaposed_operands_are_multiplied() {
+        assert_eq!(tree("2pi"), "(* 2 pi)");
+        assert_eq!(tree("3(x + 1)"), "(* 3 (+ x 1))");
+        assert_eq!(tree("(a)(b)"), "(* a b)");
+        assert_eq!(tree("2sin(x)"), "(* 2 (sin x))");
+        assert_eq!(tree("2 x y"), "(* (* 2 x) y)");
+        assert_eq!(answer("2(3)(4)"), Value::Float(24.0));
+    }
+
+    // the edge cases listed at the top of the file
+    #[test]
+    fn implicit_multiplication_edge_cases() {
+        assert_eq!(tree("1/2x"), "(* (/ 1 2) x)");
+        assert_eq!(tree("2^3x"), "(* (^ 2 3) x)");
+        assert_eq!(tree("2sin(x)^2"), "(* 2 (^ (sin x) 2))");
+        assert_eq!(tree("-2x"), "(* (Negate 2) x)");
+        assert_eq!(tree("x(1 + 2)"), "(x (+ 1 2))");
+        assert_eq!(tree("2e3"), "2000");
+        assert_eq!(tree("2e"), "(* 2 e)");
+        assert_eq!(tree("2 e3"), "(* 2 e3)");
+    }
+
+    #[test]
+    fn spans_cover_the_brackets() {
+        let tokens = tokenize(b"2 * (x + 1)").unwrap();
+        let expr = parse(&tokens).unwrap();
+        let ExprKind::Binary(_, _, rhs) = &expr.kind else { panic!() };
+        assert_eq!((rhs.span.start, rhs.span.end), (4, 11));
+    }
+
+    // the test threads have a much smaller stack than main, so these also catch shallower recursion
+    #[test]
+    fn deep_brackets() {
+        let input = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert_eq!(answer(&input), Value::Float(1.0));
+    }
+
+    #[test]
+    fn deep_unary_signs() {
+        assert_eq!(answer(&format!("{}1", "-".repeat(100_000))), Value::Float(1.0));
+        assert_eq!(answer(&format!("{}1", "-".repeat(100_001))), Value::Float(-1.0));
+    }
+
+    #[test]
+    fn long_sums() {
+        let input = vec!["1"; 200_000].join("+");
+        assert_eq!(answer(&input), Value::Float(200_000.0));
+    }
+
+    #[test]
+    fn deep_function_bodies() {
+        let mut calc = Calculator::new();
+        let body = format!("{}x + y{}", "(".repeat(100_000), ")".repeat(100_000));
+        calc.eval(&format!("f(x) = {body}")).unwrap();
+        assert_eq!(calc.function_variables("f"), Some(vec!["y".to_string()]));
+    }
+}
@@ -0,0 +1,98 @@
+/// Supplies variables that live outside the calculator, eg. values read from application state.
+///
+/// Providers are consulted when a name is not a local variable or an answer from the history,
+/// in the order they were added to the calculator.
+pub trait VariableProvider {
+    /// The current value of `name`, or `None` if this provider does not know it.
+    fn get(&self, name: &str) -> Option<f64>;
+
+    /// Whether users are prevented from assigning their own value to `name`.
+    fn is_read_only(&self, _name: &str) -> bool {
+        false
+    }
+}
+
+// any lookup closure can be used as a provider whose values can be shadowed
+impl<F> VariableProvider for F
+where
+    F: Fn(&str) -> Option<f64>,
+{
+    fn get(&self, name: &str) -> Option<f64> {
+        self(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VariableProvider;
+    use crate::mathengine::core::{Calc, Calculator};
+    use crate::mathengine::error::ErrorKind;
+    use crate::mathengine::testing::{error, eval};
+    use crate::mathengine::value::Value;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // live readings that users cannot overwrite
+    struct Gauges {
+        rpm: Rc<Cell<f64>>,
+    }
+
+    impl VariableProvider for Gauges {
+        fn get(&self, name: &str) -> Option<f64> {
+            match name {
+                "rpm" => Some(self.rpm.get()),
+                "temp_in" => Some(21.5),
+                _ => None,
+            }
+        }
+
+        fn is_read_only(&self, name: &str) -> bool {
+            name == "rpm"
+        }
+    }
+
+    #[test]
+    fn providers_are_read_live() {
+        let rpm = Rc::new(Cell::new(1000.0));
+        let mut calc = Calculator::new();
+        calc.add_variable_provider(Gauges { rpm: rpm.clone() });
+        calc.eval("per_second(r) = r / 60").unwrap();
+        calc.eval("rate() = rpm / 60").unwrap();
+        assert_eq!(eval(&mut calc, "rpm + temp_in"), Value::Float(1021.5));
+        rpm.set(3000.0);
+        assert_eq!(eval(&mut calc, "rate()"), Value::Float(50.0));
+        assert_eq!(eval(&mut calc, "per_second(rpm)"), Value::Float(50.0));
+        assert!(matches!(error(&mut calc, "oil"), ErrorKind::VarDoesNotExist(_)));
+    }
+
+    #[test]
+    fn read_only_values_cannot_be_assigned() {
+        let mut calc = Calculator::new();
+        calc.add_variable_provider(Gauges { rpm: Rc::new(Cell::new(1.0)) });
+        assert!(matches!(error(&mut calc, "rpm = 5"), ErrorKind::VarReadOnly(_)));
+        assert!(matches!(calc.set_variable("rpm", 5.0).unwrap_err().kind, ErrorKind::VarReadOnly(_)));
+        assert_eq!(calc.variable("rpm"), Some(Value::Float(1.0)));
+        // the others can be shadowed by a local variable, and come back when it is deleted
+        calc.eval("temp_in = 30").unwrap();
+        assert_eq!(eval(&mut calc, "temp_in"), Value::Float(30.0));
+        calc.eval("del temp_in").unwrap();
+        assert_eq!(eval(&mut calc, "temp_in"), Value::Float(21.5));
+    }
+
+    #[test]
+    fn providers_are_consulted_in_order() {
+        let mut calc = Calculator::new();
+        calc.add_variable_provider(|name: &str| (name == "a").then_some(1.0));
+        calc.add_variable_provider(|name: &str| match name {
+            "a" | "b" => Some(2.0),
+            _ => None,
+        });
+        assert_eq!(eval(&mut calc, "a"), Value::Float(1.0));
+        assert_eq!(eval(&mut calc, "b"), Value::Float(2.0));
+        calc.eval("a = 3").unwrap();
+        assert_eq!(eval(&mut calc, "a"), Value::Float(3.0));
+        // answers come before the providers
+        calc.add_variable_provider(|name: &str| (name == "ans").then_some(9.0));
+        assert_eq!(eval(&mut calc, "ans"), Value::Float(3.0));
+    }
+}
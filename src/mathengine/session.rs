@@ -0,0 +1,270 @@
+use std::fs;
+use std::path::Path;
+
+use super::core::{Calc, Calculator};
+use super::error::{CalcError, ErrorKind};
+use super::format::NumberFormat;
+use super::token::Token::Number;
+use super::token::MAX_LITERAL_EXPONENT;
+use super::value::{ComplexDisplay, RationalDisplay, Value};
+
+// the first line of a saved session
+const SESSION_HEADER: &str = "# rustcalc session";
+
+// how deeply scripts can run each other, eg. a session that loads itself
+const MAX_SCRIPT_DEPTH: usize = 16;
+
+impl Calculator {
+    /// The settings, variables and user defined functions as a script that recreates them, see
+    /// [`Calculator::run_script`].
+    ///
+    /// Functions are written as their definitions, with any `$name` replaced by the value it
+    /// captured. Registered rust functions, variable providers and the answer history are not
+    /// included, and values that could not be read back, eg. inf, are written as comments.
+    pub fn session(&self) -> String {
+        let mut lines = vec![SESSION_HEADER.to_string()];
+        lines.extend(self.setting_commands());
+
+        // values are written exactly, so they are read back in exact mode whatever the number mode
+        lines.push("mode exact".to_string());
+        let mut variables: Vec<(&Vec<u8>, &Value)> = self
+            .variables
+            .iter()
+            .filter_map(|(name, token)| match token {
+                Number(value) => Some((name, value)),
+                _ => None,
+            })
+            .collect();
+        variables.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, value) in variables {
+            let declaration = if self.constants.contains(name) { "const " } else { "" };
+            let name = String::from_utf8_lossy(name);
+            match value {
+                // inf and NaN cannot be written as a literal, nor can a decimal too large or small to read back
+                Value::Float(x) if !x.is_finite() => lines.push(format!("# {declaration}{name} = {x} cannot be saved")),
+                Value::Decimal(d) if d.parts().1.unsigned_abs() > MAX_LITERAL_EXPONENT => {
+                    lines.push(format!("# {declaration}{name} = {value} cannot be saved"))
+                }
+                _ => lines.push(format!("{declaration}{name} = {value}")),
+            }
+        }
+
+        let mut functions: Vec<&str> = self.functions.values().map(|f| f.source.as_str()).collect();
+        functions.sort();
+        lines.extend(functions.iter().map(|source| source.to_string()));
+
+        lines.push(format!("mode {}", self.number_mode));
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    /// Writes [`Calculator::session`] to a file.
+    pub fn save_session<P: AsRef<Path>>(&self, path: P) -> Result<(), CalcError> {
+        let path = path.as_ref();
+        fs::write(path, self.session()).map_err(|e| ErrorKind::FileError(path.display().to_string(), e.to_string()).into())
+    }
+
+    /// Runs a session saved with [`Calculator::save_session`]. Settings, variables, constants and
+    /// functions in the file replace the current ones, anything else is kept.
+    pub fn load_session<P: AsRef<Path>>(&mut self, path: P) -> Result<(), CalcError> {
+        // a constant can only be declared again by a session, eg. one a prelude declared too
+        let loading_session = std::mem::replace(&mut self.loading_session, true);
+        let result = self.run_file(path);
+        self.loading_session = loading_session;
+        result
+    }
+
+    /// Reads a script, eg. a prelude of constants and functions, and runs it, see
+    /// [`Calculator::run_script`].
+    pub fn run_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), CalcError> {
+        let path = path.as_ref();
+        let script = fs::read_to_string(path).map_err(|e| ErrorKind::FileError(path.display().to_string(), e.to_string()))?;
+        self.run_script(&script, &path.display().to_string())
+    }
+
+    /// Runs each line of a script as if it was passed to [`Calc::eval`], skipping blank lines and
+    /// lines starting with `#`.
+    ///
+    /// Stops at the first line that fails with [`ErrorKind::ScriptError`], which names the source
+    /// and the line number. Scripts that `load` each other fail with [`ErrorKind::ScriptTooDeep`]
+    /// past a limit.
+    pub fn run_script(&mut self, script: &str, source: &str) -> Result<(), CalcError> {
+        if self.script_depth >= MAX_SCRIPT_DEPTH {
+            return Err(ErrorKind::ScriptTooDeep(MAX_SCRIPT_DEPTH).into());
+        }
+        self.script_depth += 1;
+        let result = self.run_lines(script, source);
+        self.script_depth -= 1;
+        result
+    }
+
+    fn run_lines(&mut self, script: &str, source: &str) -> Result<(), CalcError> {
+        for (i, line) in script.lines().enumerate() {
+            let statement = line.trim();
+            if statement.is_empty() || statement.starts_with('#') {
+                continue;
+            }
+            // the span would point into the line rather than whatever input ran the script
+            self.eval(statement).map_err(|e| match e.kind {
+                // the script that went too deep is named once, rather than every script that ran it
+                ErrorKind::ScriptError(_, _, ref kind) if matches!(**kind, ErrorKind::ScriptTooDeep(_)) => e.kind,
+                kind => ErrorKind::ScriptError(source.to_string(), i + 1, Box::new(kind)),
+            })?;
+        }
+        Ok(())
+    }
+
+    // the commands that restore every setting but the number mode, which precision changes
+    fn setting_commands(&self) -> Vec<String> {
+        let rational_display = match self.rational_display {
+            RationalDisplay::Fraction => "display fraction".to_string(),
+            RationalDisplay::Decimal(places) => format!("display decimal {places}"),
+        };
+        let complex_display = match self.complex_display {
+            ComplexDisplay::Rectangular => "display rect",
+            ComplexDisplay::Polar => "display polar",
+        };
+        let number_format = match self.number_format {
+            NumberFormat::Auto => "display auto".to_string(),
+            NumberFormat::Fixed(places) => format!("display fixed {places}"),
+            NumberFormat::Scientific => "display sci".to_string(),
+            NumberFormat::Engineering { si_prefixes: false } => "display eng".to_string(),
+            NumberFormat::Engineering { si_prefixes: true } => "display eng si".to_string(),
+            NumberFormat::SignificantFigures(count) => format!("display sig {count}"),
+        };
+        vec![
+            self.angle_unit.name().to_string(),
+            format!("precision {}", self.precision),
+            format!("rounding {}", self.rounding_mode),
+            format!("recursion {}", self.recursion_limit),
+            format!("word {}", self.word_type),
+            format!("overflow {}", self.overflow),
+            format!("display {}", self.radix.name()),
+            rational_display,
+            complex_display.to_string(),
+            number_format,
+            format!("display group {}", if self.digit_grouping { "on" } else { "off" }),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathengine::angle::AngleUnit;
+    use crate::mathengine::testing::{error, eval, feedback, TempFile};
+    use crate::mathengine::value::NumberMode;
+    use num_rational::BigRational;
+
+    #[test]
+    fn sessions_round_trip() {
+        let mut calc = Calculator::new();
+        for input in ["deg", "display fixed 3", "display group on", "k = 0.1", "f(x) = x * k", "g(x) = x + $k"] {
+            calc.eval(input).unwrap();
+        }
+        let file = TempFile::new("round-trip.calc", "");
+        calc.save_session(&file.0).unwrap();
+        let saved = std::fs::read_to_string(&file.0).unwrap();
+        assert!(saved.starts_with(SESSION_HEADER));
+        assert!(saved.contains("\nf(x) = x * k\n"));
+        assert!(saved.contains("\ng(x) = x + (0.1)\n"));
+
+        let mut other = Calculator::new();
+        other.load_session(&file.0).unwrap();
+        assert_eq!(other.angle_unit(), AngleUnit::Degrees);
+        assert_eq!(other.number_format(), NumberFormat::Fixed(3));
+        assert!(other.digit_grouping());
+        assert_eq!(other.number_mode(), NumberMode::Float);
+        // values are read back exactly and used as floats again
+        assert_eq!(other.variable("k"), Some(Value::Rational(BigRational::new(1.into(), 10.into()))));
+        assert_eq!(eval(&mut other, "k"), Value::Float(0.1));
+        assert_eq!(eval(&mut other, "f(10) + g(1)"), Value::Float(2.1));
+    }
+
+    #[test]
+    fn loading_replaces_only_what_the_session_has() {
+        let mut calc = Calculator::new();
+        calc.eval("x = 1").unwrap();
+        let file = TempFile::new("replace.calc", &calc.session());
+        let mut other = Calculator::new();
+        other.eval("x = 5").unwrap();
+        other.eval("y = 6").unwrap();
+        other.eval("deg").unwrap();
+        other.eval("h(x) = x").unwrap();
+        other.load_session(&file.0).unwrap();
+        assert_eq!(other.variable("x"), Some(Value::from(1)));
+        assert_eq!(other.variable("y"), Some(Value::Float(6.0)));
+        assert_eq!(other.angle_unit(), AngleUnit::Radians);
+        assert!(other.function_source("h").is_some());
+    }
+
+    #[test]
+    fn save_and_load_commands() {
+        let file = TempFile::new("commands.calc", "");
+        let mut calc = Calculator::new();
+        calc.eval("r = 3").unwrap();
+        calc.eval("area(r) = pi * r^2").unwrap();
+        assert!(feedback(&mut calc, &format!("save {}", file.path())).contains(&file.path()));
+        let mut other = Calculator::new();
+        other.eval(&format!("load {}", file.path())).unwrap();
+        assert_eq!(eval(&mut other, "area(r) / pi"), Value::Float(9.0));
+        assert!(matches!(error(&mut other, "load /nonexistent/session.calc"), ErrorKind::FileError(..)));
+    }
+
+    #[test]
+    fn values_that_cannot_be_written_are_left_out() {
+        let mut calc = Calculator::new();
+        calc.eval("big = 1e308 * 10").unwrap();
+        calc.eval("precision 10").unwrap();
+        calc.eval("huge = 10^20000").unwrap();
+        calc.eval("large = 1.5 * 10^9000").unwrap();
+        calc.eval("z = 1").unwrap();
+        let session = calc.session();
+        assert!(session.contains("\n# big = inf cannot be saved\n"));
+        assert!(session.contains("\n# huge = 1e+20000 cannot be saved\n"));
+        assert!(session.contains("\nlarge = 1.5e+9000\n"));
+        // the lines after them are still read
+        let mut other = Calculator::new();
+        other.run_script(&session, "<test>").unwrap();
+        assert_eq!((other.variable("big"), other.variable("huge")), (None, None));
+        assert!(other.variable("large").is_some());
+        assert_eq!(other.variable("z"), Some(Value::from(1)));
+    }
+
+    #[test]
+    fn script_errors_name_the_source_and_line() {
+        let mut calc = Calculator::new();
+        let e = calc.run_script("# constants\nx = 1\n\ny = nope\nz = 3", "prelude.calc").unwrap_err();
+        match e.kind {
+            ErrorKind::ScriptError(source, 4, kind) => {
+                assert_eq!(source, "prelude.calc");
+                assert!(matches!(*kind, ErrorKind::VarDoesNotExist(_)));
+            }
+            kind => panic!("{kind:?}"),
+        }
+        assert_eq!(calc.variable("x"), Some(Value::Float(1.0)));
+        assert_eq!(calc.variable("z"), None);
+    }
+
+    #[test]
+    fn sessions_keep_constants() {
+        let mut calc = Calculator::new();
+        calc.eval("const g = 2").unwrap();
+        calc.eval("h = 3").unwrap();
+        let session = calc.session();
+        assert!(session.contains("\nconst g = 2\n"));
+        assert!(session.contains("\nh = 3\n"));
+
+        // loading the session over a calculator that already declared the constant replaces it
+        let file = TempFile::new("constants.calc", &session);
+        let mut other = Calculator::new();
+        other.eval("const g = 1").unwrap();
+        other.load_session(&file.0).unwrap();
+        // values are read back exactly
+        assert_eq!(other.variable("g"), Some(Value::from(2)));
+        assert!(other.is_constant("g"));
+
+        // but a script is not a session
+        assert!(other.run_script("const g = 5", "<test>").is_err());
+    }
+}
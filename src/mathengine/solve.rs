@@ -1,243 +1,571 @@
-use crate::mathengine::token::{self, Token::{self, *}};
-
-use super::{core::Calculator, error::CalcError, math_function::FunctionHandling};
+use super::{
+    angle::AngleUnit,
+    core::Calculator,
+    decimal::{Decimal, DecimalContext},
+    error::{CalcError, ErrorKind},
+    expr::{BinaryOp, Expr, ExprKind, UnaryOp},
+    math_function::FunctionHandling,
+    token::Span,
+    value::{NumberMode, Value},
+    word::Overflow,
+};
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use std::cmp::Ordering;
 
 pub trait Solver {
-    fn solve(&mut self, equation: Vec<Token>, depth: i32) -> Result<Vec<Token>, CalcError>;
-    fn solve_rec(&mut self, token_list: &mut Vec<Token>, depth: i32) -> Result<Vec<Token>, CalcError>;
+    fn solve(&self, expr: &Expr, args: &[Value]) -> Result<Value, CalcError>;
+}
+
+// one step of solving an expression
+// the steps are kept on a stack instead of recursing, so deeply recursive user functions hit the
+// recursion limit rather than overflowing the rust stack
+enum Step<'a> {
+    // solve an expression and push its value
+    Solve(&'a Expr),
+    // pop the operand(s) and push the result
+    Unary(UnaryOp, Span),
+    Binary(BinaryOp, Span),
+    // pop the args and call the function, the span is the call
+    Call(&'a [u8], usize, Span),
+    // pop the condition and solve one of the branches
+    Branch(&'a Expr, &'a Expr),
+    // pop the lhs of a && or ||, and only solve the rhs if the lhs does not decide the result
+    ShortCircuit(BinaryOp, &'a Expr),
+    // pop a value and push it as 1 or 0
+    Truth,
+    // a function body has been solved, drop its args
+    Return,
 }
 
 impl Solver for Calculator {
-    fn solve(&mut self, equation: Vec<Token>, depth: i32) -> Result<Vec<Token>, CalcError> {
-        if depth > 32 {
-            return Err(CalcError::TooMuchRecursion);
-        }
-        if equation.len() == 0 {
-            let mut v = Vec::new();
-            v.push(Number(0.0));
-            return Ok(v);
-        }
-        let mut token_list = equation.clone();
-        let answer = self.solve_rec(&mut token_list, depth + 1);
-        answer
-    }
+    // args holds the values of the function args when solving a function body
+    fn solve(&self, expr: &Expr, args: &[Value]) -> Result<Value, CalcError> {
+        let mut steps = vec![Step::Solve(expr)];
+        let mut values: Vec<Value> = Vec::new();
+        // the args of each user defined function that is being solved, innermost last
+        let mut frames: Vec<Vec<Value>> = Vec::new();
+        // a function body has spans into the input it was defined with, so errors from inside a
+        // function are pointed at the outermost call instead
+        let mut outer_call: Option<Span> = None;
 
-    fn solve_rec(&mut self, token_list: &mut Vec<Token>, depth: i32) -> Result<Vec<Token>, CalcError> {
-        if depth > 32 {
-            return Err(CalcError::TooMuchRecursion);
-        }
-        let mut i = 0;
-        while i < token_list.len() {
-            match &token_list[i] {
-                Token::LeftBracket => {
-                    let right_bracket_index =
-                        {
-                            match get_matching_bracket_index(&token_list[i + 1..]) {
-                                Some(index) => index,
-                                None => return Err(CalcError::ImbalancedBrackets)
-                            }
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Solve(expr) => match &expr.kind {
+                    ExprKind::Number(n) => {
+                        let value = self
+                            .settle(n.clone())
+                            .map_err(|kind| CalcError::new(kind, outer_call.unwrap_or(expr.span)))?;
+                        values.push(value);
+                    }
+                    ExprKind::Variable(name) => match self.get_variable(name) {
+                        Some(n) => {
+                            let value = self
+                                .settle(n)
+                                .map_err(|kind| CalcError::new(kind, outer_call.unwrap_or(expr.span)))?;
+                            values.push(value);
                         }
-                        + i + 1;
-                    match self.solve_rec(&mut token_list[i + 1..right_bracket_index].into(), depth + 1) {
-                        Ok(answer) => token_list.splice(i..right_bracket_index + 1, answer),
-                        Err(e) => return Err(e),
-                    };
-                    i = 0;
-                }
-                Token::FunctionName(func_name) => {
-                    let right_bracket_index =
-                    {
-                        match get_matching_bracket_index(&token_list[i + 2..]) {
-                            Some(index) => index,
-                            None => return Err(CalcError::ImbalancedBrackets)
+                        // angle unit suffixes, eg. sin(30deg)
+                        _ => match AngleUnit::from_name(name) {
+                            Some(unit) => {
+                                let value = self
+                                    .settle(self.angle_unit_size(unit))
+                                    .map_err(|kind| CalcError::new(kind, outer_call.unwrap_or(expr.span)))?;
+                                values.push(value);
+                            }
+                            None => {
+                                let span = outer_call.unwrap_or(expr.span);
+                                return Err(CalcError::new(ErrorKind::VarDoesNotExist(String::from_utf8_lossy(name).into_owned()), span));
+                            }
+                        },
+                    },
+                    ExprKind::FunctionArg(index) => {
+                        let args = frames.last().map_or(args, |frame| &frame[..]);
+                        match args.get(*index) {
+                            Some(n) => values.push(n.clone()),
+                            None => {
+                                let span = outer_call.unwrap_or(expr.span);
+                                return Err(CalcError::new(ErrorKind::FailedToSolveEquation, span));
+                            }
                         }
                     }
-                        + i + 1;
-                    match self
-                        .solve_function(&func_name, &token_list[i + 2..right_bracket_index + 1], depth + 1)
-                    {
-                        Ok(answer) => {
-                            token_list.splice(i..right_bracket_index + 2, Vec::from([answer]))
+                    ExprKind::Unary(op, operand) => match (op, &operand.kind) {
+                        // a negative literal is one number, so the most negative value of a signed
+                        // word can be written even when overflow is an error, eg. -128 as an i8
+                        (UnaryOp::Negate, ExprKind::Number(n)) => {
+                            let value = self
+                                .apply_unary(*op, n.clone())
+                                .and_then(|value| self.settle(value))
+                                .map_err(|kind| CalcError::new(kind, outer_call.unwrap_or(expr.span)))?;
+                            values.push(value);
                         }
-                        Err(e) => return Err(e),
-                    };
-                    i = 0;
-                }
-                _ => {
-                    i += 1;
-                }
-            }
-        }
-        i = 0;
-        while i < token_list.len() {
-            match token_list[i] {
-                Token::Exponentation => {
-                    if let Token::Number(a) = token_list[i - 1] {
-                        if let Token::Number(b) = token_list[i + 1] {
-                            let answer = a.powf(b);
-                            token_list[i - 1] = Token::Number(answer);
-                            token_list.remove(i);
-                            token_list.remove(i);
-                            i = 0;
+                        _ => {
+                            steps.push(Step::Unary(*op, expr.span));
+                            steps.push(Step::Solve(operand));
                         }
+                    },
+                    ExprKind::Binary(op @ (BinaryOp::And | BinaryOp::Or), lhs, rhs) => {
+                        steps.push(Step::ShortCircuit(*op, rhs));
+                        steps.push(Step::Solve(lhs));
                     }
-                }
-                _ => {
-                    i += 1;
-                }
-            }
-        }
-        i = 0;
-        while i < token_list.len() {
-            match token_list[i] {
-                Token::Multiplication => {
-                    if let Token::Number(a) = token_list[i - 1] {
-                        if let Token::Number(b) = token_list[i + 1] {
-                            let answer = a * b;
-                            token_list[i - 1] = Token::Number(answer);
-                            token_list.remove(i);
-                            token_list.remove(i);
-                            i = 0;
-                        }
+                    ExprKind::Binary(op, lhs, rhs) => {
+                        steps.push(Step::Binary(*op, expr.span));
+                        steps.push(Step::Solve(rhs));
+                        steps.push(Step::Solve(lhs));
                     }
-                }
-                Token::Division => {
-                    if let Token::Number(a) = token_list[i - 1] {
-                        if let Token::Number(b) = token_list[i + 1] {
-                            let answer = a / b;
-                            token_list[i - 1] = Token::Number(answer);
-                            token_list.remove(i);
-                            token_list.remove(i);
-                            i = 0;
-                        }
+                    ExprKind::Call(name, arg_exprs) => {
+                        steps.push(Step::Call(name, arg_exprs.len(), expr.span));
+                        steps.extend(arg_exprs.iter().rev().map(Step::Solve));
                     }
-                }
-                Token::Modulation => {
-                    if let Token::Number(a) = token_list[i - 1] {
-                        if let Token::Number(b) = token_list[i + 1] {
-                            let answer = a % b;
-                            token_list[i - 1] = Token::Number(answer);
-                            token_list.remove(i);
-                            token_list.remove(i);
-                            i = 0;
-                        }
+                    ExprKind::If(condition, then, otherwise) => {
+                        steps.push(Step::Branch(then, otherwise));
+                        steps.push(Step::Solve(condition));
                     }
+                },
+                Step::Unary(op, span) => {
+                    let a = values.pop().unwrap();
+                    let value = self
+                        .apply_unary(op, a)
+                        .and_then(|value| self.settle(value))
+                        .map_err(|kind| CalcError::new(kind, outer_call.unwrap_or(span)))?;
+                    values.push(value);
                 }
-                _ => {
-                    i += 1;
+                Step::Binary(op, span) => {
+                    let b = values.pop().unwrap();
+                    let a = values.pop().unwrap();
+                    let value = self
+                        .apply_binary(op, a, b)
+                        .and_then(|value| self.settle(value))
+                        .map_err(|kind| CalcError::new(kind, outer_call.unwrap_or(span)))?;
+                    values.push(value);
                 }
-            }
-        }
-        i = 0;
-        while i < token_list.len() {
-            match token_list[i] {
-                Token::BitwiseAnd => {
-                    if let Token::Number(a) = token_list[i - 1] {
-                        if let Token::Number(b) = token_list[i + 1] {
-                            let answer = (a as i64) & (b as i64);
-                            token_list[i - 1] = Token::Number(answer as f64);
-                            token_list.remove(i);
-                            token_list.remove(i);
-                            i = 0;
+                Step::Call(name, arg_count, span) => {
+                    let arg_values = values.split_off(values.len() - arg_count);
+                    let error_span = outer_call.unwrap_or(span);
+                    // user defined functions are solved on this stack, builtin and registered
+                    // functions are called directly. a name can only belong to one of these
+                    match self.functions.get(name) {
+                        Some(function) => {
+                            if function.arg_count != arg_count {
+                                let kind = ErrorKind::FuncIncorrectArgCount(
+                                    String::from_utf8_lossy(name).into_owned(),
+                                    function.arity(),
+                                    arg_count,
+                                );
+                                return Err(CalcError::new(kind, error_span));
+                            }
+                            if frames.len() >= self.recursion_limit {
+                                let kind = ErrorKind::TooMuchRecursion(self.recursion_limit);
+                                return Err(CalcError::new(kind, error_span));
+                            }
+                            if frames.is_empty() {
+                                outer_call = Some(span);
+                            }
+                            frames.push(arg_values);
+                            steps.push(Step::Return);
+                            steps.push(Step::Solve(&function.func));
                         }
-                    }
-                }
-                Token::BitwiseOr => {
-                    if let Token::Number(a) = token_list[i - 1] {
-                        if let Token::Number(b) = token_list[i + 1] {
-                            let answer = (a as i64) | (b as i64);
-                            token_list[i - 1] = Token::Number(answer as f64);
-                            token_list.remove(i);
-                            token_list.remove(i);
-                            i = 0;
+                        None => {
+                            let value = self
+                                .solve_function(name, &arg_values)
+                                .and_then(|value| self.settle(value).map_err(CalcError::from))
+                                .map_err(|e| e.with_span(error_span))?;
+                            values.push(value);
                         }
                     }
                 }
-                Token::BitwiseXor => {
-                    if let Token::Number(a) = token_list[i - 1] {
-                        if let Token::Number(b) = token_list[i + 1] {
-                            let answer = (a as i64) ^ (b as i64);
-                            token_list[i - 1] = Token::Number(answer as f64);
-                            token_list.remove(i);
-                            token_list.remove(i);
-                            i = 0;
-                        }
-                    }
+                Step::Branch(then, otherwise) => {
+                    let condition = values.pop().unwrap();
+                    steps.push(Step::Solve(if is_true(&condition) { then } else { otherwise }));
                 }
-                Token::BitwiseLeftShift => {
-                    if let Token::Number(a) = token_list[i - 1] {
-                        if let Token::Number(b) = token_list[i + 1] {
-                            let answer = (a as i64) << (b as i64);
-                            token_list[i - 1] = Token::Number(answer as f64);
-                            token_list.remove(i);
-                            token_list.remove(i);
-                            i = 0;
+                Step::ShortCircuit(op, rhs) => {
+                    let lhs = is_true(&values.pop().unwrap());
+                    match (op, lhs) {
+                        (BinaryOp::And, false) => values.push(self.truth_value(false)),
+                        (BinaryOp::Or, true) => values.push(self.truth_value(true)),
+                        _ => {
+                            steps.push(Step::Truth);
+                            steps.push(Step::Solve(rhs));
                         }
                     }
                 }
-                Token::BitwiseRightShift => {
-                    if let Token::Number(a) = token_list[i - 1] {
-                        if let Token::Number(b) = token_list[i + 1] {
-                            let answer = (a as i64) >> (b as i64);
-                            token_list[i - 1] = Token::Number(answer as f64);
-                            token_list.remove(i);
-                            token_list.remove(i);
-                            i = 0;
-                        }
+                Step::Truth => {
+                    let a = values.pop().unwrap();
+                    values.push(self.truth_value(is_true(&a)));
+                }
+                Step::Return => {
+                    frames.pop();
+                    if frames.is_empty() {
+                        outer_call = None;
                     }
                 }
-                _ => {
-                    i += 1;
+            }
+        }
+
+        Ok(values.pop().unwrap())
+    }
+}
+
+// 0 is false, any other number is true
+pub fn is_true(x: &Value) -> bool {
+    !x.is_zero()
+}
+
+impl Calculator {
+    // comparisons give 1 for true and 0 for false
+    fn truth_value(&self, b: bool) -> Value {
+        match self.number_mode {
+            NumberMode::Float => Value::Float(if b { 1.0 } else { 0.0 }),
+            NumberMode::Exact | NumberMode::Programmer => Value::from(b as i64),
+            NumberMode::Decimal => Value::Decimal(Decimal::from_integer((b as i64).into())),
+        }
+    }
+
+    // the size of an angle unit suffix in the active unit, eg. 1deg is pi/180 in radians
+    fn angle_unit_size(&self, unit: AngleUnit) -> Value {
+        match self.number_mode {
+            NumberMode::Decimal => Value::Decimal(unit.decimal_in_unit(self.angle_unit, self.decimal_context())),
+            _ => Value::Float(unit.in_unit(self.angle_unit)),
+        }
+    }
+
+    // how a value is used in the number mode, in programmer mode it must also be an integer and it
+    // is wrapped into the word or reported as an overflow
+    fn settle(&self, value: Value) -> Result<Value, ErrorKind> {
+        let value = self.normalize(value);
+        if self.number_mode != NumberMode::Programmer {
+            return Ok(value);
+        }
+        let n = integer_operand(&value)?;
+        if self.word_type.contains(&n) {
+            return Ok(Value::from(n));
+        }
+        match self.overflow {
+            Overflow::Wrap => Ok(Value::from(self.word_type.wrap(&n))),
+            Overflow::Error => Err(ErrorKind::Overflow(self.word_type)),
+        }
+    }
+
+    fn apply_unary(&self, op: UnaryOp, a: Value) -> Result<Value, ErrorKind> {
+        match (op, a) {
+            (UnaryOp::Plus, a) => Ok(a),
+            (UnaryOp::Negate, Value::Float(x)) => Ok(Value::Float(-x)),
+            (UnaryOp::Negate, Value::Rational(r)) => Ok(Value::Rational(-r)),
+            (UnaryOp::Negate, Value::Decimal(d)) => Ok(Value::Decimal(d.neg())),
+            (UnaryOp::Negate, Value::Complex(z)) => Ok(Value::Complex(-z)),
+            (UnaryOp::Not, a) => Ok(self.truth_value(!is_true(&a))),
+            // ~x is -x - 1 in two's complement, so in an unsigned word it flips only the bits of the word
+            (UnaryOp::BitNot, a) => {
+                let n = !integer_operand(&a)?;
+                match self.number_mode {
+                    NumberMode::Programmer => Ok(Value::from(self.word_type.wrap(&n))),
+                    _ => Ok(Value::from(n)),
                 }
             }
         }
-        i = 0;
-        while i < token_list.len() {
-            match token_list[i] {
-                Token::Addition => {
-                    if let Token::Number(a) = token_list[i - 1] {
-                        if let Token::Number(b) = token_list[i + 1] {
-                            let answer = a + b;
-                            token_list[i - 1] = Token::Number(answer);
-                            token_list.remove(i);
-                            token_list.remove(i);
-                            i = 0;
-                        }
+    }
+
+    fn apply_binary(&self, op: BinaryOp, a: Value, b: Value) -> Result<Value, ErrorKind> {
+        if a.is_complex() || b.is_complex() {
+            return self.apply_complex(op, a.to_complex(), b.to_complex());
+        }
+        if self.number_mode == NumberMode::Programmer {
+            if let Some(result) = self.apply_word(op, &a, &b) {
+                return result;
+            }
+        }
+        let ordering = || a.compare(&b);
+        let ctx = self.decimal_context();
+        match op {
+            BinaryOp::Add => combine(&a, &b, ctx, |x, y| Ok(Value::from(x + y)), |x, y| Ok(x.add(y, ctx)), |x, y| x + y),
+            BinaryOp::Sub => combine(&a, &b, ctx, |x, y| Ok(Value::from(x - y)), |x, y| Ok(x.sub(y, ctx)), |x, y| x - y),
+            BinaryOp::Mul => combine(&a, &b, ctx, |x, y| Ok(Value::from(x * y)), |x, y| Ok(x.mul(y, ctx)), |x, y| x * y),
+            BinaryOp::Div => combine(
+                &a,
+                &b,
+                ctx,
+                |x, y| Ok(Value::from(x / nonzero(y)?)),
+                |x, y| x.div(y, ctx).ok_or(ErrorKind::DivisionByZero),
+                |x, y| x / y,
+            ),
+            BinaryOp::Mod => combine(
+                &a,
+                &b,
+                ctx,
+                |x, y| Ok(Value::from(x % nonzero(y)?)),
+                |x, y| x.rem(y, ctx).ok_or(ErrorKind::DivisionByZero),
+                |x, y| x % y,
+            ),
+            BinaryOp::Pow => {
+                if let (Some(x), Some(y)) = (a.to_rational(), b.to_rational()) {
+                    if x.is_zero() && y.is_negative() {
+                        return Err(ErrorKind::DivisionByZero);
                     }
                 }
-                Token::Subtraction => {
-                    if let Token::Number(a) = token_list[i - 1] {
-                        if let Token::Number(b) = token_list[i + 1] {
-                            let answer = a - b;
-                            token_list[i - 1] = Token::Number(answer);
-                            token_list.remove(i);
-                            token_list.remove(i);
-                            i = 0;
+                if self.number_mode == NumberMode::Decimal {
+                    if let (Some(x), Some(y)) = (a.to_decimal(ctx), b.to_decimal(ctx)) {
+                        if let Some(result) = x.pow(&y, ctx) {
+                            return Ok(Value::Decimal(result));
                         }
                     }
                 }
-                _ => {
-                    i += 1;
-                }
+                // exact when possible, eg. 2^100 or 4^(1/2), otherwise a float
+                Ok(a.pow(&b).unwrap_or_else(|| float_pow(a.to_f64(), b.to_f64())))
             }
+            // bitwise operators only take integers, and work them out exactly in every mode
+            BinaryOp::Shl => combine_integers(&a, &b, shift_left),
+            BinaryOp::Shr => combine_integers(&a, &b, |x, y| shift_left(x, &-y)),
+            BinaryOp::BitAnd => combine_integers(&a, &b, |x, y| Ok(x & y)),
+            BinaryOp::BitOr => combine_integers(&a, &b, |x, y| Ok(x | y)),
+            BinaryOp::BitXor => combine_integers(&a, &b, |x, y| Ok(x ^ y)),
+            // floats are compared exactly, so 0.1 + 0.2 == 0.3 is 0 unless in exact mode
+            BinaryOp::Less => Ok(self.truth_value(ordering() == Some(Ordering::Less))),
+            BinaryOp::LessEqual => Ok(self.truth_value(matches!(ordering(), Some(Ordering::Less | Ordering::Equal)))),
+            BinaryOp::Greater => Ok(self.truth_value(ordering() == Some(Ordering::Greater))),
+            BinaryOp::GreaterEqual => Ok(self.truth_value(matches!(ordering(), Some(Ordering::Greater | Ordering::Equal)))),
+            BinaryOp::Equal => Ok(self.truth_value(ordering() == Some(Ordering::Equal))),
+            BinaryOp::NotEqual => Ok(self.truth_value(ordering() != Some(Ordering::Equal))),
+            // solving normally goes through Step::ShortCircuit instead, so the rhs can be skipped
+            BinaryOp::And => Ok(self.truth_value(is_true(&a) && is_true(&b))),
+            BinaryOp::Or => Ok(self.truth_value(is_true(&a) || is_true(&b))),
+        }
+    }
+
+    // the operators that work differently on fixed size integers, None for the rest which give the
+    // same result as in exact mode and are then fitted into the word
+    fn apply_word(&self, op: BinaryOp, a: &Value, b: &Value) -> Option<Result<Value, ErrorKind>> {
+        let (x, y) = (a.to_integer()?, b.to_integer()?);
+        let bits = BigInt::from(self.word_type.bits());
+        match op {
+            // division truncates towards zero, like integer division in most languages
+            BinaryOp::Div if y.is_zero() => Some(Err(ErrorKind::DivisionByZero)),
+            BinaryOp::Div => Some(Ok(Value::from(x / y))),
+            // shifting by the word size or more leaves nothing of x but its sign, so larger shifts
+            // do not need to be worked out
+            BinaryOp::Shl => Some(shift_left(&x, &y.clamp(-&bits, bits.clone())).map(Value::from)),
+            BinaryOp::Shr => Some(shift_left(&x, &-y.clamp(-&bits, bits.clone())).map(Value::from)),
+            // any integer other than 0, 1 and -1 to a power larger than the word size overflows
+            BinaryOp::Pow if y > bits && x.abs() > BigInt::one() => Some(match self.overflow {
+                Overflow::Wrap => Ok(Value::from(x.modpow(&y, &(BigInt::one() << self.word_type.bits())))),
+                Overflow::Error => Err(ErrorKind::Overflow(self.word_type)),
+            }),
+            _ => None,
+        }
+    }
+
+    // complex numbers can be added, multiplied, raised to powers and so on, but not ordered
+    fn apply_complex(&self, op: BinaryOp, x: Complex64, y: Complex64) -> Result<Value, ErrorKind> {
+        match op {
+            BinaryOp::Add => Ok(Value::Complex(x + y)),
+            BinaryOp::Sub => Ok(Value::Complex(x - y)),
+            BinaryOp::Mul => Ok(Value::Complex(x * y)),
+            BinaryOp::Div => Ok(Value::Complex(x / y)),
+            BinaryOp::Pow => Ok(Value::Complex(complex_pow(x, y))),
+            BinaryOp::Equal => Ok(self.truth_value(x == y)),
+            BinaryOp::NotEqual => Ok(self.truth_value(x != y)),
+            BinaryOp::And => Ok(self.truth_value(!x.is_zero() && !y.is_zero())),
+            BinaryOp::Or => Ok(self.truth_value(!x.is_zero() || !y.is_zero())),
+            _ => Err(ErrorKind::OperatorComplexArg(op.symbol().to_string())),
         }
+    }
+}
+
+// a negative number to a fractional power is complex, eg. (-4)^0.5 is 2i
+fn float_pow(x: f64, y: f64) -> Value {
+    let result = x.powf(y);
+    if result.is_nan() && !x.is_nan() && !y.is_nan() {
+        return Value::Complex(complex_pow(Complex64::from(x), Complex64::from(y)));
+    }
+    Value::Float(result)
+}
+
+fn complex_pow(x: Complex64, y: Complex64) -> Complex64 {
+    // integer powers by multiplying, so i^2 is exactly -1
+    if y.im == 0.0 && y.re.fract() == 0.0 && y.re.abs() <= i32::MAX as f64 {
+        return x.powi(y.re as i32);
+    }
+    // sqrt is exact on the axes, so (-4)^0.5 is 2i rather than 1.2e-16 + 2i
+    if y == Complex64::new(0.5, 0.0) {
+        return x.sqrt();
+    }
+    // powc works through ln(x), which is undefined at 0
+    if x.is_zero() && y.re > 0.0 {
+        return Complex64::zero();
+    }
+    x.powc(y)
+}
 
-        Ok(token_list.to_vec())
+// uses the exact operation when both values are exact, the decimal one when one is a decimal and
+// the other is not a float, otherwise the float one
+fn combine<E, D, F>(a: &Value, b: &Value, ctx: DecimalContext, exact: E, decimal: D, float: F) -> Result<Value, ErrorKind>
+where
+    E: Fn(&BigRational, &BigRational) -> Result<Value, ErrorKind>,
+    D: Fn(&Decimal, &Decimal) -> Result<Decimal, ErrorKind>,
+    F: Fn(f64, f64) -> f64,
+{
+    match (a, b) {
+        (Value::Rational(x), Value::Rational(y)) => exact(x, y),
+        (Value::Float(_), _) | (_, Value::Float(_)) => Ok(Value::Float(float(a.to_f64(), b.to_f64()))),
+        _ => match (a.to_decimal(ctx), b.to_decimal(ctx)) {
+            (Some(x), Some(y)) => decimal(&x, &y).map(Value::Decimal),
+            _ => Ok(Value::Float(float(a.to_f64(), b.to_f64()))),
+        },
     }
 }
 
-pub fn get_matching_bracket_index(token_list: &[Token]) -> Option<usize> {
-    let mut depth = 1;
-    for i in 0..token_list.len() {
-        match token_list[i] {
-            Token::LeftBracket => depth += 1,
-            Token::RightBracket => depth -= 1,
-            _ => (),
+// the value as an integer, floats that are whole numbers included
+fn integer_operand(x: &Value) -> Result<BigInt, ErrorKind> {
+    x.whole_number().ok_or_else(|| ErrorKind::NotAnInteger(x.to_string()))
+}
+
+// bitwise operations, the result is an exact integer that the solver turns into a float in float mode
+fn combine_integers<E>(a: &Value, b: &Value, exact: E) -> Result<Value, ErrorKind>
+where
+    E: Fn(&BigInt, &BigInt) -> Result<BigInt, ErrorKind>,
+{
+    exact(&integer_operand(a)?, &integer_operand(b)?).map(Value::from)
+}
+
+// a negative shift goes the other way
+fn shift_left(x: &BigInt, shift: &BigInt) -> Result<BigInt, ErrorKind> {
+    if shift.is_negative() {
+        // shifting right past the highest bit always gives 0, or -1 for negative numbers
+        let shift = (-shift).to_u64().unwrap_or(u64::MAX).min(x.bits() + 1);
+        return Ok(x >> shift);
+    }
+    if x.is_zero() {
+        return Ok(BigInt::zero());
+    }
+    match shift.to_u32() {
+        Some(shift) if shift <= MAX_EXACT_SHIFT => Ok(x << shift),
+        _ => Err(ErrorKind::ResultTooLarge),
+    }
+}
+
+// shifting left further than this is an error, 1 << (1 << 20) already has a million bits
+const MAX_EXACT_SHIFT: u32 = 1 << 20;
+
+fn nonzero(x: &BigRational) -> Result<&BigRational, ErrorKind> {
+    if x.is_zero() {
+        Err(ErrorKind::DivisionByZero)
+    } else {
+        Ok(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathengine::core::Calc;
+    use crate::mathengine::testing::{answer, error, eval};
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(answer("1 + 2 * 3"), Value::Float(7.0));
+        assert_eq!(answer("2 * (3 + 4)"), Value::Float(14.0));
+        assert_eq!(answer("10 / 4"), Value::Float(2.5));
+        assert_eq!(answer("5 % 3"), Value::Float(2.0));
+        assert_eq!(answer("-7 % 3"), Value::Float(-1.0));
+        assert_eq!(answer("1 / 0"), Value::Float(f64::INFINITY));
+    }
+
+    #[test]
+    fn variables_and_functions() {
+        let mut calc = Calculator::new();
+        calc.eval("x = 4").unwrap();
+        calc.eval("f(a, b) = a * b + x").unwrap();
+        assert_eq!(eval(&mut calc, "f(2, 3)"), Value::Float(10.0));
+        assert_eq!(eval(&mut calc, "f(f(1, 1), 2) + sqrt(x)"), Value::Float(16.0));
+        assert!(matches!(error(&mut calc, "y + 1"), ErrorKind::VarDoesNotExist(_)));
+        assert!(matches!(error(&mut calc, "g(1)"), ErrorKind::FuncDoesNotExist(_)));
+        assert!(matches!(error(&mut calc, "f(1)"), ErrorKind::FuncIncorrectArgCount(..)));
+    }
+
+    #[test]
+    fn recursive_functions_with_if() {
+        let mut calc = Calculator::new();
+        calc.eval("fib(n) = if(n < 2, n, fib(n - 1) + fib(n - 2))").unwrap();
+        calc.eval("fact2(n) = if(n <= 1, 1, n * fact2(n - 1))").unwrap();
+        assert_eq!(eval(&mut calc, "fib(20)"), Value::Float(6765.0));
+        assert_eq!(eval(&mut calc, "fact2(10)"), Value::Float(3628800.0));
+    }
+
+    #[test]
+    fn if_only_solves_the_branch_it_takes() {
+        let mut calc = Calculator::new();
+        assert_eq!(eval(&mut calc, "if(1, 2, nope)"), Value::Float(2.0));
+        assert_eq!(eval(&mut calc, "if(0, nope(1), 3)"), Value::Float(3.0));
+        assert!(matches!(error(&mut calc, "if(0, 2, nope)"), ErrorKind::VarDoesNotExist(_)));
+        assert!(matches!(error(&mut calc, "if(1, 2)"), ErrorKind::FuncIncorrectArgCount(..)));
+    }
+
+    #[test]
+    fn recursion_limit() {
+        let mut calc = Calculator::new();
+        calc.eval("loop(n) = loop(n + 1)").unwrap();
+        assert!(matches!(error(&mut calc, "loop(0)"), ErrorKind::TooMuchRecursion(1000)));
+        calc.eval("count(n) = if(n <= 0, 0, 1 + count(n - 1))").unwrap();
+        assert!(matches!(error(&mut calc, "count(5000)"), ErrorKind::TooMuchRecursion(1000)));
+        calc.eval("recursion 10").unwrap();
+        assert_eq!(calc.recursion_limit(), 10);
+        assert_eq!(eval(&mut calc, "count(9)"), Value::Float(9.0));
+        assert!(matches!(error(&mut calc, "count(10)"), ErrorKind::TooMuchRecursion(10)));
+    }
+
+    #[test]
+    fn deep_recursion_does_not_use_the_rust_stack() {
+        let mut calc = Calculator::new();
+        calc.set_recursion_limit(100_000);
+        calc.eval("count(n) = if(n <= 0, 0, 1 + count(n - 1))").unwrap();
+        assert_eq!(eval(&mut calc, "count(50000)"), Value::Float(50000.0));
+    }
+
+    #[test]
+    fn comparisons_give_one_or_zero() {
+        for (input, expected) in [
+            ("1 < 2", 1.0),
+            ("2 <= 1", 0.0),
+            ("3 >= 3", 1.0),
+            ("3 > 3", 0.0),
+            ("3 == 3", 1.0),
+            ("3 != 3", 0.0),
+            ("-1 < 0", 1.0),
+            ("0.1 + 0.2 == 0.3", 0.0),
+        ] {
+            assert_eq!(answer(input), Value::Float(expected), "{input}");
         }
-        if depth == 0 {
-            return Some(i);
+        let mut calc = Calculator::new();
+        assert!(matches!(error(&mut calc, "i < 1"), ErrorKind::OperatorComplexArg(_)));
+        assert_eq!(eval(&mut calc, "i == i"), Value::Float(1.0));
+    }
+
+    #[test]
+    fn logic_takes_any_nonzero_number_as_true() {
+        for (input, expected) in [("!0", 1.0), ("!5", 0.0), ("!!3", 1.0), ("2 && -1", 1.0), ("0 || 0.5", 1.0), ("1 && 0", 0.0)] {
+            assert_eq!(answer(input), Value::Float(expected), "{input}");
         }
+        assert_eq!(answer("(5 > 3) * 7"), Value::Float(7.0));
     }
 
-    None
+    #[test]
+    fn logic_short_circuits() {
+        let mut calc = Calculator::new();
+        assert_eq!(eval(&mut calc, "0 && nope"), Value::Float(0.0));
+        assert_eq!(eval(&mut calc, "1 || nope(2)"), Value::Float(1.0));
+        assert!(matches!(error(&mut calc, "1 && nope"), ErrorKind::VarDoesNotExist(_)));
+        assert!(matches!(error(&mut calc, "0 || nope"), ErrorKind::VarDoesNotExist(_)));
+        // ln(-1) is complex and cannot be compared, so only the guard may be solved
+        calc.eval("big(x) = x > 0 && ln(x) > 1").unwrap();
+        assert_eq!(eval(&mut calc, "big(-1)"), Value::Float(0.0));
+        assert_eq!(eval(&mut calc, "big(10)"), Value::Float(1.0));
+    }
+
+    #[test]
+    fn errors_point_at_the_operand() {
+        let mut calc = Calculator::new();
+        let e = calc.eval("1 + foo * 2").unwrap_err();
+        assert_eq!(e.span.map(|span| (span.start, span.end)), Some((4, 7)));
+    }
 }
@@ -0,0 +1,80 @@
+// helpers shared by the unit tests
+
+use super::core::{Calc, Calculator, EvalResult};
+use super::error::ErrorKind;
+use super::value::Value;
+
+// the answer to an expression, panics if it is an error or a command
+pub fn eval(calc: &mut Calculator, input: &str) -> Value {
+    match calc.eval(input) {
+        Ok(EvalResult::Answer(value)) => value,
+        other => panic!("{:.40} gave {other:?}", input),
+    }
+}
+
+// what a command or assignment said back
+pub fn feedback(calc: &mut Calculator, input: &str) -> String {
+    match calc.eval(input) {
+        Ok(EvalResult::Feedback(message)) => message,
+        other => panic!("{input} gave {other:?}"),
+    }
+}
+
+pub fn error(calc: &mut Calculator, input: &str) -> ErrorKind {
+    match calc.eval(input) {
+        Err(e) => e.kind,
+        Ok(result) => panic!("{input} gave {result:?}"),
+    }
+}
+
+// an expression solved by a new calculator
+pub fn answer(input: &str) -> Value {
+    eval(&mut Calculator::new(), input)
+}
+
+// a float answer, panics if the answer has another type
+pub fn float_of(calc: &mut Calculator, input: &str) -> f64 {
+    match eval(calc, input) {
+        Value::Float(x) => x,
+        other => panic!("{input} gave {other:?}"),
+    }
+}
+
+pub fn float(input: &str) -> f64 {
+    float_of(&mut Calculator::new(), input)
+}
+
+pub fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() <= 1e-12 * expected.abs().max(1.0), "{actual} is not {expected}");
+}
+
+pub fn ratio(numer: i64, denom: i64) -> Value {
+    Value::Rational(num_rational::BigRational::new(numer.into(), denom.into()))
+}
+
+// the answer as the calculator displays it
+pub fn shown(calc: &mut Calculator, input: &str) -> String {
+    let value = eval(calc, input);
+    calc.format(&value)
+}
+
+// a file in the temp directory that is deleted when dropped
+pub struct TempFile(pub std::path::PathBuf);
+
+impl TempFile {
+    pub fn new(name: &str, contents: &str) -> TempFile {
+        let path = std::env::temp_dir().join(format!("rustcalc-{}-{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        TempFile(path)
+    }
+
+    pub fn path(&self) -> String {
+        self.0.display().to_string()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
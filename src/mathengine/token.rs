@@ -1,4 +1,12 @@
-use crate::{mathengine::{core::Calculator, error::CalcError, token::Token::*}, operators};
+use crate::mathengine::{
+    core::Calculator,
+    error::{CalcError, ErrorKind},
+    token::Token::*,
+    value::Value,
+};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Num, Zero};
 use std::fmt;
 #[derive(Clone, Debug, PartialEq)]
 pub enum Token {
@@ -14,6 +22,18 @@ pub enum Token {
     BitwiseAnd,        // &
     BitwiseOr,         // |
     BitwiseXor,        // ^^
+    BitwiseNot,        // ~
+
+    LessThan,           // <
+    LessThanOrEqual,    // <=
+    GreaterThan,        // >
+    GreaterThanOrEqual, // >=
+    Equal,              // ==
+    NotEqual,           // !=
+
+    LogicalAnd, // &&
+    LogicalOr,  // ||
+    LogicalNot, // !
 
     LeftBracket,  // (
     RightBracket, // )
@@ -24,50 +44,53 @@ pub enum Token {
 
     UnresolvedString(Vec<u8>),
     Variable(Vec<u8>),
+    Captured(Vec<u8>), // $name, the value of a variable when a function is defined
     FunctionName(Vec<u8>),
     FunctionArg(usize),
 
-    Number(f64),
+    Number(Value),
 }
 
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let msg = match self {
-            Addition => format!("+ "),
-            Subtraction => format!("- "),
-            Division => format!("/ "),
-            Multiplication => format!("* "),
-            Modulation => format!("% "),
-            Exponentation => format!("^ "),
-            BitwiseLeftShift => format!("<< "),
-            BitwiseRightShift => format!(">> "),
-            BitwiseAnd => format!("& "),
-            BitwiseOr => format!("| "),
-            BitwiseXor => format!("^^ "),
-            LeftBracket => format!("( "),
-            RightBracket => format!(") "),
-            Comma => format!(", "),
-            Assignment => format!("= "),
-            UnresolvedString(name) => format!("{} ", String::from_utf8_lossy(&name)),
-            Variable(name) => format!("{} ", String::from_utf8_lossy(&name)),
-            FunctionName(name)=> format!("{} ", String::from_utf8_lossy(&name)),
-            FunctionArg(index) => format!("[{}] ", index),
-            Number(num) => format!("{} ", num),
-        };
-        write!(f, "{}", msg)
-    }
-}
-
-pub fn print_token_list(tokens: &[Token]) {
-    let mut msg = String::new();
-    for token in tokens {
-        msg.push_str(&format!("{}", token));
+        match self {
+            Addition => write!(f, "+"),
+            Subtraction => write!(f, "-"),
+            Division => write!(f, "/"),
+            Multiplication => write!(f, "*"),
+            Modulation => write!(f, "%"),
+            Exponentation => write!(f, "^"),
+            BitwiseLeftShift => write!(f, "<<"),
+            BitwiseRightShift => write!(f, ">>"),
+            BitwiseAnd => write!(f, "&"),
+            BitwiseOr => write!(f, "|"),
+            BitwiseXor => write!(f, "^^"),
+            BitwiseNot => write!(f, "~"),
+            LessThan => write!(f, "<"),
+            LessThanOrEqual => write!(f, "<="),
+            GreaterThan => write!(f, ">"),
+            GreaterThanOrEqual => write!(f, ">="),
+            Equal => write!(f, "=="),
+            NotEqual => write!(f, "!="),
+            LogicalAnd => write!(f, "&&"),
+            LogicalOr => write!(f, "||"),
+            LogicalNot => write!(f, "!"),
+            LeftBracket => write!(f, "("),
+            RightBracket => write!(f, ")"),
+            Comma => write!(f, ","),
+            Assignment => write!(f, "="),
+            UnresolvedString(name) => write!(f, "{}", String::from_utf8_lossy(name)),
+            Variable(name) => write!(f, "{}", String::from_utf8_lossy(name)),
+            Captured(name) => write!(f, "${}", String::from_utf8_lossy(name)),
+            FunctionName(name) => write!(f, "{}", String::from_utf8_lossy(name)),
+            FunctionArg(index) => write!(f, "[{}]", index),
+            Number(num) => write!(f, "{}", num),
+        }
     }
-    println!("{}", msg);
 }
 
 pub fn match_token(key: &[u8]) -> Option<Token> {
-    let result = match key {
+    match key {
         b"<<" => Some(BitwiseLeftShift),
         b">>" => Some(BitwiseRightShift),
         b"^" => Some(Exponentation),
@@ -79,149 +102,148 @@ pub fn match_token(key: &[u8]) -> Option<Token> {
         b"&" => Some(BitwiseAnd),
         b"|" => Some(BitwiseOr),
         b"^^" => Some(BitwiseXor),
+        b"~" => Some(BitwiseNot),
+        b"<" => Some(LessThan),
+        b"<=" => Some(LessThanOrEqual),
+        b">" => Some(GreaterThan),
+        b">=" => Some(GreaterThanOrEqual),
+        b"==" => Some(Equal),
+        b"!=" => Some(NotEqual),
+        b"&&" => Some(LogicalAnd),
+        b"||" => Some(LogicalOr),
+        b"!" => Some(LogicalNot),
         b"(" => Some(LeftBracket),
         b")" => Some(RightBracket),
         b"," => Some(Comma),
         b"=" => Some(Assignment),
         _ => None,
-    };
-    result
+    }
 }
 
-pub fn tokenize(input: &Vec<u8>) -> Result<Vec<Token>, CalcError> {
+// a range of bytes in the input, end is exclusive
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    // the smallest span covering both self and other
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+// a token and where it came from in the input
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+pub fn tokenize(input: &[u8]) -> Result<Vec<SpannedToken>, CalcError> {
     let mut tokens = get_tokens(input)?;
     resolve_strings(&mut tokens);
     Ok(tokens)
 }
 
-fn get_tokens(input: &[u8]) -> Result<Vec<Token>, CalcError> {
+fn get_tokens(input: &[u8]) -> Result<Vec<SpannedToken>, CalcError> {
     // this will store the list of tokens, and will be returned
-    let mut tokens: Vec<Token> = Vec::new();
+    let mut tokens: Vec<SpannedToken> = Vec::new();
+    let mut push = |token: Token, start: usize, end: usize| {
+        tokens.push(SpannedToken {
+            token,
+            span: Span::new(start, end),
+        })
+    };
 
-    let is_number_part = |x: u8| (x as char).is_digit(10) || x == b'.';
-    let is_string_part = |x: u8| (x as char).is_alphabetic() || x == b'_';
+    let is_number_part = |x: u8| x.is_ascii_digit() || x == b'.';
+    let is_string_start = |x: u8| (x as char).is_alphabetic() || x == b'_';
+    // names can contain digits after the first character, eg. atan2 or x1
+    let is_string_part = |x: u8| is_string_start(x) || x.is_ascii_digit();
 
     // this loop parses the input bytes into a vec of raw tokens
     let mut i: usize = 0;
     let len = input.len();
     'outer: while i < len {
+        // whitespace only separates tokens
+        if input[i].is_ascii_whitespace() {
+            i += 1;
+        }
         // numbers
-        if is_number_part(input[i]) {
-            let slice_bounds = get_token_bounds(is_number_part, i, &input);
-            let string_attempt = input[slice_bounds.0..slice_bounds.1].to_vec();
-            match String::from_utf8(string_attempt.to_vec()).unwrap().parse::<f64>()
-            {
-                Ok(n) => {
-                    tokens.push(Number(n));
-                }
-                Err(_) => return Err(CalcError::CannotParseNumber(string_attempt)),
-            }
-            i = slice_bounds.1;
+        else if is_number_part(input[i]) {
+            let (n, end) = get_number(input, i)?;
+            push(Number(n), i, end);
+            i = end;
         }
         // strings
-        else if is_string_part(input[i]) {
-            let slice_bounds: (usize, usize) = get_token_bounds(is_string_part, i, &input);
+        else if is_string_start(input[i]) {
+            let slice_bounds: (usize, usize) = get_token_bounds(is_string_part, i, input);
             // just stored as a string for now, will later be turned into a variable, function, or command
-            tokens.push(UnresolvedString(
-                input[slice_bounds.0..slice_bounds.1].to_vec(),
-            ));
+            push(
+                UnresolvedString(input[slice_bounds.0..slice_bounds.1].to_vec()),
+                slice_bounds.0,
+                slice_bounds.1,
+            );
             i = slice_bounds.1;
+        }
+        // captured variables, eg. $k
+        else if input[i] == b'$' && input.get(i + 1).is_some_and(|x| is_string_start(*x)) {
+            let (_, end) = get_token_bounds(is_string_part, i + 1, input);
+            push(Captured(input[i + 1..end].to_vec()), i, end);
+            i = end;
         } else if input[i].is_ascii() {
             //operator token
             for j in (0..3).rev() {
                 if i + j <= len {
                     if let Some(t) = match_token(&input[i..i + j]) {
-                        tokens.push(t.clone());
+                        push(t, i, i + j);
                         i += j;
                         continue 'outer;
                     }
                 }
             }
-            let operator_string = input[i..(i + 3).clamp(1, input.len())].to_vec();
-            return Err(CalcError::CannotParseOperator(operator_string));
+            let operator_string = String::from_utf8_lossy(&input[i..i + 1]).into_owned();
+            return Err(CalcError::new(ErrorKind::CannotParseOperator(operator_string), Span::new(i, i + 1)));
         } else {
-            return Err(CalcError::InvalidTokenSeq);
-        }
-    }
-
-    // handle signs (negative, positive)
-    let mut i: usize = 0;
-    if tokens.len() == 0 {
-        return Ok(tokens);
-    }
-    while i < tokens.len() - 1 {
-        match tokens[i] {
-            Subtraction => {
-                if let Number(n) = tokens[i + 1] {
-                    if i == 0 {
-                        tokens[i + 1] = Number(-n);
-                        tokens.remove(i);
-                    } else if match tokens[i - 1] {operators!() => true, _ => false}
-                    {
-                        tokens[i + 1] = Number(-n);
-                        tokens.remove(i);
-                    } else {
-                        i += 1;
-                    }
-                } else {
-                    i += 1;
-                }
-            }
-            Addition => {
-                if let Number(n) = tokens[i + 1] {
-                    if i == 0 {
-                        tokens[i + 1] = Number(n);
-                        tokens.remove(i);
-                    } else if match tokens[i - 1] {operators!() => true, _ => false}
-                    {
-                        tokens[i + 1] = Number(n);
-                        tokens.remove(i);
-                    } else {
-                        i += 1;
-                    }
-                } else {
-                    i += 1;
-                }
-            }
-            _ => {
-                i += 1;
-            }
+            return Err(CalcError::new(ErrorKind::InvalidTokenSeq, Span::new(i, i + 1)));
         }
     }
 
     Ok(tokens)
 }
 
-fn resolve_strings(tokens: &mut Vec<Token>) {
+fn resolve_strings(tokens: &mut [SpannedToken]) {
     for i in 0..tokens.len() {
-        if let UnresolvedString(name) = &tokens[i] {
-            if i < tokens.len() - 1 {
-                match tokens[i + 1] {
-                    LeftBracket => tokens[i] = FunctionName(name.clone()),
-                    _ => tokens[i] = Variable(name.clone()),
-                }
+        if let UnresolvedString(name) = &tokens[i].token {
+            if tokens.get(i + 1).is_some_and(|next| next.token == LeftBracket) {
+                tokens[i].token = FunctionName(name.clone());
             } else {
-                tokens[i] = Variable(name.clone());
+                tokens[i].token = Variable(name.clone());
             }
         }
     }
 }
 
 pub trait TokenHandling {
-    fn resolve_variables(&self, tokens: &mut Vec<Token>) -> Result<(), CalcError>;
+    fn resolve_captures(&self, tokens: &mut [SpannedToken]) -> Result<(), CalcError>;
 }
 
 impl TokenHandling for Calculator {
-    fn resolve_variables(&self, tokens: &mut Vec<Token>) -> Result<(), CalcError> {
-        for token in tokens {
-            if let Variable(name) = token {
-                let hash_try = self.variables.get(name);
-                match hash_try {
+    // replaces each $name with the current value of name
+    fn resolve_captures(&self, tokens: &mut [SpannedToken]) -> Result<(), CalcError> {
+        for spanned in tokens {
+            if let Captured(name) = &spanned.token {
+                match self.get_variable(name) {
                     Some(number) => {
-                        *token = number.clone();
+                        spanned.token = Number(number);
                     }
                     None => {
-                        return Err(CalcError::VarDoesNotExist(name.to_vec()));
+                        return Err(CalcError::new(ErrorKind::VarDoesNotExist(String::from_utf8_lossy(name).into_owned()), spanned.span));
                     }
                 }
             }
@@ -230,6 +252,111 @@ impl TokenHandling for Calculator {
     }
 }
 
+// parses the number literal starting at start, returns its value and the index after it
+// supports 1.5, 6.02e23, 1_000_000, 0xFF, 0b1010 and 0o755
+// literals are always read exactly, the solver turns them into floats when not in exact mode
+fn get_number(input: &[u8], start: usize) -> Result<(Value, usize), CalcError> {
+    // radix prefixed integers
+    if input[start] == b'0' {
+        let radix = match input.get(start + 1) {
+            Some(b'x' | b'X') => Some(16),
+            Some(b'b' | b'B') => Some(2),
+            Some(b'o' | b'O') => Some(8),
+            _ => None,
+        };
+        if let Some(radix) = radix {
+            let (_, end) = get_token_bounds(|x| x.is_ascii_alphanumeric() || x == b'_', start + 1, input);
+            let n = parse_radix_literal(&input[start..end], radix)
+                .map_err(|kind| CalcError::new(kind, Span::new(start, end)))?;
+            return Ok((n, end));
+        }
+    }
+
+    // decimals, the exponent is only taken if digits follow it so "2e" stays 2 and e
+    let (_, mut end) = get_token_bounds(|x| x.is_ascii_digit() || x == b'.' || x == b'_', start, input);
+    if let Some(b'e' | b'E') = input.get(end) {
+        let mut exponent_start = end + 1;
+        if let Some(b'+' | b'-') = input.get(exponent_start) {
+            exponent_start += 1;
+        }
+        if input.get(exponent_start).is_some_and(u8::is_ascii_digit) {
+            (_, end) = get_token_bounds(|x| x.is_ascii_digit() || x == b'_', exponent_start, input);
+        }
+    }
+
+    let literal = &input[start..end];
+    let span = Span::new(start, end);
+    check_digit_separators(literal, literal, |x| x.is_ascii_digit()).map_err(|kind| CalcError::new(kind, span))?;
+    let digits: String = literal.iter().filter(|&&x| x != b'_').map(|&x| x as char).collect();
+    let n = parse_decimal_literal(&digits, literal).map_err(|kind| CalcError::new(kind, span))?;
+    Ok((n, end))
+}
+
+// digits is the literal without digit separators, eg. 1.5e3
+fn parse_decimal_literal(digits: &str, literal: &[u8]) -> Result<Value, ErrorKind> {
+    let cannot_parse = || ErrorKind::CannotParseNumber(String::from_utf8_lossy(literal).into_owned());
+    let (mantissa, exponent) = match digits.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i64>().map_err(|_| cannot_parse())?),
+        None => (digits, 0),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if whole.len() + fraction.len() == 0 || fraction.contains('.') {
+        return Err(cannot_parse());
+    }
+
+    // 1.25e3 is 125 * 10^(3 - 2), the exponent can be anything an i64 holds so check before using it
+    let exponent = exponent
+        .checked_sub(fraction.len() as i64)
+        .filter(|exponent| exponent.unsigned_abs() <= MAX_LITERAL_EXPONENT)
+        .ok_or_else(|| ErrorKind::NumberTooLarge(String::from_utf8_lossy(literal).into_owned()))?;
+    let significand = BigInt::from_str_radix(&format!("{whole}{fraction}"), 10).map_err(|_| cannot_parse())?;
+    let scale = BigInt::from(10).pow(exponent.unsigned_abs() as u32);
+    let n = if exponent < 0 {
+        BigRational::new(significand, scale)
+    } else {
+        BigRational::from_integer(significand * scale)
+    };
+    Ok(Value::Rational(n))
+}
+
+// literals like 1e100000 would take a long time to read exactly
+pub(crate) const MAX_LITERAL_EXPONENT: u64 = 10_000;
+
+// literal includes the 0x, 0b or 0o prefix
+fn parse_radix_literal(literal: &[u8], radix: u32) -> Result<Value, ErrorKind> {
+    let body = &literal[2..];
+    if body.iter().all(|&x| x == b'_') {
+        return Err(ErrorKind::MissingDigits(String::from_utf8_lossy(literal).into_owned()));
+    }
+    if body.iter().any(|&x| x != b'_' && (x as char).to_digit(radix).is_none()) {
+        return Err(ErrorKind::InvalidDigit(String::from_utf8_lossy(literal).into_owned(), radix));
+    }
+    check_digit_separators(literal, body, |x| x.is_ascii_alphanumeric())?;
+
+    let n = body
+        .iter()
+        .filter_map(|&x| (x as char).to_digit(radix))
+        .fold(BigInt::zero(), |n, digit| n * radix + digit);
+    Ok(Value::from(n))
+}
+
+// every '_' must sit between two digits, eg. 1_000 but not 1__000, _1 or 1_
+fn check_digit_separators<F>(literal: &[u8], body: &[u8], is_digit: F) -> Result<(), ErrorKind>
+where
+    F: Fn(&u8) -> bool,
+{
+    for (i, x) in body.iter().enumerate() {
+        if *x == b'_' {
+            let before = i.checked_sub(1).and_then(|j| body.get(j));
+            let after = body.get(i + 1);
+            if !before.is_some_and(&is_digit) || !after.is_some_and(&is_digit) {
+                return Err(ErrorKind::MisplacedDigitSeparator(String::from_utf8_lossy(literal).into_owned()));
+            }
+        }
+    }
+    Ok(())
+}
+
 fn get_token_bounds<F>(f: F, start: usize, input_chars: &[u8]) -> (usize, usize)
 where
     F: Fn(u8) -> bool,
@@ -242,3 +369,92 @@ where
 
     (start, end)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathengine::testing::ratio;
+
+    fn tokenize_err(input: &str) -> ErrorKind {
+        tokenize(input.as_bytes()).unwrap_err().kind
+    }
+
+    // the value of a literal that is the whole input
+    fn number(input: &str) -> Value {
+        match &tokenize(input.as_bytes()).unwrap()[..] {
+            [SpannedToken { token: Number(n), .. }] => n.clone(),
+            tokens => panic!("{input} gave {tokens:?}"),
+        }
+    }
+
+    fn spans(tokens: &[SpannedToken]) -> Vec<(usize, usize)> {
+        tokens.iter().map(|t| (t.span.start, t.span.end)).collect()
+    }
+
+    #[test]
+    fn tokens_carry_byte_spans() {
+        let tokens = tokenize(b" 12 +  sin(x)").unwrap();
+        assert_eq!(spans(&tokens), [(1, 3), (4, 5), (7, 10), (10, 11), (11, 12), (12, 13)]);
+        let tokens = tokenize(b"\tx2 ^ 0xff\n").unwrap();
+        assert_eq!(spans(&tokens), [(1, 3), (4, 5), (6, 10)]);
+    }
+
+    #[test]
+    fn literal_errors_carry_spans() {
+        for (input, span) in [("3 @ 4", (2, 3)), ("1 + 0x1g", (4, 8)), ("2 + 1.2.3", (4, 9))] {
+            let e = tokenize(input.as_bytes()).unwrap_err();
+            assert_eq!(e.span.map(|s| (s.start, s.end)), Some(span), "{input}");
+        }
+    }
+
+    #[test]
+    fn scientific_notation() {
+        assert_eq!(number("6.02e23"), Value::from(602 * BigInt::from(10).pow(21)));
+        assert_eq!(number("1.5e-3"), ratio(3, 2000));
+        assert_eq!(number("1E+3"), Value::from(1000));
+        assert_eq!(number(".5"), ratio(1, 2));
+        assert_eq!(number("5."), Value::from(5));
+        // without digits after it the e is the constant, so 2e is 2 * e
+        let tokens = tokenize(b"2e").unwrap();
+        assert_eq!(tokens[1].token, Variable(b"e".to_vec()));
+    }
+
+    #[test]
+    fn radix_prefixes() {
+        assert_eq!(number("0xFF"), Value::from(255));
+        assert_eq!(number("0X1f"), Value::from(31));
+        assert_eq!(number("0b1010"), Value::from(10));
+        assert_eq!(number("0o755"), Value::from(493));
+        assert_eq!(number("0xff_ff"), Value::from(65535));
+    }
+
+    #[test]
+    fn digit_separators() {
+        assert_eq!(number("1_000_000"), Value::from(1_000_000));
+        assert_eq!(number("1_0.2_5e1_0"), Value::from(102_500_000_000));
+        for input in ["1__0", "1_", "1_.5", "0x_1", "0b1_"] {
+            assert!(matches!(tokenize_err(input), ErrorKind::MisplacedDigitSeparator(_)), "{input}");
+        }
+        // a leading _ starts a name
+        assert_eq!(tokenize(b"_1").unwrap()[0].token, Variable(b"_1".to_vec()));
+    }
+
+    #[test]
+    fn malformed_literals_are_precise_errors() {
+        assert!(matches!(tokenize_err("0x"), ErrorKind::MissingDigits(_)));
+        assert!(matches!(tokenize_err("0b102"), ErrorKind::InvalidDigit(_, 2)));
+        assert!(matches!(tokenize_err("0o8"), ErrorKind::InvalidDigit(_, 8)));
+        assert!(matches!(tokenize_err("1.2.3"), ErrorKind::CannotParseNumber(_)));
+        let e = tokenize(b"1 + 0xfg").unwrap_err();
+        assert_eq!((e.span.unwrap().start, e.span.unwrap().end), (4, 8));
+    }
+
+    #[test]
+    fn huge_exponents_are_errors() {
+        assert!(matches!(tokenize_err("1.5e-9223372036854775807"), ErrorKind::NumberTooLarge(_)));
+        assert!(matches!(tokenize_err("1e-9223372036854775808"), ErrorKind::NumberTooLarge(_)));
+        assert!(matches!(tokenize_err("1e9223372036854775807"), ErrorKind::NumberTooLarge(_)));
+        assert!(matches!(tokenize_err("1e10001"), ErrorKind::NumberTooLarge(_)));
+        assert!(tokenize(b"1e10000").is_ok());
+    }
+}
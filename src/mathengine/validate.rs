@@ -1,8 +1,10 @@
-use super::error::CalcError;
-use crate::mathengine::token::Token::{self, *};
+use super::error::{CalcError, ErrorKind};
+use crate::mathengine::token::{
+    Span, SpannedToken,
+    Token::{self, *},
+};
 
 // just all of the operator tokens as a macro so I dont have to type all of them every time
-#[macro_export]
 macro_rules! operators {
     () => {
         Assignment
@@ -17,84 +19,89 @@ macro_rules! operators {
             | BitwiseAnd
             | BitwiseOr
             | BitwiseXor
-            
+            | BitwiseNot
+            | LessThan
+            | LessThanOrEqual
+            | GreaterThan
+            | GreaterThanOrEqual
+            | Equal
+            | NotEqual
+            | LogicalAnd
+            | LogicalOr
+            | LogicalNot
     };
 }
 
-pub fn validate_token_list(tokens: &Vec<Token>) -> Result<(), CalcError> {
+pub fn validate_token_list(tokens: &[SpannedToken]) -> Result<(), CalcError> {
     // make sure input is not empty
-    if tokens.len() == 0 {
-        return Err(CalcError::NoInput);
+    if tokens.is_empty() {
+        return Err(ErrorKind::NoInput.into());
     }
 
     // make sure there are one or less '='
-    let mut equal_count = 0;
-    for token in tokens {
-        if token == &Assignment {
-            equal_count += 1;
-        }
-    }
-    if equal_count > 1 {
-        return Err(CalcError::TooManyAssignmentOps);
+    if let Some(second) = tokens.iter().filter(|t| t.token == Assignment).nth(1) {
+        return Err(CalcError::new(ErrorKind::TooManyAssignmentOps, second.span));
     }
 
     // make sure first and last tokens are valid
-    match tokens[0] {
-        Comma | RightBracket | operators!() => return Err(CalcError::InvalidFirstToken(tokens[0].clone())),
+    let first = &tokens[0];
+    match first.token {
+        // a leading '+', '-', '!' or '~' applies to the first operand
+        Comma | RightBracket | operators!() if !is_prefix(&first.token) => {
+            return Err(CalcError::new(ErrorKind::InvalidFirstToken(first.token.to_string()), first.span))
+        }
         _ => (),
     }
-    match tokens[tokens.len() - 1] {
+    let last = &tokens[tokens.len() - 1];
+    match last.token {
         Comma | LeftBracket | FunctionName(_) | operators!() => {
-            return Err(CalcError::InvalidLastToken(tokens[tokens.len()-1].clone()))
+            return Err(CalcError::new(ErrorKind::InvalidLastToken(last.token.to_string()), last.span))
         }
         _ => (),
     }
 
     // make sure all tokens are proceeded by a valid token
-    for i in 0..tokens.len() - 1 {
-        if !is_next_token_valid(&tokens[i], &tokens[i + 1]) {
-            return Err(CalcError::InvalidTokenSeq);
+    for pair in tokens.windows(2) {
+        if !is_next_token_valid(&pair[0].token, &pair[1].token) {
+            return Err(CalcError::new(ErrorKind::InvalidTokenSeq, pair[0].span.to(pair[1].span)));
         }
     }
 
     // make sure all brackets are closed
-    let mut bracket_depth: i32 = 0;
-    for token in tokens {
-        match token {
-            LeftBracket => bracket_depth += 1,
-            RightBracket => bracket_depth -= 1,
+    let mut open_brackets: Vec<Span> = Vec::new();
+    for spanned in tokens {
+        match spanned.token {
+            LeftBracket => open_brackets.push(spanned.span),
+            // a ')' with no '(' left to close
+            RightBracket if open_brackets.pop().is_none() => {
+                return Err(CalcError::new(ErrorKind::ImbalancedBrackets, spanned.span));
+            }
             _ => (),
         }
     }
-    if bracket_depth != 0 {
-        return Err(CalcError::ImbalancedBrackets);
+    if let Some(unclosed) = open_brackets.pop() {
+        return Err(CalcError::new(ErrorKind::ImbalancedBrackets, unclosed));
     }
-    bracket_depth = 0;
 
     // make sure commas are only found inside of functions
+    let mut bracket_depth: i32 = 0;
     let mut in_function_brackets: bool = false;
-    for token in tokens {
-        match token {
+    for spanned in tokens {
+        match spanned.token {
             FunctionName(_) => {
                 in_function_brackets = true;
             }
-            LeftBracket => {
-                if in_function_brackets {
-                    bracket_depth += 1;
-                }
+            LeftBracket if in_function_brackets => {
+                bracket_depth += 1;
             }
-            RightBracket => {
-                if in_function_brackets {
-                    bracket_depth -= 1;
-                    if bracket_depth == 0 {
-                        in_function_brackets = false;
-                    }
+            RightBracket if in_function_brackets => {
+                bracket_depth -= 1;
+                if bracket_depth == 0 {
+                    in_function_brackets = false;
                 }
             }
-            Comma => {
-                if !in_function_brackets {
-                    return Err(CalcError::MisplacedComma);
-                }
+            Comma if !in_function_brackets => {
+                return Err(CalcError::new(ErrorKind::MisplacedComma, spanned.span));
             }
             _ => {}
         }
@@ -106,112 +113,149 @@ pub fn validate_token_list(tokens: &Vec<Token>) -> Result<(), CalcError> {
 
 fn is_next_token_valid(current: &Token, next: &Token) -> bool {
     match current {
-        // current token is a number or var
-        Number(_) | Variable(_) => {
-            // if next token matches any of these, its invalid
-            match next {
-                Number(_) => return false,
-                Variable(_) => return false,
-                FunctionName(_) => return false,
-                LeftBracket => return false,
-                _ => return true,
-            };
+        // current token is a number
+        // a var, function or '(' after it is an implicit multiplication
+        Number(_) => !matches!(next, Number(_) | LogicalNot | BitwiseNot),
+
+        // current token is a var
+        // if next token matches any of these, its invalid
+        Variable(_) | Captured(_) => {
+            !matches!(next, Number(_) | Variable(_) | Captured(_) | FunctionName(_) | LeftBracket | LogicalNot | BitwiseNot)
         }
 
         // current token is an operator
-        operators!() => {
-            // if next token matches any of these, its invalid
-            match next {
-                operators!() => return false,
-                RightBracket => return false,
-                _ => return true,
-            };
-        }
+        // if next token matches any of these, its invalid
+        // a '+', '-', '!' or '~' after an operator, '(' or ',' applies to the next operand so it is allowed
+        operators!() => is_prefix(next) || !matches!(next, operators!() | RightBracket),
 
         // current token is a '('
-        LeftBracket => {
-            // if next token matches any of these, its invalid
-            match next {
-                operators!() => return false,
-                _ => return true,
-            };
-        }
+        // if next token matches any of these, its invalid
+        LeftBracket => is_prefix(next) || !matches!(next, operators!()),
 
         // current token is a ')'
-        RightBracket => {
-            // if next token matches any of these, its invalid
-            match next {
-                Number(_) => return false,
-                Variable(_) => return false,
-                FunctionName(_) => return false,
-                LeftBracket => return false,
-                _ => return true,
-            };
-        }
+        // a var, function or '(' after it is an implicit multiplication, eg. (a)(b)
+        RightBracket => !matches!(next, Number(_) | LogicalNot | BitwiseNot),
+
+        // current token is a ','
+        // if next token matches any of these, its invalid
+        Comma => is_prefix(next) || !matches!(next, operators!() | RightBracket | Comma),
 
         // current token is a function
-        FunctionName(_) => {
-            // the only valid next token is a left bracket
-            match next {
-                LeftBracket => return true,
-                _ => return false,
-            };
-        }
+        // the only valid next token is a left bracket
+        FunctionName(_) => next == &LeftBracket,
 
-        _ => return false,
-    };
+        _ => false,
+    }
 }
 
-pub fn is_valid_lhs_function(tokens: &Vec<Token>) -> Result<(), CalcError> {
+// operators that can come before an operand, eg. -x, !x or ~x
+fn is_prefix(token: &Token) -> bool {
+    matches!(token, Addition | Subtraction | LogicalNot | BitwiseNot)
+}
+
+// is_reserved says whether a name belongs to a function that cannot be reassigned
+pub fn is_valid_lhs_function<F>(tokens: &[SpannedToken], is_reserved: F) -> Result<(), CalcError>
+where
+    F: Fn(&[u8]) -> bool,
+{
     // make sure first token is a function name that is not reserved
-    match &tokens[0] {
-        FunctionName(name) => match &name[..] {
-            b"sin" => return Err(CalcError::FuncHardcodedReassignAttempt(b"sin".to_vec())),
-            b"cos" => return Err(CalcError::FuncHardcodedReassignAttempt(b"cos".to_vec())),
-            b"tan" => return Err(CalcError::FuncHardcodedReassignAttempt(b"tan".to_vec())),
-            b"asin" => return Err(CalcError::FuncHardcodedReassignAttempt(b"asin".to_vec())),
-            b"acos" => return Err(CalcError::FuncHardcodedReassignAttempt(b"acos".to_vec())),
-            b"atan" => return Err(CalcError::FuncHardcodedReassignAttempt(b"atan".to_vec())),
-            b"sqrt" => return Err(CalcError::FuncHardcodedReassignAttempt(b"sqrt".to_vec())),
-            b"fact" => return Err(CalcError::FuncHardcodedReassignAttempt(b"fact".to_vec())),
-            b"log" => return Err(CalcError::FuncHardcodedReassignAttempt(b"log".to_vec())),
-            b"ln" => return Err(CalcError::FuncHardcodedReassignAttempt(b"ln".to_vec())),
-            _ => (),
-        },
-        _ => return Err(CalcError::FuncNoName),
+    match &tokens[0].token {
+        FunctionName(name) => {
+            if is_reserved(name) {
+                let kind = ErrorKind::FuncHardcodedReassignAttempt(String::from_utf8_lossy(name).into_owned());
+                return Err(CalcError::new(kind, tokens[0].span));
+            }
+        }
+        _ => return Err(CalcError::new(ErrorKind::FuncNoName, tokens[0].span)),
     }
 
     // args must be encased in brackets
-    if &tokens[1] != &LeftBracket {
-        return Err(CalcError::FuncArgsNotInBrackets);
+    if tokens[1].token != LeftBracket {
+        return Err(CalcError::new(ErrorKind::FuncArgsNotInBrackets, tokens[1].span));
     }
-    if &tokens[tokens.len() - 1] != &RightBracket {
-        return Err(CalcError::FuncArgsNotInBrackets);
+    let last = &tokens[tokens.len() - 1];
+    if last.token != RightBracket {
+        return Err(CalcError::new(ErrorKind::FuncArgsNotInBrackets, last.span));
     }
 
     // contents of brackets must be variables and commas in alternating order
-    // eg. func(a,b,c)
+    // eg. func(a,b,c), with no variable named twice
     let mut prev_token_was_arg: bool = false;
+    let mut params: Vec<&[u8]> = Vec::new();
 
-    for token in &tokens[2..tokens.len() - 1] {
+    for spanned in &tokens[2..tokens.len() - 1] {
         if prev_token_was_arg {
-            match token {
+            match spanned.token {
                 Comma => {
                     prev_token_was_arg = false;
                     continue;
                 }
-                _ => return Err(CalcError::FuncExpectedComma),
+                _ => return Err(CalcError::new(ErrorKind::FuncExpectedComma, spanned.span)),
             }
         } else {
-            match token {
-                Variable(_) => {
+            match &spanned.token {
+                Variable(name) if params.contains(&name.as_slice()) => {
+                    let kind = ErrorKind::FuncDuplicateArg(String::from_utf8_lossy(name).into_owned());
+                    return Err(CalcError::new(kind, spanned.span));
+                }
+                Variable(name) => {
+                    params.push(name);
                     prev_token_was_arg = true;
                     continue;
                 }
-                _ => return Err(CalcError::FuncExpectedArg),
+                _ => return Err(CalcError::new(ErrorKind::FuncExpectedArg, spanned.span)),
             }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathengine::token::tokenize;
+
+    // the error for an invalid input and the text its span covers
+    fn invalid(input: &str) -> (ErrorKind, &str) {
+        let e = validate_token_list(&tokenize(input.as_bytes()).unwrap()).unwrap_err();
+        let span = e.span.expect("validation errors have a span");
+        (e.kind, &input[span.start..span.end])
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_tokens() {
+        assert!(matches!(invalid("x = 1 = 2"), (ErrorKind::TooManyAssignmentOps, "=")));
+        assert!(matches!(invalid("* 2"), (ErrorKind::InvalidFirstToken(_), "*")));
+        assert!(matches!(invalid("2 ^"), (ErrorKind::InvalidLastToken(_), "^")));
+        assert!(matches!(invalid("2 * * 3"), (ErrorKind::InvalidTokenSeq, "* *")));
+        assert!(matches!(invalid("(1 + (2)"), (ErrorKind::ImbalancedBrackets, "(")));
+        assert!(matches!(invalid("1 + 2) * 3"), (ErrorKind::ImbalancedBrackets, ")")));
+        assert!(matches!(invalid("(1, 2)"), (ErrorKind::MisplacedComma, ",")));
+    }
+
+    #[test]
+    fn unclosed_brackets_point_at_the_innermost() {
+        let input = "((1 + 2) * (3";
+        let e = validate_token_list(&tokenize(input.as_bytes()).unwrap()).unwrap_err();
+        assert_eq!(e.span, Some(Span::new(11, 12)));
+        assert!(validate_token_list(&[]).unwrap_err().span.is_none());
+    }
+
+    #[test]
+    fn function_definitions_point_at_bad_parameters() {
+        let check = |input: &str| {
+            let e = is_valid_lhs_function(&tokenize(input.as_bytes()).unwrap(), |name| name == b"sin").unwrap_err();
+            let span = e.span.unwrap();
+            (e.kind, input[span.start..span.end].to_string())
+        };
+        assert!(matches!(check("sin(x)"), (ErrorKind::FuncHardcodedReassignAttempt(_), s) if s == "sin"));
+        assert!(matches!(check("f(x y)"), (ErrorKind::FuncExpectedComma, s) if s == "y"));
+        assert!(matches!(check("f(x, 2)"), (ErrorKind::FuncExpectedArg, s) if s == "2"));
+        assert!(matches!(check("x"), (ErrorKind::FuncNoName, s) if s == "x"));
+        assert!(matches!(check("f(x, y, x)"), (ErrorKind::FuncDuplicateArg(name), s) if name == "x" && s == "x"));
+        // the second x is the one pointed at
+        let e = is_valid_lhs_function(&tokenize(b"f(x, y, x)").unwrap(), |_| false).unwrap_err();
+        assert_eq!(e.span, Some(Span::new(8, 9)));
+    }
+}
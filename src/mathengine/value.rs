@@ -0,0 +1,481 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::{FromPrimitive, One, Signed, ToPrimitive, Zero};
+
+use super::decimal::{Decimal, DecimalContext};
+
+/// A number the calculator works with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// A floating point number, used in float mode and wherever an exact result is not possible.
+    Float(f64),
+    /// An exact fraction in lowest terms, used in exact mode.
+    Rational(BigRational),
+    /// A decimal rounded to the calculators precision, used in decimal mode.
+    Decimal(Decimal),
+    /// A complex number with a non-zero imaginary part. Complex numbers are always floating point
+    /// and results with no imaginary part become real numbers again.
+    Complex(Complex64),
+}
+
+/// Whether the calculator works with floating point numbers, exact fractions or big decimals.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NumberMode {
+    Float,
+    /// Integers are big integers and division gives fractions. Transcendental functions such as
+    /// `sin` or `ln`, and constants such as `pi`, still give floating point numbers.
+    Exact,
+    /// Every result is rounded to the calculators precision, including roots, logs, exponentials,
+    /// trig functions and the constants `pi`, `e` and `tau`. Functions without a precise version,
+    /// such as `sinh` or `gamma`, give floating point numbers.
+    Decimal,
+    /// Every value is an integer that fits in the calculators word type, eg. `u8`. Division
+    /// truncates, and results that do not fit either wrap or fail depending on the overflow
+    /// setting. Anything that is not an integer, such as `1.5` or `sqrt(2)`, is an error.
+    Programmer,
+}
+
+/// How exact fractions are displayed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RationalDisplay {
+    /// eg. `1/3`
+    Fraction,
+    /// Rounded to this many digits after the decimal point, eg. `0.3333`
+    Decimal(usize),
+}
+
+/// How complex numbers are displayed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ComplexDisplay {
+    /// eg. `3 + 4i`
+    Rectangular,
+    /// The magnitude and the angle in the calculators angle unit, eg. `5 ∠ 0.9272952180016122`
+    Polar,
+}
+
+// exact results that would need more bits than this fall back to floating point, so something
+// like 3^(10^9) does not hang the calculator
+const EXACT_BITS_LIMIT: u64 = 1 << 20;
+
+impl Value {
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Value::Float(x) => *x,
+            Value::Rational(r) => r.to_f64().unwrap_or(f64::NAN),
+            Value::Decimal(d) => d.to_f64(),
+            // a complex number has no real value
+            Value::Complex(_) => f64::NAN,
+        }
+    }
+
+    pub(crate) fn is_complex(&self) -> bool {
+        matches!(self, Value::Complex(_))
+    }
+
+    pub(crate) fn to_complex(&self) -> Complex64 {
+        match self {
+            Value::Complex(z) => *z,
+            _ => Complex64::from(self.to_f64()),
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Value::Float(x) => *x == 0.0,
+            Value::Rational(r) => r.is_zero(),
+            Value::Decimal(d) => d.is_zero(),
+            Value::Complex(z) => z.is_zero(),
+        }
+    }
+
+    // the value as an exact integer, if it is one
+    pub(crate) fn to_integer(&self) -> Option<BigInt> {
+        match self {
+            Value::Rational(r) if r.is_integer() => Some(r.to_integer()),
+            Value::Decimal(d) => d.to_integer(),
+            _ => None,
+        }
+    }
+
+    // the value as an integer if it is a whole number, unlike to_integer this includes floats
+    pub(crate) fn whole_number(&self) -> Option<BigInt> {
+        match self {
+            Value::Float(x) if x.fract() == 0.0 => BigInt::from_f64(*x),
+            _ => self.to_integer(),
+        }
+    }
+
+    // the exact value of a fraction or decimal, None for floats
+    pub(crate) fn to_rational(&self) -> Option<BigRational> {
+        match self {
+            Value::Float(_) | Value::Complex(_) => None,
+            Value::Rational(r) => Some(r.clone()),
+            Value::Decimal(d) => Some(d.to_rational()),
+        }
+    }
+
+    // the value as a decimal rounded to the context, None for floats
+    pub(crate) fn to_decimal(&self, ctx: DecimalContext) -> Option<Decimal> {
+        match self {
+            Value::Float(_) | Value::Complex(_) => None,
+            Value::Rational(r) => Some(Decimal::from_rational(r, ctx)),
+            Value::Decimal(d) => Some(d.round(ctx)),
+        }
+    }
+
+    pub(crate) fn abs(&self) -> Value {
+        match self {
+            Value::Float(x) => Value::Float(x.abs()),
+            Value::Rational(r) => Value::Rational(r.abs()),
+            Value::Decimal(d) => Value::Decimal(d.abs()),
+            Value::Complex(z) => Value::Float(z.norm()),
+        }
+    }
+
+    // unlike f64::signum, the sign of 0 is 0
+    pub(crate) fn sign(&self) -> Value {
+        match self {
+            Value::Float(x) if *x == 0.0 || x.is_nan() => Value::Float(*x),
+            Value::Float(x) => Value::Float(x.signum()),
+            Value::Rational(r) => Value::Rational(r.signum()),
+            Value::Decimal(d) => Value::Decimal(d.signum()),
+            // the point on the unit circle in the same direction
+            Value::Complex(z) => Value::Complex(z / z.norm()),
+        }
+    }
+
+    pub(crate) fn floor(&self) -> Value {
+        match self {
+            Value::Float(x) => Value::Float(x.floor()),
+            Value::Rational(r) => Value::Rational(r.floor()),
+            Value::Decimal(d) => Value::Decimal(d.floor()),
+            // both parts are rounded
+            Value::Complex(z) => Value::Complex(Complex64::new(z.re.floor(), z.im.floor())),
+        }
+    }
+
+    pub(crate) fn ceil(&self) -> Value {
+        match self {
+            Value::Float(x) => Value::Float(x.ceil()),
+            Value::Rational(r) => Value::Rational(r.ceil()),
+            Value::Decimal(d) => Value::Decimal(d.ceil()),
+            // both parts are rounded
+            Value::Complex(z) => Value::Complex(Complex64::new(z.re.ceil(), z.im.ceil())),
+        }
+    }
+
+    // halfway cases round away from zero
+    pub(crate) fn round(&self) -> Value {
+        match self {
+            Value::Float(x) => Value::Float(x.round()),
+            Value::Rational(r) => Value::Rational(r.round()),
+            Value::Decimal(d) => Value::Decimal(d.round_whole()),
+            // both parts are rounded
+            Value::Complex(z) => Value::Complex(Complex64::new(z.re.round(), z.im.round())),
+        }
+    }
+
+    pub(crate) fn trunc(&self) -> Value {
+        match self {
+            Value::Float(x) => Value::Float(x.trunc()),
+            Value::Rational(r) => Value::Rational(r.trunc()),
+            Value::Decimal(d) => Value::Decimal(d.trunc()),
+            // both parts are rounded
+            Value::Complex(z) => Value::Complex(Complex64::new(z.re.trunc(), z.im.trunc())),
+        }
+    }
+
+    // exact values are compared exactly, anything involving a float is compared as floats
+    pub(crate) fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Rational(a), Value::Rational(b)) => Some(a.cmp(b)),
+            (Value::Decimal(a), Value::Decimal(b)) => Some(a.cmp(b)),
+            _ => match (self.to_rational(), other.to_rational()) {
+                (Some(a), Some(b)) => Some(a.cmp(&b)),
+                _ => self.to_f64().partial_cmp(&other.to_f64()),
+            },
+        }
+    }
+
+    // a^b, exact when a is exact and b is an integer, or when b is a fraction and the root is exact
+    pub(crate) fn pow(&self, exponent: &Value) -> Option<Value> {
+        let (Value::Rational(base), Value::Rational(exponent)) = (self, exponent) else {
+            return None;
+        };
+        let root = exponent.denom().to_u32()?;
+        let base = if root == 1 { base.clone() } else { exact_root(base, root)? };
+        let power = exponent.numer().to_i32()?;
+        let bits = base.numer().bits().max(base.denom().bits());
+        if bits.saturating_mul(power.unsigned_abs() as u64) > EXACT_BITS_LIMIT {
+            return None;
+        }
+        if base.is_zero() && power < 0 {
+            return None;
+        }
+        Some(Value::Rational(base.pow(power)))
+    }
+
+    // the nth root, if it is exact
+    pub(crate) fn root(&self, n: &Value) -> Option<Value> {
+        match (self, n.to_integer().and_then(|n| n.to_u32())) {
+            (Value::Rational(r), Some(n)) if n > 0 => exact_root(r, n).map(Value::Rational),
+            _ => None,
+        }
+    }
+}
+
+// the exact nth root of a fraction, if both its numerator and denominator have one
+fn exact_root(r: &BigRational, n: u32) -> Option<BigRational> {
+    if r.is_negative() {
+        // only odd roots of negative numbers are real
+        return match n % 2 {
+            1 => exact_root(&-r, n).map(|root| -root),
+            _ => None,
+        };
+    }
+    let numer = r.numer().nth_root(n);
+    let denom = r.denom().nth_root(n);
+    if numer.pow(n) == *r.numer() && denom.pow(n) == *r.denom() {
+        Some(BigRational::new(numer, denom))
+    } else {
+        None
+    }
+}
+
+// n! as an exact integer, None if it would be too large to compute quickly
+pub(crate) fn exact_factorial(n: &BigInt) -> Option<BigInt> {
+    let n = n.to_u64()?;
+    // 30000! has about 120000 digits
+    if n > 30_000 {
+        return None;
+    }
+    Some((2..=n).fold(BigInt::one(), |acc, k| acc * k))
+}
+
+// n! / (n - r)!, the number of ordered ways to pick r things from n
+pub(crate) fn exact_permutations(n: &BigInt, r: &BigInt) -> Option<BigInt> {
+    let r = r.to_u64()?;
+    if r > 30_000 {
+        return None;
+    }
+    Some((0..r).fold(BigInt::one(), |acc, k| acc * (n - k)))
+}
+
+pub(crate) fn exact_combinations(n: &BigInt, r: &BigInt) -> Option<BigInt> {
+    // nCr(n, r) == nCr(n, n - r), and the smaller one needs fewer steps
+    let r = r.min(&(n - r)).to_u64()?;
+    if r > 30_000 {
+        return None;
+    }
+    let mut result = BigInt::one();
+    for k in 0..r {
+        // every partial product is itself a binomial coefficient, so the division is exact
+        result = result * (n - k) / (k + 1);
+    }
+    Some(result)
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Float(x) => write!(f, "{x}"),
+            Value::Rational(r) if r.is_integer() => write!(f, "{}", r.numer()),
+            Value::Rational(r) => write!(f, "{}/{}", r.numer(), r.denom()),
+            Value::Decimal(d) => write!(f, "{d}"),
+            Value::Complex(z) => {
+                // i and -i rather than 1i and -1i
+                let im = if z.im.abs() == 1.0 { String::new() } else { z.im.abs().to_string() };
+                let sign = if z.im < 0.0 { "-" } else { "+" };
+                if z.re == 0.0 {
+                    write!(f, "{}{im}i", if z.im < 0.0 { "-" } else { "" })
+                } else {
+                    write!(f, "{} {sign} {im}i", z.re)
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for NumberMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumberMode::Float => write!(f, "float"),
+            NumberMode::Exact => write!(f, "exact"),
+            NumberMode::Decimal => write!(f, "decimal"),
+            NumberMode::Programmer => write!(f, "programmer"),
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Value {
+        Value::Float(x)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Value {
+        Value::Rational(BigRational::from_integer(n.into()))
+    }
+}
+
+impl From<BigInt> for Value {
+    fn from(n: BigInt) -> Value {
+        Value::Rational(BigRational::from_integer(n))
+    }
+}
+
+impl From<BigRational> for Value {
+    fn from(r: BigRational) -> Value {
+        Value::Rational(r)
+    }
+}
+
+impl From<Decimal> for Value {
+    fn from(d: Decimal) -> Value {
+        Value::Decimal(d)
+    }
+}
+
+impl From<Complex64> for Value {
+    fn from(z: Complex64) -> Value {
+        Value::Complex(z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathengine::core::{Calc, Calculator};
+    use crate::mathengine::error::ErrorKind;
+    use crate::mathengine::testing::{error, eval, ratio, shown};
+
+    fn exact() -> Calculator {
+        let mut calc = Calculator::new();
+        calc.set_number_mode(NumberMode::Exact);
+        calc
+    }
+
+    fn integer(digits: &str) -> Value {
+        Value::Rational(BigRational::from_integer(digits.parse().unwrap()))
+    }
+
+    #[test]
+    fn exact_arithmetic() {
+        let mut calc = exact();
+        assert_eq!(eval(&mut calc, "0.1 + 0.2"), ratio(3, 10));
+        assert_eq!(eval(&mut calc, "0.1 + 0.2 == 0.3"), Value::from(1));
+        assert_eq!(eval(&mut calc, "1/3 + 1/6"), ratio(1, 2));
+        assert_eq!(eval(&mut calc, "-7/3"), ratio(-7, 3));
+        assert_eq!(eval(&mut calc, "7 % (2/3)"), ratio(1, 3));
+        assert_eq!(eval(&mut calc, "2^-2"), ratio(1, 4));
+        assert_eq!(eval(&mut calc, "2^100"), integer("1267650600228229401496703205376"));
+        assert_eq!(eval(&mut calc, "fact(25)"), integer("15511210043330985984000000"));
+        assert!(matches!(error(&mut calc, "1/0"), ErrorKind::DivisionByZero));
+        assert!(matches!(error(&mut calc, "0^-1"), ErrorKind::DivisionByZero));
+    }
+
+    #[test]
+    fn exact_roots_and_rounding() {
+        let mut calc = exact();
+        assert_eq!(eval(&mut calc, "sqrt(4/9)"), ratio(2, 3));
+        assert_eq!(eval(&mut calc, "8^(1/3)"), Value::from(2));
+        assert_eq!(eval(&mut calc, "(-8)^(1/3)"), Value::from(-2));
+        assert_eq!(eval(&mut calc, "floor(-7/2)"), Value::from(-4));
+        assert_eq!(eval(&mut calc, "round(5/2)"), Value::from(3));
+        assert_eq!(eval(&mut calc, "nCr(50, 25)"), integer("126410606437752"));
+    }
+
+    #[test]
+    fn transcendental_functions_fall_back_to_floats() {
+        let mut calc = exact();
+        assert_eq!(eval(&mut calc, "sqrt(2)"), Value::Float(2f64.sqrt()));
+        assert_eq!(eval(&mut calc, "2^(1/2)"), Value::Float(2f64.sqrt()));
+        assert!(matches!(eval(&mut calc, "sin(1) + 1/3"), Value::Float(_)));
+        // so do results too large to work out quickly
+        assert!(matches!(eval(&mut calc, "3^(10^9)"), Value::Float(_)));
+        assert!(matches!(eval(&mut calc, "fact(30001)"), Value::Float(_)));
+    }
+
+    #[test]
+    fn fractions_or_decimals() {
+        let mut calc = exact();
+        assert_eq!(shown(&mut calc, "2/3"), "2/3");
+        calc.eval("display decimal 4").unwrap();
+        assert_eq!(calc.rational_display(), RationalDisplay::Decimal(4));
+        assert_eq!(shown(&mut calc, "2/3"), "0.6667");
+        assert!(matches!(error(&mut calc, "display decimal 100000000"), ErrorKind::CommandUsage(_)));
+        assert_eq!(calc.rational_display(), RationalDisplay::Decimal(4));
+        calc.set_rational_display(RationalDisplay::Fraction);
+        assert_eq!(shown(&mut calc, "-7/3"), "-7/3");
+    }
+
+    #[test]
+    fn the_mode_is_per_calculator() {
+        let mut calc = exact();
+        assert_eq!(eval(&mut calc, "1/4"), ratio(1, 4));
+        assert_eq!(eval(&mut Calculator::new(), "1/4"), Value::Float(0.25));
+        calc.eval("mode float").unwrap();
+        assert_eq!(eval(&mut calc, "1/4"), Value::Float(0.25));
+        // answers keep the type they were solved with
+        assert_eq!(calc.variable("ans2"), Some(ratio(1, 4)));
+    }
+
+    fn complex(re: f64, im: f64) -> Value {
+        Value::Complex(Complex64::new(re, im))
+    }
+
+    fn assert_near(actual: Value, re: f64, im: f64) {
+        let Value::Complex(z) = actual else { panic!("{actual:?} is not complex") };
+        assert!((z.re - re).abs() < 1e-12 && (z.im - im).abs() < 1e-12, "{z} is not {re} + {im}i");
+    }
+
+    #[test]
+    fn complex_arithmetic() {
+        let mut calc = Calculator::new();
+        assert_eq!(eval(&mut calc, "(3 + 4i) * (1 - 2i)"), complex(11.0, -2.0));
+        assert_eq!(eval(&mut calc, "1 / (1 + i)"), complex(0.5, -0.5));
+        assert_eq!(eval(&mut calc, "(1 + i)^2"), complex(0.0, 2.0));
+        assert_eq!(eval(&mut calc, "2j"), complex(0.0, 2.0));
+        // answers without an imaginary part are real again
+        assert_eq!(eval(&mut calc, "i^2"), Value::Float(-1.0));
+        assert_eq!(eval(&mut calc, "3 + 0i"), Value::Float(3.0));
+        assert!(matches!(error(&mut calc, "i & 1"), ErrorKind::OperatorComplexArg(_)));
+        // complex numbers are floats whatever the mode
+        calc.set_number_mode(NumberMode::Exact);
+        assert_eq!(eval(&mut calc, "1/2 + i"), complex(0.5, 1.0));
+    }
+
+    #[test]
+    fn functions_of_negative_and_complex_numbers() {
+        let mut calc = Calculator::new();
+        assert_eq!(eval(&mut calc, "sqrt(-4)"), complex(0.0, 2.0));
+        assert_near(eval(&mut calc, "ln(-1)"), 0.0, std::f64::consts::PI);
+        assert_near(eval(&mut calc, "(2i)^0.5"), 1.0, 1.0);
+        assert_near(eval(&mut calc, "sin(i)"), 0.0, 1f64.sinh());
+        assert_near(eval(&mut calc, "e^(i * pi) + 1"), 0.0, 0.0);
+        assert_eq!(eval(&mut calc, "abs(3 + 4i)"), Value::Float(5.0));
+        assert_eq!(eval(&mut calc, "re(3 + 4i) + im(3 + 4i)"), Value::Float(7.0));
+        assert_eq!(eval(&mut calc, "conj(1 + 2i)"), complex(1.0, -2.0));
+        assert_eq!(eval(&mut calc, "arg(-1)"), Value::Float(std::f64::consts::PI));
+    }
+
+    #[test]
+    fn complex_display() {
+        let mut calc = Calculator::new();
+        assert_eq!(shown(&mut calc, "1 - i - 1"), "-i");
+        assert_eq!(shown(&mut calc, "11 - 2i"), "11 - 2i");
+        calc.eval("display polar").unwrap();
+        assert_eq!(calc.complex_display(), ComplexDisplay::Polar);
+        assert_eq!(shown(&mut calc, "3 + 4i"), "5 ∠ 0.927295218001612");
+        calc.eval("deg").unwrap();
+        assert_eq!(shown(&mut calc, "1 + i"), "1.4142135623731 ∠ 45");
+        assert_eq!(shown(&mut calc, "2"), "2");
+        calc.set_complex_display(ComplexDisplay::Rectangular);
+        assert_eq!(shown(&mut calc, "1 + i"), "1 + i");
+    }
+}
@@ -0,0 +1,323 @@
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{One, ToPrimitive, Zero};
+
+/// The fixed size integers that programmer mode works with, eg. `i32` or `u8`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WordType {
+    bits: u32,
+    signed: bool,
+}
+
+/// What happens when a result does not fit in the word type.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+    /// Only the low bits are kept, like two's complement hardware does.
+    Wrap,
+    /// The expression fails with [`ErrorKind::Overflow`](crate::ErrorKind::Overflow).
+    Error,
+}
+
+/// The base integers are shown in, in programmer mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Radix {
+    Decimal,
+    /// eg. `0xdead_beef`
+    Hex,
+    /// eg. `0b1010_0101`
+    Binary,
+    /// eg. `0o755`
+    Octal,
+}
+
+const WORD_SIZES: [u32; 5] = [8, 16, 32, 64, 128];
+
+impl WordType {
+    /// A word of 8, 16, 32, 64 or 128 bits, None for any other size.
+    pub fn new(bits: u32, signed: bool) -> Option<WordType> {
+        WORD_SIZES.contains(&bits).then_some(WordType { bits, signed })
+    }
+
+    // the rust names, eg. i32 or u8
+    pub fn from_name(name: &str) -> Option<WordType> {
+        let signed = match name.as_bytes().first()? {
+            b'i' => true,
+            b'u' => false,
+            _ => return None,
+        };
+        WordType::new(name[1..].parse().ok()?, signed)
+    }
+
+    pub fn bits(self) -> u32 {
+        self.bits
+    }
+
+    pub fn is_signed(self) -> bool {
+        self.signed
+    }
+
+    // 2^bits, the number of values the word can hold
+    fn modulus(self) -> BigInt {
+        BigInt::one() << self.bits
+    }
+
+    pub fn min(self) -> BigInt {
+        if self.signed {
+            -(BigInt::one() << (self.bits - 1))
+        } else {
+            BigInt::zero()
+        }
+    }
+
+    pub fn max(self) -> BigInt {
+        if self.signed {
+            (BigInt::one() << (self.bits - 1)) - 1
+        } else {
+            self.modulus() - 1
+        }
+    }
+
+    pub fn contains(self, n: &BigInt) -> bool {
+        *n >= self.min() && *n <= self.max()
+    }
+
+    // keeps the low bits of n, so the result is always in range
+    pub fn wrap(self, n: &BigInt) -> BigInt {
+        let low_bits = n.mod_floor(&self.modulus());
+        if self.signed && low_bits > self.max() {
+            low_bits - self.modulus()
+        } else {
+            low_bits
+        }
+    }
+
+    // the bits of n as an unsigned number, so -1 in an i8 is 255
+    pub fn to_bits(self, n: &BigInt) -> BigInt {
+        n.mod_floor(&self.modulus())
+    }
+
+    pub fn count_ones(self, n: &BigInt) -> u64 {
+        self.to_bits(n).magnitude().count_ones()
+    }
+
+    pub fn leading_zeros(self, n: &BigInt) -> u64 {
+        self.bits as u64 - self.to_bits(n).bits()
+    }
+
+    pub fn trailing_zeros(self, n: &BigInt) -> u64 {
+        self.to_bits(n).trailing_zeros().unwrap_or(self.bits as u64)
+    }
+
+    // a negative shift rotates the other way
+    pub fn rotate_left(self, n: &BigInt, shift: &BigInt) -> BigInt {
+        let shift = shift.mod_floor(&BigInt::from(self.bits)).to_u32().unwrap_or(0);
+        let bits = self.to_bits(n);
+        let rotated = (&bits << shift) | (&bits >> (self.bits - shift));
+        self.wrap(&rotated)
+    }
+
+    // the bits of n in the radix, with an underscore between every group of digits
+    // negative numbers are shown as their two's complement bits, so -1 in an i8 is 0xff
+    pub fn format(self, n: &BigInt, radix: Radix) -> String {
+        let (base, prefix, group) = match radix {
+            Radix::Decimal => return n.to_string(),
+            Radix::Hex => (16, "0x", 4),
+            Radix::Binary => (2, "0b", 4),
+            Radix::Octal => (8, "0o", 3),
+        };
+        let digits = self.to_bits(n).to_str_radix(base);
+        // pad to a whole number of groups but never wider than the word, so 5 is 0b0101 and 44 in
+        // a u8 is 0x2c
+        let width = (self.bits as usize).div_ceil(base.trailing_zeros() as usize);
+        let padded = digits.len().div_ceil(group) * group;
+        let digits = format!("{}{digits}", "0".repeat(padded.min(width).saturating_sub(digits.len())));
+        // the groups are counted from the lowest digit
+        let first = match digits.len() % group {
+            0 => group,
+            n => n,
+        };
+        let mut grouped = digits[..first].to_string();
+        for chunk in digits.as_bytes()[first..].chunks(group) {
+            grouped.push('_');
+            grouped.push_str(std::str::from_utf8(chunk).unwrap());
+        }
+        format!("{prefix}{grouped}")
+    }
+}
+
+impl Radix {
+    pub fn from_name(name: &str) -> Option<Radix> {
+        match name {
+            "dec" => Some(Radix::Decimal),
+            "hex" => Some(Radix::Hex),
+            "bin" => Some(Radix::Binary),
+            "oct" => Some(Radix::Octal),
+            _ => None,
+        }
+    }
+
+    // the inverse of from_name
+    pub fn name(self) -> &'static str {
+        match self {
+            Radix::Decimal => "dec",
+            Radix::Hex => "hex",
+            Radix::Binary => "bin",
+            Radix::Octal => "oct",
+        }
+    }
+}
+
+impl fmt::Display for WordType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", if self.signed { "i" } else { "u" }, self.bits)
+    }
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Overflow::Wrap => write!(f, "wrap"),
+            Overflow::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl fmt::Display for Radix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Radix::Decimal => write!(f, "decimal"),
+            Radix::Hex => write!(f, "hex"),
+            Radix::Binary => write!(f, "binary"),
+            Radix::Octal => write!(f, "octal"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathengine::core::{Calc, Calculator};
+    use crate::mathengine::error::ErrorKind;
+    use crate::mathengine::testing::{error, eval, shown};
+    use crate::mathengine::value::{NumberMode, Value};
+
+    fn word(name: &str) -> WordType {
+        WordType::from_name(name).unwrap()
+    }
+
+    fn int(n: i128) -> BigInt {
+        BigInt::from(n)
+    }
+
+    fn programmer(word_type: &str, overflow: Overflow) -> Calculator {
+        let mut calc = Calculator::new();
+        calc.set_number_mode(NumberMode::Programmer);
+        calc.set_word_type(word(word_type));
+        calc.set_overflow(overflow);
+        calc
+    }
+
+    #[test]
+    fn word_types() {
+        assert_eq!(word("u8").to_string(), "u8");
+        assert_eq!((word("i128").bits(), word("i128").is_signed()), (128, true));
+        for name in ["u7", "i256", "x8", "u", ""] {
+            assert!(WordType::from_name(name).is_none(), "{name}");
+        }
+        assert_eq!((word("i8").min(), word("i8").max()), (int(-128), int(127)));
+        assert_eq!((word("u16").min(), word("u16").max()), (int(0), int(65535)));
+        assert_eq!(word("u128").max(), BigInt::from(u128::MAX));
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(word("u8").wrap(&int(256)), int(0));
+        assert_eq!(word("u8").wrap(&int(-1)), int(255));
+        assert_eq!(word("i8").wrap(&int(128)), int(-128));
+        assert_eq!(word("i8").wrap(&int(-129)), int(127));
+        assert_eq!(word("i8").to_bits(&int(-1)), int(255));
+    }
+
+    #[test]
+    fn bit_counts_and_rotations() {
+        let u8 = word("u8");
+        assert_eq!(u8.count_ones(&int(0xf0)), 4);
+        assert_eq!(word("i8").count_ones(&int(-1)), 8);
+        assert_eq!(u8.leading_zeros(&int(1)), 7);
+        assert_eq!(u8.trailing_zeros(&int(8)), 3);
+        assert_eq!(u8.trailing_zeros(&int(0)), 8);
+        assert_eq!(u8.rotate_left(&int(0b1000_0001), &int(1)), int(0b0000_0011));
+        assert_eq!(u8.rotate_left(&int(1), &int(-1)), int(0b1000_0000));
+        assert_eq!(u8.rotate_left(&int(1), &int(9)), int(2));
+    }
+
+    #[test]
+    fn formatting_in_a_radix() {
+        assert_eq!(word("u8").format(&int(44), Radix::Hex), "0x2c");
+        assert_eq!(word("i8").format(&int(-1), Radix::Hex), "0xff");
+        assert_eq!(word("u32").format(&int(0xdeadbeef), Radix::Hex), "0xdead_beef");
+        assert_eq!(word("u8").format(&int(5), Radix::Binary), "0b0101");
+        assert_eq!(word("u16").format(&int(0o755), Radix::Octal), "0o755");
+        assert_eq!(word("i64").format(&int(-5), Radix::Decimal), "-5");
+        for radix in [Radix::Decimal, Radix::Hex, Radix::Binary, Radix::Octal] {
+            assert_eq!(Radix::from_name(radix.name()), Some(radix));
+        }
+    }
+
+    #[test]
+    fn results_wrap_or_overflow() {
+        let mut calc = programmer("u8", Overflow::Wrap);
+        assert_eq!(eval(&mut calc, "255 + 1"), Value::from(0));
+        assert_eq!(eval(&mut calc, "~0"), Value::from(255));
+        calc.set_overflow(Overflow::Error);
+        assert!(matches!(error(&mut calc, "255 + 1"), ErrorKind::Overflow(_)));
+        assert!(matches!(error(&mut calc, "0 - 1"), ErrorKind::Overflow(_)));
+        let mut calc = programmer("i8", Overflow::Error);
+        assert_eq!(eval(&mut calc, "-128"), Value::from(-128));
+        assert_eq!(eval(&mut calc, "2 * -64"), Value::from(-128));
+        assert!(matches!(error(&mut calc, "-128 - 1"), ErrorKind::Overflow(_)));
+        assert!(matches!(error(&mut calc, "-2^7"), ErrorKind::Overflow(_)));
+    }
+
+    #[test]
+    fn integers_stay_exact() {
+        let mut calc = programmer("i64", Overflow::Wrap);
+        assert_eq!(eval(&mut calc, "2^53 + 1"), Value::from(9007199254740993));
+        assert_eq!(eval(&mut calc, "2^63"), Value::from(i64::MIN));
+        assert_eq!(eval(&mut calc, "7 / 2"), Value::from(3));
+        assert!(matches!(error(&mut calc, "1.5"), ErrorKind::NotAnInteger(_)));
+        assert!(matches!(error(&mut calc, "1.5 | 1"), ErrorKind::NotAnInteger(_)));
+        // shifts past the word width give zero rather than panicking
+        assert_eq!(eval(&mut calc, "1 << 64"), Value::from(0));
+        assert_eq!(eval(&mut calc, "1 << 200"), Value::from(0));
+        assert_eq!(eval(&mut calc, "-1 >> 200"), Value::from(-1));
+    }
+
+    #[test]
+    fn bit_functions_and_display() {
+        let mut calc = programmer("u64", Overflow::Wrap);
+        assert_eq!(eval(&mut calc, "popcount(255)"), Value::from(8));
+        assert_eq!(eval(&mut calc, "clz(1)"), Value::from(63));
+        assert_eq!(eval(&mut calc, "ctz(8)"), Value::from(3));
+        assert_eq!(eval(&mut calc, "rotr(1, 1)"), eval(&mut calc, "rotl(1, 63)"));
+        calc.eval("display hex").unwrap();
+        assert_eq!(calc.radix(), Radix::Hex);
+        assert_eq!(shown(&mut calc, "~0"), "0xffff_ffff_ffff_ffff");
+        calc.eval("word u8").unwrap();
+        assert_eq!(shown(&mut calc, "-1"), "0xff");
+        calc.eval("display bin").unwrap();
+        assert_eq!(shown(&mut calc, "5"), "0b0101");
+    }
+
+    #[test]
+    fn only_programmer_mode_shows_integers_in_a_radix() {
+        let mut calc = Calculator::new();
+        calc.eval("display hex").unwrap();
+        assert_eq!(shown(&mut calc, "255"), "255");
+        // a huge decimal is shown without working out every digit of it
+        calc.eval("precision 10").unwrap();
+        assert_eq!(shown(&mut calc, "10^999999"), "1e+999999");
+    }
+}